@@ -0,0 +1,155 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Username and password for a calendar server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+// A `WWW-Authenticate` challenge: the scheme and its parameters
+#[derive(Debug, PartialEq)]
+pub struct Challenge {
+    pub scheme: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    // `Digest realm="radicale", nonce="abc", qop="auth"`
+    pub fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        let (scheme, mut rest) = header.split_once(' ').unwrap_or((header, ""));
+        if scheme.is_empty() {
+            return None;
+        }
+        let mut params = Vec::new();
+        while let Some((key, after)) = rest.split_once('=') {
+            let key = key
+                .trim()
+                .trim_start_matches(',')
+                .trim()
+                .to_ascii_lowercase();
+            let after = after.trim_start();
+            let (value, remainder) = match after.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"').unwrap_or(quoted.len());
+                    (
+                        quoted[..end].to_owned(),
+                        quoted.get(end + 1..).unwrap_or(""),
+                    )
+                }
+                None => {
+                    let end = after.find(',').unwrap_or(after.len());
+                    (after[..end].trim().to_owned(), &after[end..])
+                }
+            };
+            params.push((key, value));
+            rest = remainder;
+        }
+        Some(Self {
+            scheme: scheme.to_ascii_lowercase(),
+            params,
+        })
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub fn basic_authorization(credentials: &Credentials) -> String {
+    let pair = format!("{}:{}", credentials.username, credentials.password);
+    format!("Basic {}", STANDARD.encode(pair))
+}
+
+fn hex_digest<D: Digest>(data: &str) -> String {
+    D::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// The response to a digest challenge (RFC 7616) for a request of `uri`. Only the
+// MD5 and SHA-256 algorithms and the `auth` quality of protection are supported
+pub fn digest_authorization(
+    challenge: &Challenge,
+    credentials: &Credentials,
+    method: &str,
+    uri: &str,
+    cnonce: &str,
+) -> Option<String> {
+    let realm = challenge.param("realm").unwrap_or_default();
+    let nonce = challenge.param("nonce")?;
+    let algorithm = challenge.param("algorithm").unwrap_or("MD5");
+    let hash: fn(&str) -> String = match algorithm.to_ascii_uppercase().as_str() {
+        "MD5" => hex_digest::<Md5>,
+        "SHA-256" => hex_digest::<Sha256>,
+        _ => return None,
+    };
+    let qop = challenge
+        .param("qop")
+        .map(|qop| qop.split(',').any(|q| q.trim() == "auth"));
+    if qop == Some(false) {
+        return None;
+    }
+
+    let ha1 = hash(&format!(
+        "{}:{}:{}",
+        credentials.username, realm, credentials.password
+    ));
+    let ha2 = hash(&format!("{}:{}", method, uri));
+    let nc = "00000001";
+    let mut header = format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}",
+        credentials.username, realm, nonce, uri, algorithm
+    );
+    let response = if qop.is_some() {
+        header += &format!(", qop=auth, nc={}, cnonce=\"{}\"", nc, cnonce);
+        hash(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2))
+    } else {
+        hash(&format!("{}:{}:{}", ha1, nonce, ha2))
+    };
+    header += &format!(", response=\"{}\"", response);
+    if let Some(opaque) = challenge.param("opaque") {
+        header += &format!(", opaque=\"{}\"", opaque);
+    }
+    Some(header)
+}
+
+// The client nonce only has to be unpredictable to the server
+pub fn cnonce() -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    hex_digest::<Sha256>(&format!("{}:{}", time, std::process::id()))[..16].to_owned()
+}
+
+// The `Authorization` header answering the strongest of the offered challenges
+pub fn authorization<'a>(
+    challenges: impl IntoIterator<Item = &'a str>,
+    credentials: &Credentials,
+    method: &str,
+    uri: &str,
+) -> Option<String> {
+    let challenges: Vec<_> = challenges
+        .into_iter()
+        .filter_map(Challenge::parse)
+        .collect();
+    let digest = challenges
+        .iter()
+        .filter(|c| c.scheme == "digest")
+        .find_map(|c| digest_authorization(c, credentials, method, uri, &cnonce()));
+    digest.or_else(|| {
+        challenges
+            .iter()
+            .any(|c| c.scheme == "basic")
+            .then(|| basic_authorization(credentials))
+    })
+}
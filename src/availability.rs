@@ -0,0 +1,119 @@
+use crate::calendar::{AgendaEntry, DisplayOptions};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use itertools::Itertools;
+use std::str::FromStr;
+
+// Gaps shorter than this aren't worth offering
+const MIN_FREE_MINUTES: i64 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        Self {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        }
+    }
+}
+
+impl FromStr for WorkingHours {
+    type Err = String;
+
+    // `09:00-17:00`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid working hours: {}", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+        let hours = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if hours.start >= hours.end {
+            return Err(invalid());
+        }
+        Ok(hours)
+    }
+}
+
+// Round up to the next quarter hour, nobody is free at 10:07
+fn round_up(time: NaiveDateTime) -> NaiveDateTime {
+    let past = time.minute() % 15;
+    if past == 0 && time.second() == 0 {
+        return time;
+    }
+    let truncated = time.with_second(0).unwrap_or(time) - Duration::minutes(past as i64);
+    truncated + Duration::minutes(15)
+}
+
+// Free blocks within the working hours of `day`, not before `now`. All-day events
+// and todos don't count as busy
+pub fn free_blocks(
+    entries: &[AgendaEntry],
+    day: NaiveDate,
+    hours: WorkingHours,
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let day_end = day.and_time(hours.end);
+    let mut cursor = day.and_time(hours.start).max(round_up(now));
+    let mut blocks = Vec::new();
+
+    let busy = entries
+        .iter()
+        .filter(|e| e.due.is_none() && !e.all_day && e.duration > Duration::zero())
+        .map(|e| (e.start, e.start + e.duration))
+        .sorted();
+
+    for (start, end) in busy.chain(std::iter::once((day_end, day_end))) {
+        let start = start.min(day_end);
+        if start - cursor >= Duration::minutes(MIN_FREE_MINUTES) {
+            blocks.push((cursor, start));
+        }
+        cursor = cursor.max(end);
+    }
+    blocks
+}
+
+// "free 10:00–11:30, 14:00–16:00 CET"
+pub fn format_availability(
+    blocks: &[(NaiveDateTime, NaiveDateTime)],
+    zone: &str,
+    opts: &DisplayOptions,
+) -> String {
+    if blocks.is_empty() {
+        return "no free time".to_owned();
+    }
+    let ranges = blocks
+        .iter()
+        .map(|(start, end)| {
+            format!(
+                "{}{}{}",
+                opts.clock.format(start.time()),
+                opts.glyphs().dash,
+                opts.clock.format(end.time())
+            )
+        })
+        .join(", ");
+    format!("free {} {}", ranges, zone)
+}
+
+// Abbreviation of the local zone at `time` (CET), or its UTC offset if unknown
+pub fn local_zone_name(time: NaiveDateTime) -> String {
+    let zone = iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|name| name.parse::<Tz>().ok())
+        .and_then(|tz| tz.from_local_datetime(&time).earliest())
+        .map(|dt| dt.format("%Z").to_string());
+    zone.unwrap_or_else(|| {
+        Local
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|dt| dt.format("UTC%:z").to_string())
+            .unwrap_or_default()
+    })
+}
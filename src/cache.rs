@@ -0,0 +1,184 @@
+use crate::calendar::EventRecord;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// $XDG_CACHE_HOME/polybar-agenda, falling back to ~/.cache/polybar-agenda
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("polybar-agenda"))
+}
+
+// Postcard can't tell an old layout from a new one, so this is bumped whenever the
+// snapshot or EventRecord changes, and older snapshots are never read
+const SNAPSHOT_VERSION: u32 = 2;
+
+// When a file was last modified and its size, which tell that it hasn't changed
+// without reading it
+pub type Stamp = (SystemTime, u64);
+
+pub fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    path: PathBuf,
+    settings: String,
+    // Of the file when it was read, None for calendars that aren't files
+    stamp: Option<Stamp>,
+    digest: [u8; 32],
+    events: Vec<EventRecord>,
+}
+
+// Binary snapshots of the events parsed from each calendar file, so unchanged files
+// don't have to be parsed again on every refresh. A file whose modification time and
+// size are the same isn't even read, and one that was touched is only parsed again if
+// the hash of its contents changed
+#[derive(Clone)]
+pub struct ParseCache {
+    dir: PathBuf,
+}
+
+impl ParseCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        cache_dir().map(|dir| Self::new(dir.join("parsed")))
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        entry_path(&self.dir, (SNAPSHOT_VERSION, path))
+    }
+
+    fn snapshot(&self, path: &Path) -> Option<Snapshot> {
+        let snapshot = fs::read(self.entry_path(path)).ok()?;
+        let cached: Snapshot = postcard::from_bytes(&snapshot).ok()?;
+        (cached.path == path).then_some(cached)
+    }
+
+    // Downloaded calendars are keyed by their URL, which isn't a file to resolve
+    fn key(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+    }
+
+    // The events parsed from `path` when it had the same `stamp`, without reading it.
+    // `settings` covers any options the parsed events depend on
+    pub fn load_unchanged(
+        &self,
+        path: &Path,
+        stamp: Stamp,
+        settings: &str,
+    ) -> Option<Vec<EventRecord>> {
+        let cached = self.snapshot(&Self::key(path))?;
+        (cached.stamp == Some(stamp) && cached.settings == settings).then_some(cached.events)
+    }
+
+    // The events parsed from `contents` when `path` was last read, if it hasn't changed since
+    pub fn load(&self, path: &Path, contents: &str, settings: &str) -> Option<Vec<EventRecord>> {
+        let cached = self.snapshot(&Self::key(path))?;
+        (cached.digest == digest(contents, settings)).then_some(cached.events)
+    }
+
+    // The cache is only an optimization, so failing to write it is not an error. The
+    // stamp is the one taken before reading `contents`, so that a file written to in
+    // between is read again
+    pub fn store(
+        &self,
+        path: &Path,
+        stamp: Option<Stamp>,
+        contents: &str,
+        settings: &str,
+        events: &[EventRecord],
+    ) {
+        let snapshot = Snapshot {
+            path: Self::key(path),
+            settings: settings.to_owned(),
+            stamp,
+            digest: digest(contents, settings),
+            events: events.to_vec(),
+        };
+        if let Ok(bytes) = postcard::to_stdvec(&snapshot) {
+            let _ = fs::create_dir_all(&self.dir);
+            let _ = fs::write(self.entry_path(&snapshot.path), bytes);
+        }
+    }
+}
+
+// A file in `dir` for state kept between the runs of one module, e.g. the ongoing
+// events. Modules with other sources or options each get their own
+pub fn state_path(dir: &Path, name: &str, module: impl Hash) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    module.hash(&mut hasher);
+    dir.join(format!("{}-{:016x}", name, hasher.finish()))
+}
+
+fn entry_path(dir: &Path, key: impl Hash) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    dir.join(format!("{:016x}.bin", hasher.finish()))
+}
+
+// The last response to a download, with the validators needed to ask the server
+// whether it has changed since. Also what's shown while the server can't be reached
+#[derive(Default, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: Vec<u8>,
+    // When the server last confirmed the body, in seconds since the epoch
+    pub fetched_at: u64,
+}
+
+// Bodies of remote calendars, so unchanged feeds aren't downloaded again on every refresh
+// and the agenda still works offline
+#[derive(Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        cache_dir().map(|dir| Self::new(dir.join("http")))
+    }
+
+    pub fn load(&self, url: &str) -> Option<CachedResponse> {
+        let bytes = fs::read(entry_path(&self.dir, url)).ok()?;
+        let cached: CachedResponse = postcard::from_bytes(&bytes).ok()?;
+        (cached.url == url).then_some(cached)
+    }
+
+    // Like the parse cache, this is best effort
+    pub fn store(&self, response: &CachedResponse) {
+        if let Ok(bytes) = postcard::to_stdvec(response) {
+            let _ = fs::create_dir_all(&self.dir);
+            let _ = fs::write(entry_path(&self.dir, &response.url), bytes);
+        }
+    }
+}
+
+fn digest(contents: &str, settings: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(SNAPSHOT_VERSION.to_le_bytes());
+    hasher.update(settings.as_bytes());
+    hasher.update([0]);
+    hasher.update(contents.as_bytes());
+    hasher.finalize().into()
+}
@@ -0,0 +1,314 @@
+use crate::calendar::EventRecord;
+use crate::remote;
+use crate::sources::{parse_calendar, Collection, Document, LoadOptions, SourceError};
+use chrono::{DateTime, Local, Utc};
+use itertools::Itertools;
+use quick_xml::{events::Event, Reader};
+use std::path::Path;
+
+// `caldav:https://cloud.example.com/remote.php/dav/calendars/me/personal/`
+pub const SCHEME: &str = "caldav:";
+
+fn utc(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+// A calendar-query (RFC 4791, section 7.8) for the events overlapping the window,
+// or all of them without one
+pub fn calendar_query(window: Option<(DateTime<Local>, DateTime<Local>)>) -> String {
+    let time_range = window
+        .map(|(start, end)| {
+            format!(
+                "<c:time-range start=\"{}\" end=\"{}\"/>",
+                utc(start),
+                utc(end)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <c:calendar-query xmlns:d=\"DAV:\" xmlns:c=\"urn:ietf:params:xml:ns:caldav\">\
+         <d:prop><c:calendar-data/></d:prop>\
+         <c:filter><c:comp-filter name=\"VCALENDAR\">\
+         <c:comp-filter name=\"VEVENT\">{}</c:comp-filter>\
+         </c:comp-filter></c:filter>\
+         </c:calendar-query>",
+        time_range
+    )
+}
+
+// A WebDAV property: its text, the URLs in it and the names of the elements in it,
+// which covers the handful of properties needed here
+#[derive(Debug, Default)]
+pub struct Property {
+    pub text: String,
+    pub hrefs: Vec<String>,
+    pub children: Vec<String>,
+}
+
+// The properties of a single resource in a multistatus response
+#[derive(Debug, Default)]
+pub struct Resource {
+    pub href: String,
+    pub properties: Vec<(String, Property)>,
+}
+
+impl Resource {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, property)| property)
+    }
+}
+
+fn local_name(name: quick_xml::name::LocalName) -> String {
+    String::from_utf8_lossy(name.as_ref()).into_owned()
+}
+
+// Parse a multistatus response (RFC 4918, section 13), namespaces are ignored
+pub fn multistatus(xml: &str) -> Result<Vec<Resource>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut resources: Vec<Resource> = Vec::new();
+    // Names of the open elements
+    let mut stack: Vec<String> = Vec::new();
+
+    // Depth of the element holding a property's value, below prop
+    let property_depth = |stack: &[String]| {
+        stack
+            .iter()
+            .rposition(|name| name == "prop")
+            .map(|i| stack.len() - i - 1)
+    };
+    loop {
+        let event = reader.read_event().map_err(|e| e.to_string())?;
+        let (name, empty) = match &event {
+            Event::Start(e) => (local_name(e.local_name()), false),
+            Event::Empty(e) => (local_name(e.local_name()), true),
+            Event::End(_) => {
+                stack.pop();
+                continue;
+            }
+            Event::Text(_) | Event::CData(_) => {
+                let text = match &event {
+                    Event::Text(text) => text.unescape().map_err(|e| e.to_string())?.into_owned(),
+                    Event::CData(data) => String::from_utf8_lossy(data).into_owned(),
+                    _ => unreachable!(),
+                };
+                let Some(resource) = resources.last_mut() else {
+                    continue;
+                };
+                let in_href = stack.last().is_some_and(|n| n == "href");
+                match (property_depth(&stack), resource.properties.last_mut()) {
+                    (Some(1), Some((_, property))) => property.text.push_str(&text),
+                    (Some(_), Some((_, property))) if in_href => {
+                        property.hrefs.push(text.trim().to_owned())
+                    }
+                    (None, _) if in_href => resource.href.push_str(text.trim()),
+                    _ => {}
+                }
+                continue;
+            }
+            Event::Eof => return Ok(resources),
+            _ => continue,
+        };
+
+        if name == "response" {
+            resources.push(Resource::default());
+        }
+        if let Some(resource) = resources.last_mut() {
+            match property_depth(&stack) {
+                Some(0) => resource
+                    .properties
+                    .push((name.clone(), Property::default())),
+                Some(1) => {
+                    if let Some((_, property)) = resource.properties.last_mut() {
+                        property.children.push(name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !empty {
+            stack.push(name);
+        }
+    }
+}
+
+// The ics documents in the calendar-data properties of a multistatus response
+pub fn calendar_data(xml: &str) -> Result<Vec<String>, String> {
+    Ok(multistatus(xml)?
+        .into_iter()
+        .filter_map(|resource| {
+            let data = resource.property("calendar-data")?;
+            (!data.text.trim().is_empty()).then(|| data.text.clone())
+        })
+        .collect())
+}
+
+fn propfind(
+    url: &str,
+    depth: &str,
+    properties: &str,
+    opts: &LoadOptions,
+) -> Result<Vec<Resource>, SourceError> {
+    let headers = [
+        ("Depth", depth),
+        ("Content-Type", "application/xml; charset=utf-8"),
+    ];
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <d:propfind xmlns:d=\"DAV:\" xmlns:c=\"urn:ietf:params:xml:ns:caldav\" \
+         xmlns:a=\"http://apple.com/ns/ical/\"><d:prop>{}</d:prop></d:propfind>",
+        properties
+    );
+    let mut response = remote::send("PROPFIND", url, &headers, Some(&body), opts)?;
+    let body = remote::read_body(url, &mut response)?;
+    multistatus(&String::from_utf8_lossy(&body)).map_err(|e| SourceError::Http(url.to_owned(), e))
+}
+
+// The first URL in a property of the resource at `url`
+fn find_href(
+    url: &str,
+    property: &str,
+    request: &str,
+    opts: &LoadOptions,
+) -> Result<Option<String>, SourceError> {
+    let resources = propfind(url, "0", request, opts)?;
+    Ok(resources
+        .iter()
+        .find_map(|r| r.property(property)?.hrefs.first())
+        .map(|href| remote::resolve_url(url, href)))
+}
+
+// A calendar found on a server
+#[derive(Debug, PartialEq)]
+pub struct CalendarInfo {
+    pub url: String,
+    pub name: Option<String>,
+    pub color: Option<String>,
+}
+
+// The calendars among the resources of a calendar home
+pub fn calendars(home: &str, resources: &[Resource]) -> Vec<CalendarInfo> {
+    let text = |resource: &Resource, name| {
+        resource
+            .property(name)
+            .map(|p| p.text.trim().to_owned())
+            .filter(|text| !text.is_empty())
+    };
+    resources
+        .iter()
+        .filter(|resource| {
+            resource
+                .property("resourcetype")
+                .is_some_and(|p| p.children.iter().any(|c| c == "calendar"))
+        })
+        .map(|resource| CalendarInfo {
+            url: remote::resolve_url(home, &resource.href),
+            name: text(resource, "displayname"),
+            color: text(resource, "calendar-color"),
+        })
+        .collect()
+}
+
+// Find the calendars of the signed in user (RFC 6764 and RFC 4791, section 6): the
+// principal is looked up through /.well-known/caldav, then its calendar home is listed
+pub fn discover(url: &str, opts: &LoadOptions) -> Result<Vec<CalendarInfo>, SourceError> {
+    let principal_request = "<d:current-user-principal/>";
+    let well_known = remote::resolve_url(url, "/.well-known/caldav");
+    let principal = match find_href(
+        &well_known,
+        "current-user-principal",
+        principal_request,
+        opts,
+    ) {
+        Ok(Some(principal)) => principal,
+        _ => find_href(url, "current-user-principal", principal_request, opts)?
+            .unwrap_or_else(|| url.to_owned()),
+    };
+    let home = find_href(
+        &principal,
+        "calendar-home-set",
+        "<c:calendar-home-set/>",
+        opts,
+    )?
+    .ok_or_else(|| SourceError::Http(url.to_owned(), "no calendar home found".to_owned()))?;
+    let resources = propfind(
+        &home,
+        "1",
+        "<d:resourcetype/><d:displayname/><a:calendar-color/>",
+        opts,
+    )?;
+    Ok(calendars(&home, &resources))
+}
+
+// The events of a CalDAV collection within the agenda window. The server does the
+// filtering, so only the events that matter are transferred
+fn load_calendar(url: &str, opts: &LoadOptions) -> Result<Vec<EventRecord>, SourceError> {
+    let headers = [
+        ("Depth", "1"),
+        ("Content-Type", "application/xml; charset=utf-8"),
+    ];
+    let query = calendar_query(opts.window);
+    let mut response = remote::send("REPORT", url, &headers, Some(&query), opts)?;
+    let body = remote::read_body(url, &mut response)?;
+    let xml = String::from_utf8_lossy(&body);
+    let documents = calendar_data(&xml).map_err(|e| SourceError::Http(url.to_owned(), e))?;
+
+    let mut events = Vec::new();
+    for document in documents {
+        events.extend(parse_calendar(&document, Path::new(url), opts)?);
+    }
+    Ok(events)
+}
+
+fn is_server_root(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.trim_end_matches('/').find('/').is_none()
+}
+
+// A collection URL is queried directly. Given just the server, or the names of the
+// calendars to show, the calendars are discovered first
+pub fn load(url: &str, opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+    if !is_server_root(url) && opts.calendars.is_empty() {
+        return Ok(vec![Collection {
+            name: None,
+            color: None,
+            documents: vec![Document::Ics(load_calendar(url, opts)?)],
+            stale: None,
+            label: None,
+            icon: None,
+        }]);
+    }
+
+    let found = discover(url, opts)?;
+    let selected: Vec<_> = found
+        .iter()
+        .filter(|c| opts.wants_calendar(c.name.as_deref()))
+        .collect();
+    if selected.is_empty() && !opts.calendars.is_empty() {
+        let names = found.iter().filter_map(|c| c.name.as_deref()).join(", ");
+        let message = format!(
+            "no calendar named {}, found {}",
+            opts.calendars.join(" or "),
+            names
+        );
+        return Err(SourceError::Http(url.to_owned(), message));
+    }
+    selected
+        .into_iter()
+        .map(|calendar| {
+            Ok(Collection {
+                name: calendar.name.clone(),
+                color: calendar.color.clone(),
+                documents: vec![Document::Ics(load_calendar(&calendar.url, opts)?)],
+                stale: None,
+                label: None,
+                icon: None,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,512 @@
+use crate::calendar::{
+    capped_hours, capped_minutes, Clock, DisplayOptions, EmptyOutput, Icons, LineTags, WindowMode,
+};
+use crate::sources::{LoadOptions, EXEC};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration as StdDuration,
+};
+
+// `$XDG_CONFIG_HOME`, usually `~/.config`
+pub fn config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+// Settings taken from the environment, e.g. exported in the session before polybar starts.
+// They override the configuration file, and flags override them in turn
+#[derive(Debug, Default)]
+pub struct Environment {
+    // POLYBAR_AGENDA_CALENDAR, a path or URL read when no sources are given
+    pub calendar: Option<String>,
+    // POLYBAR_AGENDA_MODE, the display mode
+    pub mode: Option<String>,
+}
+
+impl Environment {
+    pub fn from_env() -> Self {
+        let var = |name| env::var(name).ok().filter(|value| !value.is_empty());
+        Self {
+            calendar: var("POLYBAR_AGENDA_CALENDAR"),
+            mode: var("POLYBAR_AGENDA_MODE"),
+        }
+    }
+
+    pub fn apply(&self, opts: &mut DisplayOptions) -> Result<(), String> {
+        if let Some(mode) = &self.mode {
+            opts.mode = parse("POLYBAR_AGENDA_MODE", mode)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("polybar-agenda").join("config.toml"))
+}
+
+// The settings of `config.toml`. Keys are named like the command line flags, which
+// take precedence over them
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub display: Option<String>,
+    pub show_calendar: Option<bool>,
+    pub show_location: Option<bool>,
+    pub show_description: Option<bool>,
+    pub description_width: Option<usize>,
+    pub humanize: Option<bool>,
+    pub units: Option<String>,
+    pub durations: Option<String>,
+    pub rounding: Option<String>,
+    pub lang: Option<String>,
+    #[serde(rename = "12h")]
+    pub twelve_hour: Option<bool>,
+    pub day_label: Option<String>,
+    pub show_end: Option<bool>,
+    // Minutes before an entry to start counting down to the second
+    pub countdown: Option<u32>,
+    pub compact_day_label: Option<String>,
+    // Days to look ahead, the rolling window if unset
+    pub days: Option<i64>,
+    // Only what's left of today, like `days = 0`
+    pub today: Option<bool>,
+    // Reach of the rolling window, in place of the days
+    pub hours_behind: Option<u32>,
+    pub hours_ahead: Option<u32>,
+    pub separator: Option<String>,
+    pub ascii: Option<bool>,
+    // Width of the bar shown for ongoing events in compact mode
+    pub progress_bar: Option<usize>,
+    pub progress_chars: Option<String>,
+    pub more_format: Option<String>,
+    pub show_count: Option<bool>,
+    pub only_next: Option<bool>,
+    // Entries shown on the bar
+    pub limit: Option<usize>,
+    pub countdown_only: Option<bool>,
+    pub count_format: Option<String>,
+    pub lines: Option<usize>,
+    pub header: Option<String>,
+    pub empty_text: Option<String>,
+    pub empty_color: Option<String>,
+    pub overdue_hours: Option<u32>,
+    // Events whose name contains any of these are hidden
+    pub exclude: Vec<String>,
+    // Entries shown and hidden by their CATEGORIES
+    pub categories: Vec<String>,
+    pub exclude_categories: Vec<String>,
+    pub skip_all_day: Option<bool>,
+    pub all_day_label: Option<String>,
+    pub show_cancelled: Option<bool>,
+    pub tentative_marker: Option<String>,
+    // Colors of entries about to start, as "MINUTES:COLOR"
+    pub thresholds: Vec<String>,
+    // Minutes before an event the line is marked urgent
+    pub urgent: Option<u32>,
+    // Status to exit with while it is
+    pub urgent_exit: Option<i32>,
+    // Exit with EXIT_EMPTY when nothing is upcoming
+    pub exit_status: Option<bool>,
+    pub max_width: Option<usize>,
+    pub title_width: Option<usize>,
+    // Scroll long titles through the title width, one character per run
+    pub scroll: Option<bool>,
+    pub format: Option<String>,
+    pub ongoing_format: Option<String>,
+    // A Tera template file rendered in place of the usual output
+    pub template: Option<PathBuf>,
+    pub cache: Option<bool>,
+    pub timezone: Option<String>,
+    // Seconds
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub retries: Option<u32>,
+    pub polybar: PolybarConfig,
+    pub icons: IconsConfig,
+    #[serde(rename = "source")]
+    pub sources: Vec<SourceConfig>,
+}
+
+// A `[[source]]` table: a path or URL with the options applying to it
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SourceConfig {
+    pub path: String,
+    // A command printing an ics calendar, in place of the path
+    pub exec: Option<String>,
+    // Set to false to skip the source without removing it
+    pub enabled: Option<bool>,
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub label: Option<String>,
+    pub icon: Option<String>,
+    pub calendars: Vec<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    // Look the password up in the keyring under this name instead
+    pub keyring: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub tenant: Option<String>,
+    pub timezone: Option<String>,
+    pub encoding: Option<String>,
+    // Minutes a download is used before the server is asked again
+    pub max_age: Option<u64>,
+}
+
+// The `[polybar]` table: tags around the whole line, which the file can't add itself
+// without leaving them behind when nothing is upcoming
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PolybarConfig {
+    pub foreground: Option<String>,
+    pub underline: Option<String>,
+    pub urgent_underline: Option<String>,
+    pub on_click: Option<String>,
+    pub on_middle_click: Option<String>,
+    pub on_right_click: Option<String>,
+    pub on_scroll_up: Option<String>,
+    pub on_scroll_down: Option<String>,
+}
+
+impl PolybarConfig {
+    fn tags(&self) -> LineTags {
+        let actions = [
+            &self.on_click,
+            &self.on_middle_click,
+            &self.on_right_click,
+            &self.on_scroll_up,
+            &self.on_scroll_down,
+        ];
+        LineTags {
+            foreground: self.foreground.clone(),
+            underline: self.underline.clone(),
+            urgent_underline: self.urgent_underline.clone(),
+            actions: (1..)
+                .zip(actions)
+                .filter_map(|(button, command)| Some((button, command.clone()?)))
+                .collect(),
+        }
+    }
+}
+
+// The `[icons]` table, e.g. `categories = { standup = "" }`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconsConfig {
+    pub categories: BTreeMap<String, String>,
+    pub calendars: BTreeMap<String, String>,
+}
+
+impl IconsConfig {
+    fn icons(&self) -> Icons {
+        Icons {
+            categories: self.categories.clone().into_iter().collect(),
+            calendars: self.calendars.clone().into_iter().collect(),
+        }
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("Invalid value for {}: {}", key, e))
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.message().to_owned())
+    }
+
+    // The configuration at `path`, None if there is no such file
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        Self::parse(&text)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Use the file's settings as the defaults the command line starts from
+    pub fn apply(
+        &self,
+        opts: &mut DisplayOptions,
+        load_opts: &mut LoadOptions,
+    ) -> Result<(), String> {
+        if let Some(display) = &self.display {
+            opts.mode = parse("display", display)?;
+        }
+        if let Some(show_calendar) = self.show_calendar {
+            opts.show_calendar = show_calendar;
+        }
+        if let Some(show_location) = self.show_location {
+            opts.show_location = show_location;
+        }
+        if let Some(show_description) = self.show_description {
+            opts.show_description = show_description;
+        }
+        if self.description_width.is_some() {
+            opts.description_width = self.description_width;
+        }
+        if let Some(humanize) = self.humanize {
+            opts.humanize = humanize;
+        }
+        if let Some(units) = &self.units {
+            opts.durations.units = parse("units", units)?;
+        }
+        if let Some(style) = &self.durations {
+            opts.durations.style = parse("durations", style)?;
+        }
+        if let Some(rounding) = &self.rounding {
+            opts.durations.rounding = parse("rounding", rounding)?;
+        }
+        if let Some(lang) = &self.lang {
+            opts.language = parse("lang", lang)?;
+        }
+        if let Some(show_end) = self.show_end {
+            opts.show_end = show_end;
+        }
+        if let Some(minutes) = self.countdown {
+            opts.countdown_below = Some(capped_minutes(minutes));
+        }
+        if let Some(label) = &self.day_label {
+            opts.day_label = parse("day-label", label)?;
+        }
+        if let Some(label) = &self.compact_day_label {
+            opts.compact_day_label = parse("compact-day-label", label)?;
+        }
+        match self.twelve_hour {
+            Some(true) => opts.clock = Clock::TwelveHour,
+            Some(false) => opts.clock = Clock::TwentyFourHour,
+            None => {}
+        }
+        if let Some(days) = self.days {
+            opts.window = WindowMode::Days(days);
+        }
+        if self.today == Some(true) {
+            opts.window = WindowMode::Days(0);
+        }
+        if let Some(hours) = self.hours_behind {
+            opts.window = opts.window.behind(hours.into());
+        }
+        if let Some(hours) = self.hours_ahead {
+            opts.window = opts.window.ahead(hours.into());
+        }
+        if let Some(separator) = &self.separator {
+            opts.separator = Some(separator.clone());
+        }
+        if let Some(ascii) = self.ascii {
+            opts.ascii = ascii;
+        }
+        if self.progress_bar.is_some() {
+            opts.progress_bar = self.progress_bar;
+        }
+        if let Some(chars) = &self.progress_chars {
+            opts.progress_chars = Some(parse("progress-chars", chars)?);
+        }
+        if let Some(more_format) = &self.more_format {
+            opts.more_format = Some(more_format.clone());
+        }
+        if self.limit.is_some() {
+            opts.limit = self.limit;
+        }
+        if let Some(only_next) = self.only_next {
+            opts.only_next = only_next;
+        }
+        if let Some(countdown_only) = self.countdown_only {
+            opts.countdown_only = countdown_only;
+        }
+        if let Some(show_count) = self.show_count {
+            opts.show_count = show_count;
+        }
+        if self.lines.is_some() {
+            opts.lines = self.lines;
+        }
+        if self.header.is_some() {
+            opts.header = self.header.clone();
+        }
+        if let Some(format) = &self.count_format {
+            opts.show_count = true;
+            opts.count_format = Some(format.clone());
+        }
+        if let Some(text) = &self.empty_text {
+            opts.empty = EmptyOutput::Text(text.clone());
+        }
+        if self.empty_color.is_some() {
+            opts.empty_color = self.empty_color.clone();
+        }
+        if let Some(hours) = self.overdue_hours {
+            opts.overdue_for = Some(capped_hours(hours));
+        }
+        opts.exclude.extend(self.exclude.iter().cloned());
+        opts.categories.extend(self.categories.iter().cloned());
+        opts.exclude_categories
+            .extend(self.exclude_categories.iter().cloned());
+        if let Some(skip_all_day) = self.skip_all_day {
+            opts.skip_all_day = skip_all_day;
+        }
+        if self.all_day_label.is_some() {
+            opts.all_day_label = self.all_day_label.clone();
+        }
+        if let Some(show_cancelled) = self.show_cancelled {
+            opts.show_cancelled = show_cancelled;
+        }
+        if self.tentative_marker.is_some() {
+            opts.tentative_marker = self.tentative_marker.clone();
+        }
+        for threshold in &self.thresholds {
+            opts.thresholds.push(parse("thresholds", threshold)?);
+        }
+        if let Some(minutes) = self.urgent {
+            opts.urgent_within = Some(capped_minutes(minutes));
+        }
+        opts.line_tags = self.polybar.tags();
+        opts.icons = self.icons.icons();
+        if let Some(format) = &self.format {
+            opts.format = Some(parse("format", format)?);
+        }
+        if let Some(format) = &self.ongoing_format {
+            opts.ongoing_format = Some(parse("ongoing-format", format)?);
+        }
+        if self.max_width.is_some() {
+            opts.max_width = self.max_width;
+        }
+        if self.title_width.is_some() {
+            opts.title_width = self.title_width;
+        }
+        if let Some(timezone) = &self.timezone {
+            load_opts.timezone = Some(parse("timezone", timezone)?);
+        }
+        if let Some(seconds) = self.connect_timeout {
+            load_opts.network.connect_timeout = StdDuration::from_secs(seconds);
+        }
+        if let Some(seconds) = self.read_timeout {
+            load_opts.network.read_timeout = StdDuration::from_secs(seconds);
+        }
+        if let Some(retries) = self.retries {
+            load_opts.network.retries = retries;
+        }
+        Ok(())
+    }
+
+    // The configured sources, each with its own options on top of `defaults`.
+    // Sources picked by their name or label with --calendar are shown whole. The other
+    // names pick calendars found on the servers of the remaining sources, which aren't
+    // loaded at all when every name is a source's
+    pub fn sources(&self, defaults: &LoadOptions) -> Result<Vec<(String, LoadOptions)>, String> {
+        let enabled = || {
+            self.sources
+                .iter()
+                .filter(|source| source.enabled != Some(false))
+        };
+        let unpicked: Vec<String> = defaults
+            .calendars
+            .iter()
+            .filter(|name| !enabled().any(|source| source.is_named(name)))
+            .cloned()
+            .collect();
+        let picked =
+            |source: &SourceConfig| defaults.calendars.iter().any(|name| source.is_named(name));
+        enabled()
+            .filter(|source| {
+                defaults.calendars.is_empty() || !unpicked.is_empty() || picked(source)
+            })
+            .map(|source| {
+                let path = match (&source.exec, source.path.is_empty()) {
+                    (Some(command), true) => format!("{}{}", EXEC, command),
+                    (None, false) => source.path.clone(),
+                    _ => return Err("Every [[source]] needs either a path or exec".to_owned()),
+                };
+                let calendars = if picked(source) {
+                    Vec::new()
+                } else {
+                    unpicked.clone()
+                };
+                let opts = source.options(&LoadOptions {
+                    calendars,
+                    ..defaults.clone()
+                })?;
+                Ok((path, opts))
+            })
+            .collect()
+    }
+}
+
+impl SourceConfig {
+    // Whether --calendar `name` is this source's name or label
+    fn is_named(&self, name: &str) -> bool {
+        [&self.name, &self.label]
+            .into_iter()
+            .flatten()
+            .any(|own| own.eq_ignore_ascii_case(name))
+    }
+
+    fn options(&self, defaults: &LoadOptions) -> Result<LoadOptions, String> {
+        let mut opts = defaults.clone();
+        if self.name.is_some() {
+            opts.name = self.name.clone();
+        }
+        if self.color.is_some() {
+            opts.color = self.color.clone();
+        }
+        if self.label.is_some() {
+            opts.label = self.label.clone();
+        }
+        if self.icon.is_some() {
+            opts.icon = self.icon.clone();
+        }
+        opts.calendars.extend(self.calendars.iter().cloned());
+        if let Some(user) = &self.user {
+            opts.credentials
+                .get_or_insert_with(Default::default)
+                .username = user.clone();
+        }
+        if let Some(password) = &self.password {
+            opts.credentials
+                .get_or_insert_with(Default::default)
+                .password = password.clone();
+        }
+        if self.keyring.is_some() {
+            opts.keyring = self.keyring.clone();
+        }
+        if let Some(id) = &self.client_id {
+            opts.oauth_client.get_or_insert_with(Default::default).id = id.clone();
+        }
+        if let Some(secret) = &self.client_secret {
+            opts.oauth_client
+                .get_or_insert_with(Default::default)
+                .secret = Some(secret.clone());
+        }
+        if let Some(tenant) = &self.tenant {
+            opts.oauth_client
+                .get_or_insert_with(Default::default)
+                .tenant = Some(tenant.clone());
+        }
+        if let Some(timezone) = &self.timezone {
+            opts.timezone = Some(parse("timezone", timezone)?);
+        }
+        if let Some(label) = &self.encoding {
+            let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| format!("Unknown encoding: {}", label))?;
+            opts.encoding = Some(encoding);
+        }
+        if let Some(minutes) = self.max_age {
+            opts.max_age = Some(StdDuration::from_secs(minutes * 60));
+        }
+        Ok(opts)
+    }
+}
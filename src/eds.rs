@@ -0,0 +1,166 @@
+use crate::sources::{Collection, LoadOptions, SourceError};
+#[cfg(any(feature = "eds", test))]
+use chrono::{DateTime, Local, Utc};
+
+// `eds:` for the calendars of evolution-data-server, as set up in GNOME Online Accounts
+// or Evolution. Pick some of them by name with --calendar
+pub const SCHEME: &str = "eds:";
+
+// A calendar configured in evolution-data-server
+#[cfg(any(feature = "eds", test))]
+#[derive(Debug, PartialEq)]
+pub struct EdsSource {
+    pub uid: String,
+    pub name: Option<String>,
+    pub color: Option<String>,
+}
+
+// Sources are described by key files. Only enabled ones with a [Calendar] section
+// are calendars
+#[cfg(any(feature = "eds", test))]
+pub fn parse_source(uid: &str, data: &str) -> Option<EdsSource> {
+    let mut section = "";
+    let mut is_calendar = false;
+    let mut enabled = true;
+    let mut name = None;
+    let mut color = None;
+    for line in data.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = header;
+            is_calendar |= section == "Calendar";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match (section, key.trim()) {
+            ("Data Source", "DisplayName") => name = Some(value.to_owned()),
+            ("Data Source", "Enabled") => enabled = value == "true",
+            ("Calendar", "Color") if !value.is_empty() => color = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+    (is_calendar && enabled).then(|| EdsSource {
+        uid: uid.to_owned(),
+        name,
+        color,
+    })
+}
+
+// The S-expression selecting the events that overlap the window
+#[cfg(any(feature = "eds", test))]
+pub fn time_range_query(window: Option<(DateTime<Local>, DateTime<Local>)>) -> String {
+    let time = |t: DateTime<Local>| t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string();
+    match window {
+        Some((start, end)) => format!(
+            "(occur-in-time-range? (make-time \"{}\") (make-time \"{}\"))",
+            time(start),
+            time(end)
+        ),
+        None => "#t".to_owned(),
+    }
+}
+
+#[cfg(feature = "eds")]
+mod dbus {
+    use super::{parse_source, time_range_query, EdsSource};
+    use crate::sources::{parse_calendar, Collection, Document, LoadOptions, SourceError};
+    use std::{collections::HashMap, path::Path};
+    use zbus::{
+        blocking::{Connection, Proxy},
+        zvariant::{OwnedObjectPath, OwnedValue},
+    };
+
+    const SOURCES_BUS: &str = "org.gnome.evolution.dataserver.Sources5";
+    const SOURCES_PATH: &str = "/org/gnome/evolution/dataserver/SourceManager";
+    const SOURCE_INTERFACE: &str = "org.gnome.evolution.dataserver.Source";
+    const CALENDAR_BUS: &str = "org.gnome.evolution.dataserver.Calendar8";
+    const FACTORY_PATH: &str = "/org/gnome/evolution/dataserver/CalendarFactory";
+    const FACTORY_INTERFACE: &str = "org.gnome.evolution.dataserver.CalendarFactory";
+    const CALENDAR_INTERFACE: &str = "org.gnome.evolution.dataserver.Calendar";
+
+    type ManagedObjects = HashMap<OwnedObjectPath, HashMap<String, HashMap<String, OwnedValue>>>;
+
+    fn dbus_error(e: zbus::Error) -> SourceError {
+        SourceError::Service("evolution-data-server".to_owned(), e.to_string())
+    }
+
+    fn sources(connection: &Connection) -> zbus::Result<Vec<EdsSource>> {
+        let manager = Proxy::new(
+            connection,
+            SOURCES_BUS,
+            SOURCES_PATH,
+            "org.freedesktop.DBus.ObjectManager",
+        )?;
+        let objects: ManagedObjects = manager.call("GetManagedObjects", &())?;
+        let property = |properties: &HashMap<String, OwnedValue>, name: &str| {
+            properties
+                .get(name)
+                .and_then(|value| String::try_from(value.clone()).ok())
+        };
+        Ok(objects
+            .values()
+            .filter_map(|interfaces| interfaces.get(SOURCE_INTERFACE))
+            .filter_map(|properties| {
+                parse_source(
+                    &property(properties, "UID")?,
+                    &property(properties, "Data")?,
+                )
+            })
+            .collect())
+    }
+
+    // The components of a calendar that overlap the agenda window, as ics
+    fn objects(connection: &Connection, uid: &str, query: &str) -> zbus::Result<Vec<String>> {
+        let factory = Proxy::new(connection, CALENDAR_BUS, FACTORY_PATH, FACTORY_INTERFACE)?;
+        let (path, bus): (String, String) = factory.call("OpenCalendar", &(uid,))?;
+        let calendar = Proxy::new(connection, bus, path, CALENDAR_INTERFACE)?;
+        calendar.call::<_, _, ()>("Open", &())?;
+        calendar.call("GetObjectList", &(query,))
+    }
+
+    pub fn load(opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+        let connection = Connection::session().map_err(dbus_error)?;
+        let query = time_range_query(opts.window);
+        let mut collections = Vec::new();
+        for source in sources(&connection).map_err(dbus_error)? {
+            if !opts.wants_calendar(source.name.as_deref()) {
+                continue;
+            }
+            let components = objects(&connection, &source.uid, &query).map_err(dbus_error)?;
+            let text = format!(
+                "BEGIN:VCALENDAR\r\n{}\r\nEND:VCALENDAR\r\n",
+                components.join("\r\n")
+            );
+            let label = format!("eds:{}", source.name.as_deref().unwrap_or(&source.uid));
+            collections.push(Collection {
+                documents: vec![Document::Ics(parse_calendar(
+                    &text,
+                    Path::new(&label),
+                    opts,
+                )?)],
+                name: source.name,
+                color: source.color,
+                stale: None,
+                label: None,
+                icon: None,
+            });
+        }
+        Ok(collections)
+    }
+}
+
+// The calendars of evolution-data-server, over the session bus
+pub fn load(opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+    #[cfg(feature = "eds")]
+    return dbus::load(opts);
+    #[cfg(not(feature = "eds"))]
+    {
+        let _ = opts;
+        Err(SourceError::Service(
+            "evolution-data-server".to_owned(),
+            "not supported by this build, rebuild with `--features eds`".to_owned(),
+        ))
+    }
+}
@@ -0,0 +1,61 @@
+use crate::calendar::AgendaEntry;
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+use icalendar::{Calendar, Component, Event, EventLike};
+use sha2::{Digest, Sha256};
+
+// The summary of every event once the details are scrubbed
+const BUSY: &str = "Busy";
+
+fn utc(time: NaiveDateTime) -> chrono::DateTime<Utc> {
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .unwrap_or_else(|| Utc.from_utc_datetime(&time).with_timezone(&Local))
+        .with_timezone(&Utc)
+}
+
+// Stable across runs, so that subscribers update events rather than duplicate them.
+// Hashed, so the title doesn't show through a scrubbed event
+fn uid(entry: &AgendaEntry) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(entry.name.as_bytes());
+    hasher.update([0]);
+    hasher.update(entry.calendar.as_deref().unwrap_or_default().as_bytes());
+    hasher.update([0]);
+    hasher.update(entry.start.to_string().as_bytes());
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}@polybar-agenda", &digest[..32])
+}
+
+// The events among `entries` as an ics calendar, to publish a trimmed copy of the
+// agenda. Scrubbed, they keep only their times. Todos are left out
+pub fn to_ics(entries: &[AgendaEntry], scrub: bool) -> Calendar {
+    let mut calendar = Calendar::new();
+    for entry in entries.iter().filter(|entry| entry.due.is_none()) {
+        let end = entry.start + entry.duration;
+        let mut event = Event::new();
+        event.uid(&uid(entry));
+        if entry.all_day {
+            event.starts(entry.start.date()).ends(end.date());
+        } else {
+            event.starts(utc(entry.start)).ends(utc(end));
+        }
+        if scrub {
+            event.summary(BUSY);
+        } else {
+            event.summary(&entry.name);
+            if let Some(location) = &entry.location {
+                event.location(location);
+            }
+            if let Some(link) = &entry.link {
+                event.url(link);
+            }
+        }
+        calendar.push(event.done());
+    }
+    calendar.done()
+}
@@ -0,0 +1,262 @@
+// Best-effort scanner for ics files the icalendar parser rejects. Some providers
+// emit technically invalid files (stray lines, broken folding, unknown value types)
+// that are otherwise perfectly usable, so this only looks at the handful of
+// properties the agenda needs and ignores everything else
+use crate::calendar::{
+    as_naive, person, recurrence_end, split_categories, unescape_text, EventRecord, Status,
+    RRULE_PROPERTIES,
+};
+use crate::links::{conference_link, LINK_PROPERTIES};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use icalendar::CalendarDateTime;
+use std::collections::HashMap;
+
+struct Property<'a> {
+    params: HashMap<String, &'a str>,
+    value: &'a str,
+}
+
+// Join folded lines (CRLF followed by a space or tab) back together
+fn unfold(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "")
+}
+
+fn parse_line(line: &str) -> Option<(String, Property<'_>)> {
+    let (head, value) = line.split_once(':')?;
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim().to_ascii_uppercase(), v.trim_matches('"')))
+        .collect();
+    Some((name, Property { params, value }))
+}
+
+// DATE, DATE-TIME (floating, UTC or with a TZID) values converted to local time
+fn parse_date_time(prop: &Property, default_tz: Option<Tz>) -> Option<(NaiveDateTime, bool)> {
+    let value = prop.value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Some((date.and_time(NaiveTime::MIN), true));
+    }
+
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(v) => (v, true),
+        None => (value, false),
+    };
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    let dt = match (utc, prop.params.get("TZID")) {
+        (true, _) => CalendarDateTime::Utc(Utc.from_utc_datetime(&naive)),
+        (false, Some(tzid)) => CalendarDateTime::WithTimezone {
+            date_time: naive,
+            tzid: tzid.to_string(),
+        },
+        (false, None) => CalendarDateTime::Floating(naive),
+    };
+    Some((as_naive(dt, default_tz).ok()?, false))
+}
+
+// RFC 5545 durations such as `PT1H30M`, `P1D` or `P2W`
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(v) => (true, v),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let mut rest = value.strip_prefix('P')?;
+    let mut total = Duration::zero();
+    let mut in_time = false;
+
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('T') {
+            in_time = true;
+            rest = r;
+            continue;
+        }
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let n: i64 = rest[..digits].parse().ok()?;
+        let unit = rest[digits..].chars().next()?;
+        let part = match (unit, in_time) {
+            ('W', false) => Duration::try_weeks(n),
+            ('D', false) => Duration::try_days(n),
+            ('H', true) => Duration::try_hours(n),
+            ('M', true) => Duration::try_minutes(n),
+            ('S', true) => Duration::try_seconds(n),
+            _ => None,
+        };
+        total = total.checked_add(&part?)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+
+    Some(if negative { -total } else { total })
+}
+
+// A DTSTART with a time but neither a TZID nor a trailing Z
+fn floating_start(props: &HashMap<String, Property>) -> bool {
+    props.get("DTSTART").is_some_and(|p| {
+        let value = p.value.trim();
+        value.contains('T') && !value.ends_with('Z') && !p.params.contains_key("TZID")
+    })
+}
+
+fn link(props: &HashMap<String, Property>) -> Option<String> {
+    conference_link(
+        LINK_PROPERTIES
+            .iter()
+            .filter_map(|&p| props.get(p).map(|prop| (p, prop.value))),
+    )
+}
+
+fn categories(props: &HashMap<String, Property>) -> Vec<String> {
+    props
+        .get("CATEGORIES")
+        .map(|p| split_categories(&unescape_text(p.value)))
+        .unwrap_or_default()
+}
+
+fn organizer(props: &HashMap<String, Property>) -> Option<String> {
+    props
+        .get("ORGANIZER")
+        .map(|p| person(p.value, p.params.get("CN").copied()))
+}
+
+fn todo_record(props: &HashMap<String, Property>, default_tz: Option<Tz>) -> Option<EventRecord> {
+    let status = props
+        .get("STATUS")
+        .map(|p| p.value.trim().to_ascii_uppercase());
+    if matches!(status.as_deref(), Some("COMPLETED" | "CANCELLED")) {
+        return None;
+    }
+    let (due, date_only) = parse_date_time(props.get("DUE")?, default_tz)?;
+    let due = if date_only {
+        due + Duration::days(1)
+    } else {
+        due
+    };
+
+    Some(EventRecord {
+        name: props
+            .get("SUMMARY")
+            .map(|p| unescape_text(p.value))
+            .unwrap_or_default(),
+        start: due,
+        end: due,
+        all_day: false,
+        recurrence: None,
+        last_end: None,
+        due: Some(due),
+        link: link(props),
+        location: props.get("LOCATION").map(|p| unescape_text(p.value)),
+        categories: categories(props),
+        description: props.get("DESCRIPTION").map(|p| unescape_text(p.value)),
+        organizer: organizer(props),
+        attendees: 0,
+        status: Status::Confirmed,
+    })
+}
+
+fn event_record(
+    props: &HashMap<String, Property>,
+    raw: &HashMap<String, &str>,
+    default_tz: Option<Tz>,
+) -> Option<EventRecord> {
+    let (start, all_day) = parse_date_time(props.get("DTSTART")?, default_tz)?;
+    let end = match (props.get("DTEND"), props.get("DURATION")) {
+        (Some(end), _) => parse_date_time(end, default_tz)?.0,
+        (None, Some(duration)) => start + parse_duration(duration.value)?,
+        (None, None) if all_day => start + Duration::days(1),
+        (None, None) => start,
+    };
+
+    let recurrence = raw.contains_key("RRULE").then(|| {
+        RRULE_PROPERTIES
+            .iter()
+            .filter_map(|p| raw.get(*p).map(|line| format!("{}\n", line)))
+            .map(|line: String| match (default_tz, floating_start(props)) {
+                (Some(tz), true) if line.starts_with("DTSTART:") => {
+                    line.replacen("DTSTART:", &format!("DTSTART;TZID={}:", tz.name()), 1)
+                }
+                _ => line,
+            })
+            .collect()
+    });
+
+    Some(EventRecord {
+        name: props
+            .get("SUMMARY")
+            .map(|p| unescape_text(p.value))
+            .unwrap_or_default(),
+        start,
+        end,
+        all_day,
+        last_end: recurrence
+            .as_deref()
+            .and_then(|r| recurrence_end(r, start, end)),
+        recurrence,
+        due: None,
+        link: link(props),
+        location: props.get("LOCATION").map(|p| unescape_text(p.value)),
+        categories: categories(props),
+        description: props.get("DESCRIPTION").map(|p| unescape_text(p.value)),
+        organizer: organizer(props),
+        attendees: 0,
+        status: props
+            .get("STATUS")
+            .map(|p| Status::parse(p.value))
+            .unwrap_or_default(),
+    })
+}
+
+// Scan VEVENT and VTODO blocks for the properties the agenda needs. Events that
+// can't be made sense of are skipped
+pub fn parse(text: &str, default_tz: Option<Tz>) -> Vec<EventRecord> {
+    let text = unfold(text);
+    let mut records = Vec::new();
+    let mut props = HashMap::new();
+    let mut raw = HashMap::new();
+    // Nesting depth below the current VEVENT/VTODO, e.g. inside a VALARM
+    let mut depth: Option<usize> = None;
+    let mut todo = false;
+    // ATTENDEE repeats, so it is counted rather than kept with the properties
+    let mut attendees = 0;
+
+    for line in text.lines().map(str::trim_end) {
+        let Some((name, prop)) = parse_line(line) else {
+            continue;
+        };
+        let kind = prop.value.trim().to_ascii_uppercase();
+        match (name.as_str(), depth) {
+            ("BEGIN", None) if kind == "VEVENT" || kind == "VTODO" => {
+                depth = Some(0);
+                todo = kind == "VTODO";
+                props.clear();
+                raw.clear();
+                attendees = 0;
+            }
+            ("BEGIN", Some(d)) => depth = Some(d + 1),
+            ("END", Some(0)) => {
+                let record = if todo && props.contains_key("DUE") {
+                    todo_record(&props, default_tz)
+                } else {
+                    event_record(&props, &raw, default_tz)
+                };
+                records.extend(record.map(|record| EventRecord {
+                    attendees,
+                    ..record
+                }));
+                depth = None;
+            }
+            ("END", Some(d)) => depth = Some(d - 1),
+            ("ATTENDEE", Some(0)) => attendees += 1,
+            (_, Some(0)) => {
+                raw.insert(name.clone(), line);
+                props.insert(name, prop);
+            }
+            _ => {}
+        }
+    }
+
+    records
+}
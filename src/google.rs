@@ -0,0 +1,208 @@
+use crate::calendar::{person, EventRecord, Status};
+use crate::links::conference_link;
+use crate::oauth::{self, Provider};
+use crate::remote::{get, http_error, percent_encode, retry};
+use crate::sources::{Collection, Document, LoadOptions, SourceError};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Deserialize;
+
+// `google:primary` or `google:<calendar id>`
+pub const SCHEME: &str = "google:";
+
+const API_URL: &str = "https://www.googleapis.com/calendar/v3";
+
+pub fn provider() -> Provider {
+    Provider {
+        name: "google",
+        device_url: "https://oauth2.googleapis.com/device/code".to_owned(),
+        token_url: "https://oauth2.googleapis.com/token".to_owned(),
+        scope: "https://www.googleapis.com/auth/calendar.readonly".to_owned(),
+    }
+}
+
+// A page of the events list
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventList {
+    // The calendar's name
+    summary: Option<String>,
+    #[serde(default)]
+    items: Vec<Event>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    status: Option<String>,
+    summary: Option<String>,
+    start: Option<EventTime>,
+    end: Option<EventTime>,
+    hangout_link: Option<String>,
+    conference_data: Option<ConferenceData>,
+    location: Option<String>,
+    description: Option<String>,
+    organizer: Option<Person>,
+    #[serde(default)]
+    attendees: Vec<Person>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Person {
+    email: Option<String>,
+    display_name: Option<String>,
+}
+
+// All-day events only have a date
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventTime {
+    date_time: Option<String>,
+    date: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConferenceData {
+    #[serde(default)]
+    entry_points: Vec<EntryPoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryPoint {
+    entry_point_type: Option<String>,
+    uri: Option<String>,
+}
+
+impl EventTime {
+    fn local(&self) -> Option<NaiveDateTime> {
+        if let Some(date_time) = &self.date_time {
+            let date_time = DateTime::parse_from_rfc3339(date_time).ok()?;
+            return Some(date_time.with_timezone(&Local).naive_local());
+        }
+        let date = NaiveDate::parse_from_str(self.date.as_deref()?, "%Y-%m-%d").ok()?;
+        Some(date.and_time(NaiveTime::MIN))
+    }
+}
+
+impl Event {
+    fn link(&self) -> Option<String> {
+        let video = self
+            .conference_data
+            .iter()
+            .flat_map(|data| &data.entry_points)
+            .filter(|entry| entry.entry_point_type.as_deref() == Some("video"))
+            .filter_map(|entry| entry.uri.as_deref());
+        let properties = self
+            .hangout_link
+            .as_deref()
+            .into_iter()
+            .chain(video)
+            .map(|uri| ("X-GOOGLE-CONFERENCE", uri))
+            .chain(self.location.as_deref().map(|l| ("LOCATION", l)))
+            .chain(self.description.as_deref().map(|d| ("DESCRIPTION", d)));
+        conference_link(properties)
+    }
+
+    // Recurring events are expanded by the API, so every item is a single occurrence
+    fn record(&self) -> Option<EventRecord> {
+        if self.status.as_deref() == Some("cancelled") {
+            return None;
+        }
+        let start = self.start.as_ref()?.local()?;
+        let end = self
+            .end
+            .as_ref()
+            .and_then(EventTime::local)
+            .unwrap_or(start);
+        Some(EventRecord {
+            name: self.summary.clone().unwrap_or_default(),
+            start,
+            end,
+            all_day: self.start.as_ref()?.date_time.is_none(),
+            recurrence: None,
+            last_end: None,
+            due: None,
+            link: self.link(),
+            location: self.location.clone(),
+            categories: Vec::new(),
+            description: self.description.clone(),
+            organizer: self.organizer.as_ref().map(|organizer| {
+                person(
+                    organizer.email.as_deref().unwrap_or_default(),
+                    organizer.display_name.as_deref(),
+                )
+            }),
+            attendees: self.attendees.len(),
+            status: self
+                .status
+                .as_deref()
+                .map(Status::parse)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl EventList {
+    pub fn records(&self) -> Vec<EventRecord> {
+        self.items.iter().filter_map(Event::record).collect()
+    }
+}
+
+// The events of a Google calendar within the agenda window, using the tokens stored
+// by `polybar-agenda login google`
+pub fn load(calendar: &str, opts: &LoadOptions) -> Result<Collection, SourceError> {
+    let provider = provider();
+    let client = opts.oauth_client.as_ref().ok_or_else(|| {
+        SourceError::Auth(
+            provider.name.to_owned(),
+            "needs an OAuth client, pass --client-id and --client-secret".to_owned(),
+        )
+    })?;
+    let token_file = oauth::token_file(provider.name)
+        .ok_or_else(|| SourceError::Auth(provider.name.to_owned(), "no cache directory".into()))?;
+    let token = oauth::access_token(&provider, client, &token_file)?;
+
+    let url = format!("{}/calendars/{}/events", API_URL, percent_encode(calendar));
+    let mut query = vec![
+        ("singleEvents", "true".to_owned()),
+        ("orderBy", "startTime".to_owned()),
+        ("maxResults", "250".to_owned()),
+    ];
+    if let Some((start, end)) = opts.window {
+        query.push(("timeMin", start.to_rfc3339()));
+        query.push(("timeMax", end.to_rfc3339()));
+    }
+
+    let mut name = None;
+    let mut events = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let page: EventList = retry(&opts.network, || {
+            let mut request = get(&url, &opts.network)
+                .header("Authorization", format!("Bearer {}", token))
+                .query_pairs(query.iter().map(|(k, v)| (*k, v.as_str())));
+            if let Some(page_token) = &page_token {
+                request = request.query("pageToken", page_token);
+            }
+            request.call()?.body_mut().read_json()
+        })
+        .map_err(|e| http_error(&url, e))?;
+        name = name.or(page.summary.clone());
+        events.extend(page.records());
+        match page.next_page_token {
+            Some(next) => page_token = Some(next),
+            None => break,
+        }
+    }
+    Ok(Collection {
+        name,
+        color: None,
+        documents: vec![Document::Ics(events)],
+        stale: None,
+        label: None,
+        icon: None,
+    })
+}
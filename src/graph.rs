@@ -0,0 +1,252 @@
+use crate::calendar::{person, EventRecord, Status};
+use crate::links::conference_link;
+use crate::oauth::{self, OAuthClient, Provider};
+use crate::remote::{get, http_error, percent_encode, retry};
+use crate::sources::{Collection, Document, LoadOptions, SourceError};
+use chrono::{Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::Deserialize;
+
+// `outlook:` for the signed in user's calendar, `outlook:<user>` for a mailbox read
+// with the client's own permissions
+pub const SCHEME: &str = "outlook:";
+
+const API_URL: &str = "https://graph.microsoft.com/v1.0";
+
+fn tenant(client: &OAuthClient) -> &str {
+    client.tenant.as_deref().unwrap_or("common")
+}
+
+// Signing in as a user with the device code flow
+pub fn provider(client: &OAuthClient) -> Provider {
+    let base = format!(
+        "https://login.microsoftonline.com/{}/oauth2/v2.0",
+        tenant(client)
+    );
+    Provider {
+        name: "outlook",
+        device_url: format!("{}/devicecode", base),
+        token_url: format!("{}/token", base),
+        // Refresh tokens are only issued with offline_access
+        scope: "offline_access https://graph.microsoft.com/Calendars.Read".to_owned(),
+    }
+}
+
+// Application permissions granted to the client by an administrator
+fn app_provider(client: &OAuthClient) -> Provider {
+    Provider {
+        name: "outlook-app",
+        scope: "https://graph.microsoft.com/.default".to_owned(),
+        ..provider(client)
+    }
+}
+
+// A page of a calendar view
+#[derive(Deserialize)]
+pub struct EventList {
+    #[serde(default)]
+    value: Vec<Event>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    subject: Option<String>,
+    start: Option<EventTime>,
+    end: Option<EventTime>,
+    #[serde(default)]
+    is_all_day: bool,
+    #[serde(default)]
+    is_cancelled: bool,
+    // Free, tentative, busy, oof, workingElsewhere or unknown
+    show_as: Option<String>,
+    online_meeting: Option<OnlineMeeting>,
+    location: Option<Location>,
+    body_preview: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    organizer: Option<Recipient>,
+    #[serde(default)]
+    attendees: Vec<Recipient>,
+}
+
+// Organizers and attendees
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Recipient {
+    email_address: Option<EmailAddress>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmailAddress {
+    name: Option<String>,
+    address: Option<String>,
+}
+
+// Times are requested in UTC, all-day events are at midnight of their dates
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventTime {
+    date_time: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnlineMeeting {
+    join_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    display_name: Option<String>,
+}
+
+impl EventTime {
+    fn local(&self, all_day: bool) -> Option<NaiveDateTime> {
+        // Fractional seconds come with seven digits
+        let time = self.date_time.split('.').next()?;
+        let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S").ok()?;
+        if all_day {
+            return Some(time.date().and_time(NaiveTime::MIN));
+        }
+        Some(
+            Utc.from_utc_datetime(&time)
+                .with_timezone(&Local)
+                .naive_local(),
+        )
+    }
+}
+
+impl Event {
+    fn link(&self) -> Option<String> {
+        let join_url = self
+            .online_meeting
+            .as_ref()
+            .and_then(|meeting| meeting.join_url.as_deref())
+            .map(|url| ("X-MICROSOFT-ONLINEMEETINGURL", url));
+        let location = self
+            .location
+            .as_ref()
+            .and_then(|location| location.display_name.as_deref())
+            .map(|l| ("LOCATION", l));
+        let description = self.body_preview.as_deref().map(|d| ("DESCRIPTION", d));
+        conference_link(join_url.into_iter().chain(location).chain(description))
+    }
+
+    // Recurring events are expanded by the calendar view
+    fn record(&self) -> Option<EventRecord> {
+        if self.is_cancelled {
+            return None;
+        }
+        let start = self.start.as_ref()?.local(self.is_all_day)?;
+        let end = self
+            .end
+            .as_ref()
+            .and_then(|end| end.local(self.is_all_day))
+            .unwrap_or(start);
+        Some(EventRecord {
+            name: self.subject.clone().unwrap_or_default(),
+            start,
+            end,
+            all_day: self.is_all_day,
+            recurrence: None,
+            last_end: None,
+            due: None,
+            link: self.link(),
+            location: self
+                .location
+                .as_ref()
+                .and_then(|location| location.display_name.clone()),
+            categories: self.categories.clone(),
+            description: self.body_preview.clone(),
+            organizer: self
+                .organizer
+                .as_ref()
+                .and_then(|organizer| organizer.email_address.as_ref())
+                .map(|email| {
+                    person(
+                        email.address.as_deref().unwrap_or_default(),
+                        email.name.as_deref(),
+                    )
+                }),
+            attendees: self.attendees.len(),
+            // Only "tentative" matches a STATUS, the others are confirmed
+            status: self
+                .show_as
+                .as_deref()
+                .map(Status::parse)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl EventList {
+    pub fn records(&self) -> Vec<EventRecord> {
+        self.value.iter().filter_map(Event::record).collect()
+    }
+}
+
+// The events of an Outlook calendar within the agenda window. The signed in user's
+// calendar uses the tokens stored by `polybar-agenda login outlook`, another user's
+// is read with a token for the client itself
+pub fn load(user: &str, opts: &LoadOptions) -> Result<Collection, SourceError> {
+    let client = opts.oauth_client.as_ref().ok_or_else(|| {
+        SourceError::Auth(
+            "outlook".to_owned(),
+            "needs an OAuth client, pass --client-id".to_owned(),
+        )
+    })?;
+    let (provider, mailbox) = match user {
+        "" => (provider(client), "me".to_owned()),
+        user => (
+            app_provider(client),
+            format!("users/{}", percent_encode(user)),
+        ),
+    };
+    let token_file = oauth::token_file(provider.name)
+        .ok_or_else(|| SourceError::Auth(provider.name.to_owned(), "no cache directory".into()))?;
+    let token = match user {
+        "" => oauth::access_token(&provider, client, &token_file)?,
+        _ => oauth::client_token(&provider, client, &token_file)?,
+    };
+
+    let url = format!("{}/{}/calendarView", API_URL, mailbox);
+    let (start, end) = opts
+        .window
+        .ok_or_else(|| SourceError::Http(url.clone(), "no time range to query".to_owned()))?;
+    let mut page_url = format!(
+        "{}?startDateTime={}&endDateTime={}&$top=100",
+        url,
+        percent_encode(&start.with_timezone(&Utc).to_rfc3339()),
+        percent_encode(&end.with_timezone(&Utc).to_rfc3339())
+    );
+    let mut events = Vec::new();
+    loop {
+        let page: EventList = retry(&opts.network, || {
+            get(&page_url, &opts.network)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Prefer", "outlook.timezone=\"UTC\"")
+                .call()?
+                .body_mut()
+                .read_json()
+        })
+        .map_err(|e| http_error(&url, e))?;
+        events.extend(page.records());
+        match page.next_link {
+            // The link carries the query already
+            Some(next) => page_url = next,
+            None => break,
+        }
+    }
+    Ok(Collection {
+        name: None,
+        color: None,
+        documents: vec![Document::Ics(events)],
+        stale: None,
+        label: None,
+        icon: None,
+    })
+}
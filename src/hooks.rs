@@ -0,0 +1,134 @@
+use crate::calendar::AgendaEntry;
+use chrono::NaiveDateTime;
+use std::{fs, io, path::Path, process::Command};
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+// Commands run through `sh -c` when an event starts or ends. The event is passed in
+// AGENDA_NAME, AGENDA_START, AGENDA_END, AGENDA_CALENDAR and AGENDA_LINK
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub on_start: Option<String>,
+    pub on_end: Option<String>,
+}
+
+// An ongoing event as remembered between runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ongoing {
+    pub name: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub calendar: String,
+    pub link: String,
+}
+
+impl Ongoing {
+    // Tabs and newlines can't be stored in the state file, so they're replaced up
+    // front to keep entries comparable with the ones read back
+    fn from_entry(entry: &AgendaEntry) -> Self {
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        Self {
+            name: clean(&entry.name),
+            start: entry.start,
+            end: entry.start + entry.duration,
+            calendar: clean(entry.calendar.as_deref().unwrap_or_default()),
+            link: clean(entry.link.as_deref().unwrap_or_default()),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.start.format(TIME_FORMAT),
+            self.end.format(TIME_FORMAT),
+            self.calendar,
+            self.link,
+            self.name
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        let start = NaiveDateTime::parse_from_str(fields.next()?, TIME_FORMAT).ok()?;
+        let end = NaiveDateTime::parse_from_str(fields.next()?, TIME_FORMAT).ok()?;
+        Some(Self {
+            calendar: fields.next()?.to_owned(),
+            link: fields.next()?.to_owned(),
+            name: fields.next()?.to_owned(),
+            start,
+            end,
+        })
+    }
+}
+
+// Events in progress at `now`. Todos and zero-length events never are
+pub fn ongoing(entries: &[AgendaEntry], now: NaiveDateTime) -> Vec<Ongoing> {
+    entries
+        .iter()
+        .filter(|e| e.due.is_none() && e.start <= now && now < e.start + e.duration)
+        .map(Ongoing::from_entry)
+        .collect()
+}
+
+// Events that started and ended since the previous run
+pub fn transitions<'a>(
+    previous: &'a [Ongoing],
+    current: &'a [Ongoing],
+) -> (Vec<&'a Ongoing>, Vec<&'a Ongoing>) {
+    let started = current.iter().filter(|e| !previous.contains(e)).collect();
+    let ended = previous.iter().filter(|e| !current.contains(e)).collect();
+    (started, ended)
+}
+
+pub fn read_state(path: &Path) -> Vec<Ongoing> {
+    fs::read_to_string(path)
+        .map(|state| state.lines().filter_map(Ongoing::from_line).collect())
+        .unwrap_or_default()
+}
+
+pub fn write_state(path: &Path, ongoing: &[Ongoing]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let lines: String = ongoing.iter().map(|e| e.to_line() + "\n").collect();
+    fs::write(path, lines)
+}
+
+fn spawn(command: &str, event: &Ongoing) -> io::Result<()> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("AGENDA_NAME", &event.name)
+        .env("AGENDA_START", event.start.format(TIME_FORMAT).to_string())
+        .env("AGENDA_END", event.end.format(TIME_FORMAT).to_string())
+        .env("AGENDA_CALENDAR", &event.calendar)
+        .env("AGENDA_LINK", &event.link)
+        .spawn()
+        .map(|_| ())
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_start.is_none() && self.on_end.is_none()
+    }
+
+    // Compare the ongoing events with the ones recorded in `state` by the previous run
+    // and run the hooks for those that started or ended in between
+    pub fn run(&self, entries: &[AgendaEntry], now: NaiveDateTime, state: &Path) -> io::Result<()> {
+        let previous = read_state(state);
+        let current = ongoing(entries, now);
+        let (started, ended) = transitions(&previous, &current);
+
+        if let Some(command) = &self.on_end {
+            for event in ended {
+                spawn(command, event)?;
+            }
+        }
+        if let Some(command) = &self.on_start {
+            for event in started {
+                spawn(command, event)?;
+            }
+        }
+        write_state(state, &current)
+    }
+}
@@ -0,0 +1,270 @@
+use chrono::{Duration, Locale};
+use std::{env, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+    French,
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    // Accepts plain codes (`de`) as well as locale names (`de_AT.UTF-8`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.split(['_', '.', '-', '@']).next().unwrap_or("");
+        match code.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Ok(Language::English),
+            "de" => Ok(Language::German),
+            "fr" => Ok(Language::French),
+            _ => Err(format!("Unsupported language: {}", s)),
+        }
+    }
+}
+
+impl Language {
+    // Pick the language from the usual locale variables, falling back to English
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Language::English)
+    }
+}
+
+// The locale times and dates are written in, from LC_TIME like the C library picks it.
+// None for the C locale and unknown ones
+pub fn time_locale() -> Option<Locale> {
+    let value = ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())?;
+    // Without the encoding and modifier of e.g. de_DE.UTF-8@euro
+    let name = value.split(['.', '@']).next()?;
+    Locale::try_from(name).ok()
+}
+
+// Singular phrase and plural template ("{}" is the count) for a unit, in the
+// grammatical case used after "in"/"ago"
+struct Unit {
+    one: &'static str,
+    many: &'static str,
+}
+
+struct Phrases {
+    future: &'static str,
+    past: &'static str,
+    ends: &'static str,
+    moment: &'static str,
+    just_started: &'static str,
+    half_hour: &'static str,
+    // Of todos: "due {}", where {} is "in ..."
+    due: &'static str,
+    overdue: &'static str,
+    // Of events whose STATUS is CANCELLED
+    cancelled: &'static str,
+    tomorrow: &'static str,
+    yesterday: &'static str,
+    // Before the end time of an ongoing event
+    until: &'static str,
+    // The number of events left today
+    today: &'static str,
+    // In place of the time of an event lasting whole days
+    all_day: &'static str,
+    minute: Unit,
+    hour: Unit,
+    day: Unit,
+}
+
+const ENGLISH: Phrases = Phrases {
+    future: "in {}",
+    past: "{} ago",
+    ends: "ends in {}",
+    moment: "in a moment",
+    just_started: "just started",
+    half_hour: "half an hour",
+    due: "due {}",
+    overdue: "OVERDUE",
+    cancelled: "CANCELLED",
+    tomorrow: "tomorrow",
+    yesterday: "yesterday",
+    until: "until {}",
+    today: "{} today",
+    all_day: "All day",
+    minute: Unit {
+        one: "a minute",
+        many: "{} minutes",
+    },
+    hour: Unit {
+        one: "an hour",
+        many: "{} hours",
+    },
+    day: Unit {
+        one: "a day",
+        many: "{} days",
+    },
+};
+
+const GERMAN: Phrases = Phrases {
+    future: "in {}",
+    past: "vor {}",
+    ends: "endet in {}",
+    moment: "gleich",
+    just_started: "hat gerade begonnen",
+    half_hour: "einer halben Stunde",
+    due: "fällig {}",
+    overdue: "ÜBERFÄLLIG",
+    cancelled: "ABGESAGT",
+    tomorrow: "morgen",
+    yesterday: "gestern",
+    until: "bis {}",
+    today: "{} heute",
+    all_day: "Ganztägig",
+    minute: Unit {
+        one: "einer Minute",
+        many: "{} Minuten",
+    },
+    hour: Unit {
+        one: "einer Stunde",
+        many: "{} Stunden",
+    },
+    day: Unit {
+        one: "einem Tag",
+        many: "{} Tagen",
+    },
+};
+
+const FRENCH: Phrases = Phrases {
+    future: "dans {}",
+    past: "il y a {}",
+    ends: "se termine dans {}",
+    moment: "dans un instant",
+    just_started: "vient de commencer",
+    half_hour: "une demi-heure",
+    due: "à rendre {}",
+    overdue: "EN RETARD",
+    cancelled: "ANNULÉ",
+    tomorrow: "demain",
+    yesterday: "hier",
+    until: "jusqu'à {}",
+    today: "{} aujourd'hui",
+    all_day: "Toute la journée",
+    minute: Unit {
+        one: "une minute",
+        many: "{} minutes",
+    },
+    hour: Unit {
+        one: "une heure",
+        many: "{} heures",
+    },
+    day: Unit {
+        one: "un jour",
+        many: "{} jours",
+    },
+};
+
+fn phrases(lang: Language) -> &'static Phrases {
+    match lang {
+        Language::English => &ENGLISH,
+        Language::German => &GERMAN,
+        Language::French => &FRENCH,
+    }
+}
+
+fn count(unit: &Unit, n: i64) -> String {
+    if n == 1 {
+        unit.one.to_owned()
+    } else {
+        unit.many.replace("{}", &n.to_string())
+    }
+}
+
+// Approximate a (positive) duration the way a person would say it
+fn humanize_duration(d: Duration, p: &Phrases) -> String {
+    let minutes = (d.num_seconds() + 30) / 60;
+    match minutes {
+        28..=32 => p.half_hour.to_owned(),
+        0..=54 => count(&p.minute, minutes.max(1)),
+        55..=89 => count(&p.hour, 1),
+        _ if d.num_hours() < 24 => count(&p.hour, (minutes as f64 / 60.0).round() as i64),
+        _ => count(&p.day, (d.num_hours() as f64 / 24.0).round() as i64),
+    }
+}
+
+// "in half an hour", "in a moment"
+pub fn humanize_until(d: Duration, lang: Language) -> String {
+    let p = phrases(lang);
+    if d < Duration::minutes(1) {
+        return p.moment.to_owned();
+    }
+    p.future.replace("{}", &humanize_duration(d, p))
+}
+
+// "10 minutes ago", "just started"
+pub fn humanize_since(d: Duration, lang: Language) -> String {
+    if d < Duration::minutes(1) {
+        return phrases(lang).just_started.to_owned();
+    }
+    humanize_ago(d, lang)
+}
+
+// "10 minutes ago", even for a moment ago
+pub fn humanize_ago(d: Duration, lang: Language) -> String {
+    let p = phrases(lang);
+    p.past.replace("{}", &humanize_duration(d, p))
+}
+
+// "in 30min", around an already formatted amount of time
+pub fn future(amount: &str, lang: Language) -> String {
+    phrases(lang).future.replace("{}", amount)
+}
+
+// "30min ago"
+pub fn past(amount: &str, lang: Language) -> String {
+    phrases(lang).past.replace("{}", amount)
+}
+
+// "due in 3h", `when` being how soon
+pub fn due(when: &str, lang: Language) -> String {
+    phrases(lang).due.replace("{}", when)
+}
+
+pub fn overdue(lang: Language) -> &'static str {
+    phrases(lang).overdue
+}
+
+// "until 15:00"
+pub fn until(time: &str, lang: Language) -> String {
+    phrases(lang).until.replace("{}", time)
+}
+
+// "4 today"
+pub fn today(count: usize, lang: Language) -> String {
+    phrases(lang).today.replace("{}", &count.to_string())
+}
+
+pub fn cancelled(lang: Language) -> &'static str {
+    phrases(lang).cancelled
+}
+
+pub fn all_day(lang: Language) -> &'static str {
+    phrases(lang).all_day
+}
+
+pub fn tomorrow(lang: Language) -> &'static str {
+    phrases(lang).tomorrow
+}
+
+pub fn yesterday(lang: Language) -> &'static str {
+    phrases(lang).yesterday
+}
+
+// "ends in 20 minutes"
+pub fn humanize_remaining(d: Duration, lang: Language) -> String {
+    let p = phrases(lang);
+    p.ends.replace("{}", &humanize_duration(d, p))
+}
@@ -0,0 +1,100 @@
+use crate::auth::Credentials;
+use crate::sources::{LoadOptions, SourceError};
+
+// A stored password, with the `user` attribute if the item has one
+pub struct Secret {
+    pub user: Option<String>,
+    pub password: String,
+}
+
+#[cfg(feature = "keyring")]
+mod secret_service {
+    use super::Secret;
+    use crate::sources::SourceError;
+    use std::collections::HashMap;
+    use zbus::{
+        blocking::{Connection, Proxy},
+        zvariant::{OwnedObjectPath, OwnedValue, Value},
+    };
+
+    // Passwords are looked up in the Secret Service (GNOME Keyring, KWallet, KeePassXC)
+    // by two attributes: `service`, always this, and `source`, the name given to the
+    // source. Store one with e.g. `secret-tool store --label='Work calendar' service
+    // polybar-agenda source work user me`
+    const SERVICE: &str = "polybar-agenda";
+    const BUS: &str = "org.freedesktop.secrets";
+    const PATH: &str = "/org/freedesktop/secrets";
+    const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+    const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+
+    fn error(e: impl ToString) -> SourceError {
+        SourceError::Service("keyring".to_owned(), e.to_string())
+    }
+
+    pub fn lookup(name: &str) -> Result<Option<Secret>, SourceError> {
+        let connection = Connection::session().map_err(error)?;
+        let service = Proxy::new(&connection, BUS, PATH, SERVICE_INTERFACE).map_err(error)?;
+        let attributes = HashMap::from([("service", SERVICE), ("source", name)]);
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+            service.call("SearchItems", &(attributes,)).map_err(error)?;
+        // Unlocking would show a prompt, which a bar module can't wait for
+        let Some(item) = unlocked.into_iter().next() else {
+            return if locked.is_empty() {
+                Ok(None)
+            } else {
+                Err(error("the keyring is locked"))
+            };
+        };
+
+        // With the plain algorithm the secret isn't encrypted, it only travels over the
+        // session bus
+        let (_, session): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &("plain", Value::from("")))
+            .map_err(error)?;
+        let item = Proxy::new(&connection, BUS, item, ITEM_INTERFACE).map_err(error)?;
+        let (_, _, value, _): (OwnedObjectPath, Vec<u8>, Vec<u8>, String) =
+            item.call("GetSecret", &(session,)).map_err(error)?;
+        let attributes: HashMap<String, String> = item.get_property("Attributes").map_err(error)?;
+        Ok(Some(Secret {
+            user: attributes.get("user").cloned(),
+            password: String::from_utf8(value).map_err(error)?,
+        }))
+    }
+}
+
+// The password stored for `name`, None if there is none
+fn lookup(name: &str) -> Result<Option<Secret>, SourceError> {
+    #[cfg(feature = "keyring")]
+    return secret_service::lookup(name);
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = name;
+        Err(SourceError::Service(
+            "keyring".to_owned(),
+            "not supported by this build, rebuild with `--features keyring`".to_owned(),
+        ))
+    }
+}
+
+// The credentials of a source whose password is kept in the keyring, None for sources
+// without a keyring entry or with a password of their own
+pub fn credentials(opts: &LoadOptions) -> Result<Option<Credentials>, SourceError> {
+    let Some(name) = &opts.keyring else {
+        return Ok(None);
+    };
+    let mut credentials = opts.credentials.clone().unwrap_or_default();
+    if !credentials.password.is_empty() {
+        return Ok(None);
+    }
+    let secret = lookup(name)?.ok_or_else(|| {
+        SourceError::Service(
+            "keyring".to_owned(),
+            format!("no password stored for {}", name),
+        )
+    })?;
+    credentials.password = secret.password;
+    if credentials.username.is_empty() {
+        credentials.username = secret.user.unwrap_or_default();
+    }
+    Ok(Some(credentials))
+}
@@ -0,0 +1,144 @@
+use crate::config::config_home;
+use crate::sources::SourceError;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+// A calendar from the `[calendars]` section of khal's configuration
+#[derive(Debug, PartialEq)]
+pub struct KhalCalendar {
+    pub path: String,
+    // None for `type = discover`, where each collection found has its own name
+    pub name: Option<String>,
+    // None for `color = auto`, where the collection's color file is used
+    pub color: Option<String>,
+}
+
+// khal's color names are those of the terminal's 16 colors
+fn named_color(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "black" => "#000000",
+        "dark red" => "#cd0000",
+        "dark green" => "#00cd00",
+        "brown" => "#cdcd00",
+        "dark blue" => "#0000ee",
+        "dark magenta" => "#cd00cd",
+        "dark cyan" => "#00cdcd",
+        "white" => "#ffffff",
+        "light gray" => "#e5e5e5",
+        "dark gray" => "#7f7f7f",
+        "light red" => "#ff0000",
+        "light green" => "#00ff00",
+        "yellow" => "#ffff00",
+        "light blue" => "#5c5cff",
+        "light magenta" => "#ff00ff",
+        "light cyan" => "#00ffff",
+        _ => return None,
+    })
+}
+
+// Colors are given by name, as #RRGGBB, or as an index into the 256 colors palette
+// (of which only the 16 named ones are understood)
+fn parse_color(value: &str) -> Option<String> {
+    if value.starts_with('#') {
+        return Some(value.to_owned());
+    }
+    if let Ok(index) = value.parse::<usize>() {
+        const NAMES: [&str; 16] = [
+            "black",
+            "dark red",
+            "dark green",
+            "brown",
+            "dark blue",
+            "dark magenta",
+            "dark cyan",
+            "light gray",
+            "dark gray",
+            "light red",
+            "light green",
+            "yellow",
+            "light blue",
+            "light magenta",
+            "light cyan",
+            "white",
+        ];
+        return NAMES
+            .get(index)
+            .and_then(|n| named_color(n))
+            .map(str::to_owned);
+    }
+    named_color(value).map(str::to_owned)
+}
+
+// The calendars configured in khal's configuration file (a ConfigObj style ini file
+// with `[[name]]` subsections), in the order they are listed
+pub fn parse_config(config: &str) -> Vec<KhalCalendar> {
+    #[derive(Default)]
+    struct Section {
+        name: String,
+        path: Option<String>,
+        color: Option<String>,
+        discover: bool,
+    }
+    let finish = |section: Section| {
+        Some(KhalCalendar {
+            path: section.path?,
+            name: (!section.discover).then_some(section.name),
+            color: section.color.as_deref().and_then(parse_color),
+        })
+    };
+
+    let mut calendars = Vec::new();
+    let mut in_calendars = false;
+    let mut current: Option<Section> = None;
+    for line in config.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            calendars.extend(current.take().and_then(finish));
+            let depth = line.len() - line.trim_start_matches('[').len();
+            let name = line.trim_matches(['[', ']']).trim();
+            match depth {
+                1 => in_calendars = name == "calendars",
+                2 if in_calendars => {
+                    current = Some(Section {
+                        name: name.to_owned(),
+                        ..Default::default()
+                    })
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let (Some(section), Some((key, value))) = (current.as_mut(), line.split_once('=')) else {
+            continue;
+        };
+        let value = value.trim().trim_matches(['"', '\'']);
+        match key.trim() {
+            "path" => section.path = Some(value.to_owned()),
+            "color" => section.color = Some(value.to_owned()),
+            "type" => section.discover = value == "discover",
+            _ => {}
+        }
+    }
+    calendars.extend(current.and_then(finish));
+    calendars
+}
+
+// khal looks for `$XDG_CONFIG_HOME/khal/config`, and `~/.khal/khal.conf` for
+// older setups
+pub fn config_path() -> Option<PathBuf> {
+    config_home()
+        .map(|dir| dir.join("khal").join("config"))
+        .into_iter()
+        .chain(env::var_os("HOME").map(|home| PathBuf::from(home).join(".khal").join("khal.conf")))
+        .find(|path| path.is_file())
+}
+
+// Read the calendars from khal's configuration file at `path`
+pub fn load(path: &Path) -> Result<Vec<KhalCalendar>, SourceError> {
+    let config = fs::read_to_string(path).map_err(|e| SourceError::Io(path.to_owned(), e))?;
+    Ok(parse_config(&config))
+}
@@ -0,0 +1,67 @@
+use crate::calendar::AgendaEntry;
+use chrono::NaiveDateTime;
+
+// Properties searched for a meeting link, most specific first. The vendor ones only
+// ever hold a conference link, the others are searched for known services
+pub const LINK_PROPERTIES: [&str; 6] = [
+    "X-GOOGLE-CONFERENCE",
+    "X-MICROSOFT-ONLINEMEETINGURL",
+    "X-MICROSOFT-SKYPETEAMSMEETINGURL",
+    "URL",
+    "LOCATION",
+    "DESCRIPTION",
+];
+
+const CONFERENCE_HOSTS: [&str; 11] = [
+    "zoom.us",
+    "zoom.com",
+    "meet.google.com",
+    "teams.microsoft.com",
+    "teams.live.com",
+    "webex.com",
+    "meet.jit.si",
+    "whereby.com",
+    "gotomeeting.com",
+    "meet.goto.com",
+    "bluejeans.com",
+];
+
+fn urls(text: &str) -> impl Iterator<Item = &str> {
+    // Descriptions are often still escaped (`\n`) or wrap links in <> or quotes
+    text.split(|c: char| c.is_whitespace() || "\\<>\"'()[]".contains(c))
+        .filter(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(|word| word.trim_end_matches(['.', ',', ';']))
+}
+
+fn is_conference_url(url: &str) -> bool {
+    let host = url
+        .split_once("://")
+        .map_or("", |(_, rest)| rest)
+        .split(['/', '?', '#', ':'])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    CONFERENCE_HOSTS
+        .iter()
+        .any(|h| host == *h || host.ends_with(&format!(".{}", h)))
+}
+
+// The first conference link found in the given (property, value) pairs
+pub fn conference_link<'a>(
+    properties: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Option<String> {
+    properties.into_iter().find_map(|(property, value)| {
+        let vendor = property.starts_with("X-");
+        urls(value)
+            .find(|url| vendor || is_conference_url(url))
+            .map(|url| url.to_owned())
+    })
+}
+
+// The ongoing or next entry with a conference link
+pub fn next_meeting(entries: &[AgendaEntry], now: NaiveDateTime) -> Option<&AgendaEntry> {
+    entries
+        .iter()
+        .filter(|e| e.link.is_some() && e.start + e.duration >= now)
+        .min_by_key(|e| e.start)
+}
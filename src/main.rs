@@ -1,13 +1,33 @@
-use std::{env, fs};
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process,
+    str::FromStr,
+    time::Duration as StdDuration,
+};
 
 mod calendar {
-    use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone};
+    use crate::i18n::{self, humanize_remaining, humanize_since, humanize_until, Language};
+    use crate::links::{conference_link, LINK_PROPERTIES};
+    use crate::marquee;
+    use crate::output::escape_markup;
+    use crate::template::EntryTemplate;
+    use crate::windows_zones;
+    use chrono::{
+        format::{DelayedFormat, Item, Numeric, Pad, StrftimeItems},
+        DateTime, Datelike, Duration, Local, Locale, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    };
     use chrono_tz::Tz;
-    use icalendar::{Calendar, CalendarComponent, Component, DatePerhapsTime};
+    use icalendar::{
+        Calendar, CalendarComponent, CalendarDateTime, Component, DatePerhapsTime, Todo, TodoStatus,
+    };
     use itertools::Itertools;
     use now::DateTimeNow;
     use rrule::{RRuleSet, Tz as RRuleTz};
+    use serde::{Deserialize, Serialize};
     use std::str::FromStr;
+    use unicode_segmentation::UnicodeSegmentation;
 
     // Custom error type for better error handling
     #[derive(Debug)]
@@ -19,15 +39,89 @@ mod calendar {
     }
 
     // Constants
-    const RRULE_PROPERTIES: [&str; 5] = ["DTSTART", "RRULE", "EXRULE", "RDATE", "EXDATE"];
+    pub const RRULE_PROPERTIES: [&str; 5] = ["DTSTART", "RRULE", "EXRULE", "RDATE", "EXDATE"];
     pub const MAX_EVENTS: u16 = 100;
     pub const HOURS_AHEAD: i64 = 32;
     pub const HOURS_BEHIND: i64 = 32;
+    // Windows are cut to ten years either way, far beyond any agenda but well within
+    // what dates can represent
+    pub const MAX_WINDOW_HOURS: i64 = 24 * 366 * 10;
+
+    // A number of hours given by the user, cut to the longest window
+    pub fn capped_hours(hours: u32) -> Duration {
+        Duration::hours(i64::from(hours).min(MAX_WINDOW_HOURS))
+    }
+
+    // A number of minutes given by the user, likewise
+    pub fn capped_minutes(minutes: u32) -> Duration {
+        Duration::minutes(i64::from(minutes).min(MAX_WINDOW_HOURS * 60))
+    }
+    // How long overdue todos keep being shown by default
+    pub const OVERDUE_HOURS: i64 = 24;
+    pub const OVERDUE_COLOR: &str = "#ff5555";
+    // Entries shown on the bar by default
+    pub const SHOWN_ENTRIES: usize = 2;
+    // After the title of an event with STATUS:TENTATIVE by default
+    pub const TENTATIVE_MARKER: &str = "?";
+
+    // The non-ASCII characters of the formatters, and the stand-ins `--ascii` uses for
+    // fonts that lack them
+    pub struct Glyphs {
+        // Between the parts of a compact entry
+        pub dot: &'static str,
+        // Between the entries on the bar
+        pub separator: &'static str,
+        // Between a start and end time
+        pub dash: &'static str,
+        pub ellipsis: &'static str,
+        // The done and remaining parts of a progress bar
+        pub filled: &'static str,
+        pub unfilled: &'static str,
+    }
 
+    const UNICODE_GLYPHS: Glyphs = Glyphs {
+        dot: "·",
+        separator: " » ",
+        dash: "–",
+        ellipsis: "…",
+        filled: "▰",
+        unfilled: "▱",
+    };
+    const ASCII_GLYPHS: Glyphs = Glyphs {
+        dot: "-",
+        separator: " | ",
+        dash: "-",
+        ellipsis: "...",
+        filled: "#",
+        unfilled: "-",
+    };
+
+    #[derive(Clone)]
     pub struct AgendaEntry {
         pub name: String,
         pub start: NaiveDateTime,
         pub duration: Duration,
+        // Given as dates only, rather than timed from one midnight to another
+        pub all_day: bool,
+        // Display name and color of the collection the entry was read from
+        pub calendar: Option<String>,
+        pub color: Option<String>,
+        // Deadline of a todo, shown as a countdown instead of a start time
+        pub due: Option<NaiveDateTime>,
+        // Video conference link, opened by the `join` subcommand
+        pub link: Option<String>,
+        pub location: Option<String>,
+        // CATEGORIES of the event, e.g. "standup"
+        pub categories: Vec<String>,
+        // DESCRIPTION, with its escapes undone
+        pub description: Option<String>,
+        // Name or address of the ORGANIZER, and the number of ATTENDEEs
+        pub organizer: Option<String>,
+        pub attendees: usize,
+        pub status: Status,
+        // Tag and icon of the source the entry was read from
+        pub label: Option<String>,
+        pub icon: Option<String>,
     }
 
     impl AgendaEntry {
@@ -36,207 +130,2407 @@ mod calendar {
                 name,
                 start,
                 duration,
+                all_day: false,
+                calendar: None,
+                color: None,
+                due: None,
+                link: None,
+                location: None,
+                categories: Vec::new(),
+                description: None,
+                organizer: None,
+                attendees: 0,
+                status: Status::default(),
+                label: None,
+                icon: None,
             }
         }
+
+        pub fn is_overdue(&self, when: NaiveDateTime) -> bool {
+            self.due.is_some_and(|due| due <= when)
+        }
     }
 
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Default)]
     pub enum DisplayMode {
+        #[default]
         Default,
         Compact,
     }
 
-    // Convert CalendarDateTime to NaiveDateTime
-    pub fn as_naive(dt: icalendar::CalendarDateTime) -> Result<NaiveDateTime, CalendarError> {
+    impl FromStr for DisplayMode {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "default" => Ok(DisplayMode::Default),
+                "compact" => Ok(DisplayMode::Compact),
+                _ => Err(format!("Unknown display mode: {}", s)),
+            }
+        }
+    }
+
+    // Look up a TZID, also accepting the Windows zone names Outlook uses
+    pub fn resolve_timezone(tzid: &str) -> Option<Tz> {
+        // Globally unique TZIDs such as "/freeassociation.sourceforge.net/America/New_York"
+        // carry a vendor prefix in front of the IANA name, see
+        // https://github.com/ahfriedman/polybar-agenda/pull/2#issuecomment-2408742959
+        if let Some(unique) = tzid.strip_prefix('/') {
+            return std::iter::once(unique)
+                .chain(unique.match_indices('/').map(|(i, _)| &unique[i + 1..]))
+                .find_map(resolve_timezone);
+        }
+
+        Tz::from_str(tzid)
+            .ok()
+            .or_else(|| windows_zones::to_iana(tzid).and_then(|iana| Tz::from_str(iana).ok()))
+    }
+
+    // Convert CalendarDateTime to NaiveDateTime. Floating times are taken to be in
+    // `default_tz` when it's given, local time otherwise
+    pub fn as_naive(
+        dt: icalendar::CalendarDateTime,
+        default_tz: Option<Tz>,
+    ) -> Result<NaiveDateTime, CalendarError> {
         match dt {
-            icalendar::CalendarDateTime::Floating(f) => Ok(f),
+            icalendar::CalendarDateTime::Floating(f) => match default_tz {
+                Some(tz) => tz
+                    .from_local_datetime(&f)
+                    .earliest()
+                    .map(|dt| dt.with_timezone(&Local).naive_local())
+                    .ok_or_else(|| CalendarError::InvalidTimezone(tz.name().to_owned())),
+                None => Ok(f),
+            },
             icalendar::CalendarDateTime::Utc(u) => {
                 Ok(Local.from_utc_datetime(&u.naive_utc()).naive_local())
             }
             icalendar::CalendarDateTime::WithTimezone { date_time, tzid } => {
-                // See https://github.com/ahfriedman/polybar-agenda/pull/2#issuecomment-2408742959
-                let fixed_zone = if tzid.starts_with("/freeassociation.sourceforge.net/") {tzid[33..].to_string()} else {tzid.clone()};
-                let tz = Tz::from_str(&fixed_zone)
-                    .map_err(|_| CalendarError::InvalidTimezone(tzid.clone()))?;
+                let tz = resolve_timezone(&tzid)
+                    .ok_or_else(|| CalendarError::InvalidTimezone(tzid.clone()))?;
                 tz.from_local_datetime(&date_time)
                     .single()
-                    .ok_or_else(|| CalendarError::InvalidTimezone(tzid))
+                    .ok_or(CalendarError::InvalidTimezone(tzid))
                     .map(|dt| dt.naive_local())
             }
         }
     }
 
-    // Extract events from a calendar component
-    pub fn extract_event(
-        event: &impl Component,
-        sod: DateTime<Local>,
-        eod: DateTime<Local>,
-    ) -> Result<Vec<AgendaEntry>, CalendarError> {
-        let start = event.get_start().ok_or(CalendarError::MissingStartTime)?;
-        let naive_start = match start {
-            DatePerhapsTime::DateTime(dt) => as_naive(dt)?,
-            DatePerhapsTime::Date(d) => Local
-                .from_local_date(&d)
-                .unwrap()
-                .and_hms_opt(0, 0, 0)
-                .unwrap()
-                .naive_local(),
-        };
-
-        let duration = match event.get_end() {
-            Some(end_time) => match end_time {
-                DatePerhapsTime::DateTime(et) => as_naive(et)? - naive_start,
-                DatePerhapsTime::Date(_) => Local::now().end_of_day().naive_local() - naive_start,
-            },
-            None => return Err(CalendarError::MissingEndTime),
+    // An event normalized from its calendar component. It doesn't depend on the
+    // extraction window, so it can be cached between runs
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct EventRecord {
+        pub name: String,
+        pub start: NaiveDateTime,
+        pub end: NaiveDateTime,
+        pub all_day: bool,
+        // DTSTART/RRULE/EXRULE/RDATE/EXDATE lines of recurring events
+        pub recurrence: Option<String>,
+        // Upper bound for the end of the last occurrence of a recurring event, None
+        // if it recurs forever. Lets expansion be skipped for events that are over
+        pub last_end: Option<NaiveDateTime>,
+        pub due: Option<NaiveDateTime>,
+        pub link: Option<String>,
+        pub location: Option<String>,
+        pub categories: Vec<String>,
+        pub description: Option<String>,
+        pub organizer: Option<String>,
+        pub attendees: usize,
+        pub status: Status,
+    }
+
+    // STATUS of an event, confirmed unless it says otherwise
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+    pub enum Status {
+        #[default]
+        Confirmed,
+        Tentative,
+        Cancelled,
+    }
+
+    impl Status {
+        // Values other than TENTATIVE and CANCELLED, like those of todos, count as confirmed
+        pub fn parse(value: &str) -> Self {
+            match value.trim().to_ascii_uppercase().as_str() {
+                "TENTATIVE" => Status::Tentative,
+                "CANCELLED" => Status::Cancelled,
+                _ => Status::Confirmed,
+            }
+        }
+    }
+
+    // The comma-separated values of a CATEGORIES property
+    pub fn split_categories(value: &str) -> Vec<String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|category| !category.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    // A TEXT value with its backslash escapes undone, `\n` becoming a line break
+    pub fn unescape_text(value: &str) -> String {
+        let mut text = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some('n' | 'N')) => {
+                    chars.next();
+                    text.push('\n');
+                }
+                ('\\', Some(escaped @ (',' | ';' | '\\'))) => {
+                    chars.next();
+                    text.push(escaped);
+                }
+                (c, _) => text.push(c),
+            }
+        }
+        text
+    }
+
+    // Who an ORGANIZER or ATTENDEE is: their common name, or else their address
+    pub fn person(address: &str, common_name: Option<&str>) -> String {
+        match common_name.map(str::trim).filter(|name| !name.is_empty()) {
+            Some(name) => name.to_owned(),
+            None => {
+                let address = address.trim();
+                match address.get(..7) {
+                    Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => {
+                        address[7..].to_owned()
+                    }
+                    _ => address.to_owned(),
+                }
+            }
+        }
+    }
+
+    fn component_organizer(component: &impl Component) -> Option<String> {
+        let organizer = component.properties().get("ORGANIZER")?;
+        let common_name = organizer.params().get("CN").map(|cn| cn.value());
+        Some(person(organizer.value(), common_name))
+    }
+
+    fn component_attendees(component: &impl Component) -> usize {
+        component
+            .multi_properties()
+            .get("ATTENDEE")
+            .map_or(0, Vec::len)
+    }
+
+    // Seconds a single FREQ step can take at most
+    fn frequency_seconds(freq: &str) -> Option<i64> {
+        match freq {
+            "SECONDLY" => Some(1),
+            "MINUTELY" => Some(60),
+            "HOURLY" => Some(3600),
+            "DAILY" => Some(86400),
+            "WEEKLY" => Some(7 * 86400),
+            "MONTHLY" => Some(31 * 86400),
+            "YEARLY" => Some(366 * 86400),
+            _ => None,
+        }
+    }
+
+    // A cheap upper bound for the end of the last occurrence, worked out from UNTIL or
+    // COUNT without expanding the rule. None when the event may recur indefinitely
+    pub fn recurrence_end(
+        recurrence: &str,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Option<NaiveDateTime> {
+        let mut last_start = start;
+        for line in recurrence.lines() {
+            let (name, value) = line.split_once(':')?;
+            match name.split(';').next()? {
+                "RRULE" => {}
+                // Extra dates may fall anywhere
+                "RDATE" => return None,
+                _ => continue,
+            }
+
+            let parts: Vec<(&str, &str)> =
+                value.split(';').filter_map(|p| p.split_once('=')).collect();
+            let part = |key: &str| parts.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+
+            let bound = if let Some(until) = part("UNTIL") {
+                let date = until.get(..8)?;
+                // A day of slack covers whatever zone UNTIL is given in
+                NaiveDate::parse_from_str(date, "%Y%m%d")
+                    .ok()?
+                    .and_time(NaiveTime::MIN)
+                    + Duration::days(2)
+            } else {
+                // BY* rules may skip periods, so only plain COUNT rules can be bounded
+                if parts.iter().any(|(k, _)| k.starts_with("BY")) {
+                    return None;
+                }
+                let freq = part("FREQ")?;
+                // So do months without the 31st and years without Feb 29, when the
+                // event starts on such a day
+                let skips = match freq {
+                    "MONTHLY" => start.day() > 28,
+                    "YEARLY" => start.month() == 2 && start.day() == 29,
+                    _ => false,
+                };
+                if skips {
+                    return None;
+                }
+                let count: i64 = part("COUNT")?.parse().ok()?;
+                let interval: i64 = part("INTERVAL").map_or(Some(1), |i| i.parse().ok())?;
+                let step = frequency_seconds(freq)?;
+                let seconds = step.checked_mul(interval)?.checked_mul(count)?;
+                start.checked_add_signed(Duration::try_seconds(seconds)?)?
+            };
+            last_start = last_start.max(bound);
+        }
+        last_start.checked_add_signed(end - start)
+    }
+
+    // The conference link found in the properties of a component
+    fn component_link(component: &impl Component) -> Option<String> {
+        conference_link(
+            LINK_PROPERTIES
+                .iter()
+                .filter_map(|&p| component.property_value(p).map(|value| (p, value))),
+        )
+    }
+
+    impl EventRecord {
+        // `default_tz` applies to floating times, for feeds that leave out the TZID
+        pub fn from_component(
+            event: &impl Component,
+            default_tz: Option<Tz>,
+        ) -> Result<Self, CalendarError> {
+            let start = event.get_start().ok_or(CalendarError::MissingStartTime)?;
+            let all_day = matches!(start, DatePerhapsTime::Date(_));
+            // The recurrence is expanded separately and needs to know the zone of DTSTART
+            let start_zone = match &start {
+                DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { tzid, .. }) => {
+                    resolve_timezone(tzid)
+                }
+                DatePerhapsTime::DateTime(CalendarDateTime::Floating(_)) => default_tz,
+                _ => None,
+            };
+            let naive_start = match start {
+                DatePerhapsTime::DateTime(dt) => as_naive(dt, default_tz)?,
+                DatePerhapsTime::Date(d) => d.and_time(NaiveTime::MIN),
+            };
+
+            // Per RFC 5545 a date-valued DTEND is exclusive: an event from 2024-05-01 to
+            // 2024-05-02 lasts one day, and a date-only event without DTEND lasts one day too
+            let naive_end = match event.get_end() {
+                Some(DatePerhapsTime::DateTime(et)) => as_naive(et, default_tz)?,
+                Some(DatePerhapsTime::Date(d)) => d.and_time(NaiveTime::MIN),
+                None if all_day => naive_start + Duration::days(1),
+                None => return Err(CalendarError::MissingEndTime),
+            };
+
+            let recurrence = event.property_value("RRULE").map(|_| {
+                RRULE_PROPERTIES
+                    .iter()
+                    .filter_map(|&p| {
+                        let value = event.property_value(p)?;
+                        Some(match (p, start_zone) {
+                            ("DTSTART", Some(tz)) => {
+                                format!("DTSTART;TZID={}:{}\n", tz.name(), value)
+                            }
+                            _ => format!("{}:{}\n", p, value),
+                        })
+                    })
+                    .collect()
+            });
+
+            Ok(Self {
+                name: event.get_summary().unwrap_or("").to_owned(),
+                start: naive_start,
+                end: naive_end,
+                all_day,
+                last_end: recurrence
+                    .as_deref()
+                    .and_then(|r| recurrence_end(r, naive_start, naive_end)),
+                recurrence,
+                due: None,
+                link: component_link(event),
+                location: event.property_value("LOCATION").map(unescape_text),
+                categories: event
+                    .property_value("CATEGORIES")
+                    .map(split_categories)
+                    .unwrap_or_default(),
+                description: event.property_value("DESCRIPTION").map(unescape_text),
+                organizer: component_organizer(event),
+                attendees: component_attendees(event),
+                status: event
+                    .property_value("STATUS")
+                    .map(Status::parse)
+                    .unwrap_or_default(),
+            })
+        }
+
+        // Todos with a DUE are shown at their deadline, others are treated like events.
+        // A date-only DUE means by the end of that day
+        pub fn from_todo(todo: &Todo, default_tz: Option<Tz>) -> Result<Self, CalendarError> {
+            let due = match todo.get_due() {
+                Some(DatePerhapsTime::DateTime(dt)) => as_naive(dt, default_tz)?,
+                Some(DatePerhapsTime::Date(d)) => (d + Duration::days(1)).and_time(NaiveTime::MIN),
+                None => return Self::from_component(todo, default_tz),
+            };
+
+            Ok(Self {
+                name: todo.get_summary().unwrap_or("").to_owned(),
+                start: due,
+                end: due,
+                all_day: false,
+                recurrence: None,
+                last_end: None,
+                due: Some(due),
+                link: component_link(todo),
+                location: todo.property_value("LOCATION").map(unescape_text),
+                categories: todo
+                    .property_value("CATEGORIES")
+                    .map(split_categories)
+                    .unwrap_or_default(),
+                description: todo.property_value("DESCRIPTION").map(unescape_text),
+                organizer: component_organizer(todo),
+                attendees: component_attendees(todo),
+                // Cancelled todos are skipped before they get here
+                status: Status::Confirmed,
+            })
+        }
+
+        fn entry(&self, start: NaiveDateTime) -> AgendaEntry {
+            let mut entry = AgendaEntry::new(self.name.clone(), start, self.end - self.start);
+            entry.all_day = self.all_day;
+            entry.due = self.due;
+            entry.link = self.link.clone();
+            entry.location = self.location.clone();
+            entry.categories = self.categories.clone();
+            entry.description = self.description.clone();
+            entry.organizer = self.organizer.clone();
+            entry.attendees = self.attendees;
+            entry.status = self.status;
+            entry
+        }
+
+        // Occurrences of the event, recurring ones limited to those between sod and eod
+        pub fn entries(
+            &self,
+            sod: DateTime<Local>,
+            eod: DateTime<Local>,
+        ) -> Result<Vec<AgendaEntry>, CalendarError> {
+            let Some(recurrence) = &self.recurrence else {
+                return Ok(vec![self.entry(self.start)]);
+            };
+            if self.start > eod.naive_local()
+                || self.last_end.is_some_and(|end| end < sod.naive_local())
+            {
+                return Ok(Vec::new());
+            }
+
+            let rrule = recurrence
+                .parse::<RRuleSet>()
+                .map_err(|e| CalendarError::RRuleParseError(e.to_string()))?;
+
+            Ok(rrule
+                .after(sod.with_timezone(&RRuleTz::UTC))
+                .before(eod.with_timezone(&RRuleTz::UTC))
+                .all(MAX_EVENTS)
+                .dates
+                .into_iter()
+                .map(|a| self.entry(Local.from_utc_datetime(&a.naive_utc()).naive_local()))
+                .collect())
+        }
+    }
+
+    #[derive(Clone, Copy, Default)]
+    pub enum UnitStyle {
+        // 45s, 30m, 1.5h
+        Short,
+        // 45s, 30min, 1.5h
+        #[default]
+        Default,
+        // 45sec, 30min, 1.5hr
+        Medium,
+        // 45 seconds, 30 minutes, 1.5 hours
+        Long,
+    }
+
+    impl FromStr for UnitStyle {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "short" => Ok(UnitStyle::Short),
+                "default" => Ok(UnitStyle::Default),
+                "medium" => Ok(UnitStyle::Medium),
+                "long" => Ok(UnitStyle::Long),
+                _ => Err(format!("Unknown unit style: {}", s)),
+            }
+        }
+    }
+
+    impl UnitStyle {
+        // Labels for seconds, minutes and hours
+        fn labels(self) -> [&'static str; 3] {
+            match self {
+                UnitStyle::Short => ["s", "m", "h"],
+                UnitStyle::Default => ["s", "min", "h"],
+                UnitStyle::Medium => ["sec", "min", "hr"],
+                UnitStyle::Long => [" seconds", " minutes", " hours"],
+            }
+        }
+
+        fn quantity(self, value: f32, unit: usize) -> String {
+            let label = self.labels()[unit];
+            match self {
+                UnitStyle::Long if value == 1.0 => {
+                    format!("{}{}", value, label.trim_end_matches('s'))
+                }
+                _ => format!("{}{}", value, label),
+            }
+        }
+    }
+
+    // How durations of an hour or more are written
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub enum DurationStyle {
+        // 1.5h, rounded up to the next quarter of an hour
+        #[default]
+        Decimal,
+        // 1h 30min, to the minute
+        Composite,
+    }
+
+    impl FromStr for DurationStyle {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "decimal" => Ok(DurationStyle::Decimal),
+                "composite" => Ok(DurationStyle::Composite),
+                _ => Err(format!("Unknown duration style: {}", s)),
+            }
+        }
+    }
+
+    // How finely countdowns below an hour are shown
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub enum Rounding {
+        // 4min 30s
+        Exact,
+        // 4min, with seconds only in the last minute
+        #[default]
+        Minutes,
+        // To the nearest 5 minutes, and whole minutes once less than that is left
+        FiveMinutes,
+    }
+
+    impl FromStr for Rounding {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "exact" => Ok(Rounding::Exact),
+                "minutes" => Ok(Rounding::Minutes),
+                "five-minutes" => Ok(Rounding::FiveMinutes),
+                _ => Err(format!("Unknown rounding: {}", s)),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Default)]
+    pub struct DurationFormat {
+        pub units: UnitStyle,
+        pub style: DurationStyle,
+        pub rounding: Rounding,
+    }
+
+    // What to print when no events are upcoming
+    #[derive(Clone, Default)]
+    pub enum EmptyOutput {
+        // An empty line, which makes most bar modules disappear
+        #[default]
+        Blank,
+        Text(String),
+        // No output at all, not even a newline
+        Nothing,
+    }
+
+    // How entries are colored
+    #[derive(Clone, Copy, Default, PartialEq)]
+    pub enum Markup {
+        // polybar's `%{F#...}` tags
+        #[default]
+        Polybar,
+        // No colors, for outputs that style the line as a whole
+        Plain,
+        // lemonbar's color tags, which polybar's are modeled on, and click regions
+        // opening the entries' conference links
+        Lemonbar,
+        // tmux's `#[fg=...]` styles
+        Tmux,
+        // Pango's `<span>` elements, read by GTK based bars and launchers
+        Pango,
+    }
+
+    // How times of day are written
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub enum Clock {
+        // 14:30
+        #[default]
+        TwentyFourHour,
+        // 2:30 PM
+        TwelveHour,
+        // The way of the locale, e.g. 14.30 in Finnish
+        Locale(Locale),
+    }
+
+    impl Clock {
+        pub fn format(self, time: NaiveTime) -> String {
+            match self {
+                Clock::TwentyFourHour => time.format("%H:%M").to_string(),
+                Clock::TwelveHour => time.format("%-I:%M %p").to_string(),
+                Clock::Locale(locale) => {
+                    // The locale's time format, without the seconds and the separator
+                    // before them. Hours aren't padded, as in "2:30 PM"
+                    let mut items = Vec::new();
+                    for item in StrftimeItems::new_with_locale("%X", locale) {
+                        match item {
+                            Item::Numeric(Numeric::Second, _) => {
+                                if let Some(Item::Literal(_)) = items.last() {
+                                    items.pop();
+                                }
+                            }
+                            Item::Numeric(Numeric::Hour12, _) => {
+                                items.push(Item::Numeric(Numeric::Hour12, Pad::None))
+                            }
+                            item => items.push(item),
+                        }
+                    }
+                    DelayedFormat::new_with_locale(None, Some(time), items.iter(), locale)
+                        .to_string()
+                }
+            }
+        }
+    }
+
+    // How entries say which day they are on when it isn't today
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub enum DayLabel {
+        // Only the time, like today's entries
+        #[default]
+        None,
+        // "tomorrow", "yesterday", the weekday within the week and the date beyond
+        Relative,
+        Weekday,
+        Date,
+    }
+
+    impl FromStr for DayLabel {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "none" => Ok(DayLabel::None),
+                "relative" => Ok(DayLabel::Relative),
+                "weekday" => Ok(DayLabel::Weekday),
+                "date" => Ok(DayLabel::Date),
+                _ => Err(format!("Unknown day label: {}", s)),
+            }
+        }
+    }
+
+    impl DayLabel {
+        // The label of `day` as seen on `today`, None for today itself
+        pub fn label(
+            self,
+            day: NaiveDate,
+            today: NaiveDate,
+            opts: &DisplayOptions,
+        ) -> Option<String> {
+            let days = (day - today).num_days();
+            if days == 0 {
+                return None;
+            }
+            let locale = opts.locale.unwrap_or(Locale::POSIX);
+            let weekday = || day.format_localized("%a", locale).to_string();
+            let date = || day.format_localized("%-d %b", locale).to_string();
+            match self {
+                DayLabel::None => None,
+                DayLabel::Relative if days == 1 => Some(i18n::tomorrow(opts.language).to_owned()),
+                DayLabel::Relative if days == -1 => Some(i18n::yesterday(opts.language).to_owned()),
+                DayLabel::Relative if (2..7).contains(&days) => Some(weekday()),
+                DayLabel::Relative | DayLabel::Date => Some(date()),
+                DayLabel::Weekday => Some(weekday()),
+            }
+        }
+    }
+
+    // The start time of an entry, after the day if it isn't today and `label` asks for it,
+    // and followed by the end time if that is shown
+    fn start_time(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+        label: DayLabel,
+    ) -> String {
+        let mut time = opts.clock.format(entry.start.time());
+        if opts.show_end && entry.duration > Duration::zero() {
+            let end = entry.start + entry.duration;
+            let dash = opts.glyphs().dash;
+            time = format!("{}{}{}", time, dash, opts.clock.format(end.time()));
+        }
+        match label.label(entry.start.date(), when.date(), opts) {
+            Some(day) => format!("{} {}", day, time),
+            None => time,
+        }
+    }
+
+    // "until 15:00" for an ongoing entry, when end times are shown
+    fn ongoing_until(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> Option<String> {
+        let end = entry.start + entry.duration;
+        (opts.show_end && entry.start <= when && when < end)
+            .then(|| i18n::until(&opts.clock.format(end.time()), opts.language))
+    }
+
+    // "2:47" to an entry starting within the countdown threshold
+    fn live_countdown(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> Option<String> {
+        let left = entry.start - when;
+        let below = opts.countdown_below?;
+        (entry.due.is_none() && left > Duration::zero() && left <= below)
+            .then(|| format!("{}:{:02}", left.num_minutes(), left.num_seconds() % 60))
+    }
+
+    // "12min" until the entry starts, by the second once within the countdown threshold
+    fn countdown(opts: &DisplayOptions, entry: &AgendaEntry, when: NaiveDateTime) -> String {
+        let left = entry.start - when;
+        live_countdown(opts, entry, when).unwrap_or_else(|| {
+            if opts.humanize {
+                humanize_until(left, opts.language)
+            } else {
+                format_duration(left, &opts.durations)
+            }
+        })
+    }
+
+    // "Standup · tomorrow" in compact mode, which has no start time to put the day before
+    fn compact_name(opts: &DisplayOptions, entry: &AgendaEntry, when: NaiveDateTime) -> String {
+        let label = opts.compact_day_label;
+        match label.label(entry.start.date(), when.date(), opts) {
+            Some(day) => format!("{} {} {}", entry.name, opts.glyphs().dot, day),
+            None => entry.name.clone(),
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct DisplayOptions {
+        pub mode: DisplayMode,
+        // Prefix entries with the name of the collection they came from
+        pub show_calendar: bool,
+        // "Standup @ B2.14", the entry's LOCATION after its title
+        pub show_location: bool,
+        // Append the first line of the entry's DESCRIPTION, cut to the width if set
+        pub show_description: bool,
+        pub description_width: Option<usize>,
+        pub humanize: bool,
+        pub language: Language,
+        pub clock: Clock,
+        // For the names of days, None for English
+        pub locale: Option<Locale>,
+        // Of entries on other days, in the default and compact modes
+        pub day_label: DayLabel,
+        pub compact_day_label: DayLabel,
+        // "until 15:00" for ongoing entries, and the end after the start of others
+        pub show_end: bool,
+        // Count down to the second ("in 2:47") once an entry starts within this
+        pub countdown_below: Option<Duration>,
+        pub durations: DurationFormat,
+        pub window: WindowMode,
+        // Entries shown on the bar, SHOWN_ENTRIES if unset
+        pub limit: Option<usize>,
+        // Template for the "+N more" counter of today's events that didn't fit,
+        // `{}` is replaced with the number of hidden events
+        pub more_format: Option<String>,
+        // Append the number of events left today, "(4 today)" or this with `{count}`
+        // replaced by the number
+        pub show_count: bool,
+        pub count_format: Option<String>,
+        // Print the soonest entry alone, without the counters
+        pub only_next: bool,
+        // Print just the time until the next event
+        pub countdown_only: bool,
+        // Print up to this many entries on lines of their own instead of the bar's line
+        pub lines: Option<usize>,
+        // Above those lines, with `{count}` replaced by the events left today
+        pub header: Option<String>,
+        pub empty: EmptyOutput,
+        // Color of the empty text, as #RRGGBB[AA]
+        pub empty_color: Option<String>,
+        // How long todos stay on the bar after their deadline, OVERDUE_HOURS if unset
+        pub overdue_for: Option<Duration>,
+        // Between the entries on the bar, the glyphs' separator if unset
+        pub separator: Option<String>,
+        // Events whose name contains any of these (ignoring case) are hidden
+        pub exclude: Vec<String>,
+        // If any, only entries with one of these CATEGORIES are shown, and never those
+        // with one of the excluded ones. Both ignore case
+        pub categories: Vec<String>,
+        pub exclude_categories: Vec<String>,
+        // Hide events lasting whole days, like birthdays and holidays
+        pub skip_all_day: bool,
+        // Shown in place of the time of those, "All day" in the language if unset
+        pub all_day_label: Option<String>,
+        // Keep events with STATUS:CANCELLED, marked as such, rather than hiding them
+        pub show_cancelled: bool,
+        // After the title of tentative events, TENTATIVE_MARKER if unset
+        pub tentative_marker: Option<String>,
+        pub markup: Markup,
+        // Colors of entries about to start, by how soon
+        pub thresholds: Vec<Threshold>,
+        // Signal the bar while an event starts within this, see is_urgent
+        pub urgent_within: Option<Duration>,
+        // Plain ASCII in place of the glyphs
+        pub ascii: bool,
+        // Show how far through an ongoing event is as a bar this many characters wide,
+        // in place of the compact mode's elapsed and remaining times
+        pub progress_bar: Option<usize>,
+        // Of the bar, the glyphs' if unset
+        pub progress_chars: Option<ProgressChars>,
+        // Characters the line may take up, formatting aside
+        pub max_width: Option<usize>,
+        // Characters each entry's title may take up
+        pub title_width: Option<usize>,
+        // Scroll longer titles through the width rather than cutting them, by this
+        // run's step
+        pub scroll_step: Option<usize>,
+        pub line_tags: LineTags,
+        pub icons: Icons,
+        // Replaces the display mode's layout of each entry
+        pub format: Option<EntryTemplate>,
+        // Replaces it for events that have started
+        pub ongoing_format: Option<EntryTemplate>,
+    }
+
+    // Icons for the entries of a category or a calendar, in place of their source's
+    #[derive(Clone, Default)]
+    pub struct Icons {
+        // By CATEGORIES value, ignoring case
+        pub categories: Vec<(String, String)>,
+        // By the name of the calendar
+        pub calendars: Vec<(String, String)>,
+    }
+
+    impl Icons {
+        // The icon of the entry's first category that has one, or else of its calendar
+        pub fn apply(&self, entry: &mut AgendaEntry) {
+            let category = entry.categories.iter().find_map(|category| {
+                self.categories
+                    .iter()
+                    .find(|(name, _)| name.to_lowercase() == category.to_lowercase())
+            });
+            let calendar = self
+                .calendars
+                .iter()
+                .find(|(name, _)| entry.calendar.as_ref() == Some(name));
+            if let Some((_, icon)) = category.or(calendar) {
+                entry.icon = Some(icon.clone());
+            }
+        }
+    }
+
+    // The done and remaining characters of a progress bar, given together like `▰▱`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ProgressChars {
+        pub filled: String,
+        pub unfilled: String,
+    }
+
+    impl FromStr for ProgressChars {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.graphemes(true).collect::<Vec<_>>()[..] {
+                [filled, unfilled] => Ok(ProgressChars {
+                    filled: filled.to_owned(),
+                    unfilled: unfilled.to_owned(),
+                }),
+                _ => Err(format!("Expected two characters, got {}", s)),
+            }
+        }
+    }
+
+    // How far through the entry `when` is, between 0 and 1
+    pub fn elapsed_fraction(entry: &AgendaEntry, when: NaiveDateTime) -> f64 {
+        let total = entry.duration.num_seconds();
+        if total <= 0 {
+            return if when < entry.start { 0.0 } else { 1.0 };
+        }
+        let elapsed = (when - entry.start).num_seconds() as f64 / total as f64;
+        elapsed.clamp(0.0, 1.0)
+    }
+
+    // "▰▰▰▱▱" three fifths into an entry
+    fn progress_bar(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+        width: usize,
+    ) -> String {
+        let glyphs = opts.glyphs();
+        let (filled, unfilled) = match &opts.progress_chars {
+            Some(chars) => (chars.filled.as_str(), chars.unfilled.as_str()),
+            None => (glyphs.filled, glyphs.unfilled),
         };
+        let done = (elapsed_fraction(entry, when) * width as f64).round() as usize;
+        filled.repeat(done) + &unfilled.repeat(width - done)
+    }
+
+    // The color an entry takes once it starts `within` from now, given as
+    // "MINUTES:COLOR" like `15:#f9f06b`
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Threshold {
+        pub within: Duration,
+        pub color: String,
+    }
 
-        let name = event.get_summary().unwrap_or("").to_owned();
+    impl FromStr for Threshold {
+        type Err = String;
 
-        if event.property_value("RRULE").is_none() {
-            return Ok(vec![AgendaEntry::new(name, naive_start, duration)]);
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (minutes, color) = s
+                .split_once(':')
+                .ok_or_else(|| format!("Expected MINUTES:COLOR, got {}", s))?;
+            let minutes: u32 = minutes
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid number of minutes: {}", minutes))?;
+            rgb_color(color).ok_or_else(|| format!("Invalid color: {}", color))?;
+            Ok(Threshold {
+                within: capped_minutes(minutes),
+                color: color.trim().to_owned(),
+            })
         }
+    }
 
-        let props: String = RRULE_PROPERTIES
+    // The color of the tightest threshold an upcoming entry is within
+    pub fn threshold_color<'a>(
+        opts: &'a DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> Option<&'a str> {
+        let left = entry.start - when;
+        opts.thresholds
             .iter()
-            .filter_map(|&p| event.property_value(p).map(|x| format!("{}:{}\n", p, x)))
-            .collect();
-
-        let rrule = props
-            .parse::<RRuleSet>()
-            .map_err(|e| CalendarError::RRuleParseError(e.to_string()))?;
-
-        Ok(rrule
-            .after(sod.with_timezone(&RRuleTz::UTC))
-            .before(eod.with_timezone(&RRuleTz::UTC))
-            .all(MAX_EVENTS)
-            .dates
-            .into_iter()
-            .map(|a| {
-                AgendaEntry::new(
-                    name.clone(),
-                    Local.from_utc_datetime(&a.naive_utc()).naive_local(),
-                    duration,
-                )
-            })
-            .collect())
+            .filter(|threshold| left > Duration::zero() && left <= threshold.within)
+            .min_by_key(|threshold| threshold.within)
+            .map(|threshold| threshold.color.as_str())
+    }
+
+    // Red once overdue, else the color of an approaching threshold or of the collection
+    pub fn entry_color<'a>(
+        opts: &'a DisplayOptions,
+        entry: &'a AgendaEntry,
+        when: NaiveDateTime,
+    ) -> Option<&'a str> {
+        if entry.is_overdue(when) {
+            return Some(OVERDUE_COLOR);
+        }
+        threshold_color(opts, entry, when).or(entry.color.as_deref())
+    }
+
+    // Whether an event, rather than a todo, starts within `within`
+    pub fn is_urgent(entries: &[AgendaEntry], within: Duration, when: NaiveDateTime) -> bool {
+        entries
+            .iter()
+            .any(|entry| entry.due.is_none() && entry.start > when && entry.start - when <= within)
+    }
+
+    // polybar tags wrapping the whole line, left out when nothing is upcoming so that
+    // the module still disappears
+    #[derive(Clone, Default)]
+    pub struct LineTags {
+        // Colors in polybar's format, #[AA]RRGGBB
+        pub foreground: Option<String>,
+        pub underline: Option<String>,
+        // Of urgent lines, which are underlined even without an underline color
+        pub urgent_underline: Option<String>,
+        // Commands by polybar's mouse button number: 1-3 for the left, middle and right
+        // buttons, 4 and 5 for scrolling up and down
+        pub actions: Vec<(u8, String)>,
     }
 
-    pub fn format_duration(d: Duration) -> String {
+    impl LineTags {
+        pub fn wrap(&self, line: String, urgent: bool) -> String {
+            let mut line = line;
+            if let Some(color) = &self.foreground {
+                // Entries colored by their calendar reset the color when they end, which
+                // has to go back to the line's rather than the bar's
+                let color = format!("%{{F{}}}", color);
+                line = format!("{}{}%{{F-}}", color, line.replace("%{F-}", &color));
+            }
+            let underline = if urgent {
+                self.urgent_underline.as_ref().or(self.underline.as_ref())
+            } else {
+                self.underline.as_ref()
+            };
+            match (underline, urgent) {
+                (Some(color), _) => {
+                    line = format!("%{{u{}}}%{{+u}}{}%{{-u}}%{{u-}}", color, line);
+                }
+                (None, true) => line = format!("%{{+u}}{}%{{-u}}", line),
+                (None, false) => {}
+            }
+            for (button, command) in &self.actions {
+                line = format!(
+                    "%{{A{}:{}:}}{}%{{A}}",
+                    button,
+                    command.replace(':', "\\:"),
+                    line
+                );
+            }
+            line
+        }
+    }
+
+    impl DisplayOptions {
+        pub fn new(mode: DisplayMode) -> Self {
+            Self {
+                mode,
+                ..Default::default()
+            }
+        }
+
+        pub fn glyphs(&self) -> &'static Glyphs {
+            if self.ascii {
+                &ASCII_GLYPHS
+            } else {
+                &UNICODE_GLYPHS
+            }
+        }
+
+        // The template laying out the entry at `when`, if any
+        pub fn template(&self, entry: &AgendaEntry, when: NaiveDateTime) -> Option<&EntryTemplate> {
+            match &self.ongoing_format {
+                Some(template) if entry.due.is_none() && entry.start <= when => Some(template),
+                _ => self.format.as_ref(),
+            }
+        }
+
+        pub fn is_excluded(&self, entry: &AgendaEntry) -> bool {
+            let name = entry.name.to_lowercase();
+            let has_category = |categories: &[String]| {
+                categories.iter().any(|wanted| {
+                    entry
+                        .categories
+                        .iter()
+                        .any(|category| category.to_lowercase() == wanted.to_lowercase())
+                })
+            };
+            self.exclude
+                .iter()
+                .any(|pattern| name.contains(&pattern.to_lowercase()))
+                || (!self.categories.is_empty() && !has_category(&self.categories))
+                || has_category(&self.exclude_categories)
+                || (self.skip_all_day && entry.all_day)
+                || (!self.show_cancelled && entry.status == Status::Cancelled)
+        }
+    }
+
+    // Durations are cut to whole seconds and minutes unless rounded, hours as set by the
+    // style
+    pub fn format_duration(d: Duration, fmt: &DurationFormat) -> String {
+        let d = match fmt.rounding {
+            Rounding::FiveMinutes if d.num_minutes() >= 5 => {
+                Duration::minutes((d.num_seconds() as f32 / 300.0).round() as i64 * 5)
+            }
+            _ => d,
+        };
         if d.num_hours() != 0 {
-            let hrs_fmt = ((d.num_minutes() as f32 / 60.0) * 4.0).ceil() / 4.0;
-            return format!("{}h", hrs_fmt);
+            let minutes = d.num_minutes();
+            return match fmt.style {
+                DurationStyle::Decimal => {
+                    let quarters = (minutes as f32 / 15.0).ceil();
+                    fmt.units.quantity(quarters / 4.0, 2)
+                }
+                DurationStyle::Composite if minutes % 60 == 0 => {
+                    fmt.units.quantity((minutes / 60) as f32, 2)
+                }
+                DurationStyle::Composite => format!(
+                    "{} {}",
+                    fmt.units.quantity((minutes / 60) as f32, 2),
+                    fmt.units.quantity((minutes % 60) as f32, 1)
+                ),
+            };
+        }
+        let seconds = d.num_seconds() % 60;
+        if d.num_minutes() != 0 && fmt.rounding == Rounding::Exact && seconds != 0 {
+            return format!(
+                "{} {}",
+                fmt.units.quantity(d.num_minutes() as f32, 1),
+                fmt.units.quantity(seconds as f32, 0)
+            );
         }
         if d.num_minutes() != 0 {
-            return format!("{}min", d.num_minutes());
+            return fmt.units.quantity(d.num_minutes() as f32, 1);
         }
-        format!("{}s", d.num_seconds())
+        fmt.units.quantity(d.num_seconds() as f32, 0)
     }
 
     pub fn format_agenda_entry(
-        mode: DisplayMode,
+        opts: &DisplayOptions,
         entry: &AgendaEntry,
         when: NaiveDateTime,
     ) -> String {
-        match mode {
-            DisplayMode::Default => format_agenda_entry_default(entry, when),
-            DisplayMode::Compact => format_agenda_entry_compact(entry, when),
+        if let Some(template) = opts.template(entry, when) {
+            return template.render(opts, entry, when);
+        }
+        if let Some(due) = entry.due {
+            return format_todo_entry(opts, entry, due, when);
+        }
+        if entry.status == Status::Cancelled {
+            return format_cancelled_entry(opts, entry, when);
+        }
+        if entry.all_day {
+            return format_all_day_entry(opts, entry, when);
+        }
+        if let Some(until) = ongoing_until(opts, entry, when) {
+            return match opts.mode {
+                DisplayMode::Default => format!("{} {}", entry.name, until),
+                DisplayMode::Compact => {
+                    let name = compact_name(opts, entry, when);
+                    format!("{} {} {}", name, opts.glyphs().dot, until)
+                }
+            };
+        }
+        if let Some(countdown) = live_countdown(opts, entry, when) {
+            return match opts.mode {
+                DisplayMode::Default => format!(
+                    "{} {} ({})",
+                    entry.name,
+                    start_time(opts, entry, when, opts.day_label),
+                    i18n::future(&countdown, opts.language)
+                ),
+                DisplayMode::Compact => {
+                    let name = compact_name(opts, entry, when);
+                    format!("{} {} {}", name, opts.glyphs().dot, countdown)
+                }
+            };
+        }
+        if opts.humanize {
+            return format_agenda_entry_humanized(opts, entry, when);
+        }
+        match opts.mode {
+            DisplayMode::Default => format_agenda_entry_default(opts, entry, when),
+            DisplayMode::Compact => format_agenda_entry_compact(opts, entry, when),
         }
     }
 
-    pub fn format_agenda_entry_compact(entry: &AgendaEntry, when: NaiveDateTime) -> String {
+    pub fn format_agenda_entry_compact(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> String {
+        let fmt = &opts.durations;
         let time_until = entry.start.signed_duration_since(when);
         let time_remaining = (entry.start + entry.duration).signed_duration_since(when);
 
+        let name = compact_name(opts, entry, when);
+        let dot = opts.glyphs().dot;
         if time_until.num_minutes() > 0 || time_until.num_hours() > 0 {
-            format!("{} · {}", entry.name, format_duration(time_until))
+            format!("{} {} {}", name, dot, format_duration(time_until, fmt))
+        } else if let Some(width) = opts.progress_bar {
+            let bar = progress_bar(opts, entry, when, width);
+            format!("{} {} {}", name, dot, bar)
         } else {
             format!(
-                "{} · {}/{}",
-                entry.name,
-                format_duration(time_until.abs()),
-                format_duration(time_remaining)
+                "{} {} {}/{}",
+                name,
+                dot,
+                format_duration(time_until.abs(), fmt),
+                format_duration(time_remaining, fmt)
             )
         }
     }
 
-    pub fn format_agenda_entry_default(entry: &AgendaEntry, when: NaiveDateTime) -> String {
-        let start_time = entry.start.format("%H:%M").to_string();
+    pub fn format_agenda_entry_default(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> String {
+        let fmt = &opts.durations;
+        let start_time = start_time(opts, entry, when, opts.day_label);
         let time_until = when.signed_duration_since(entry.start);
 
-        if time_until.num_seconds() > 0 {
-            format!(
-                "{} {} ({} ago)",
-                entry.name,
-                start_time,
-                format_duration(time_until)
-            )
+        let relative = if time_until.num_seconds() > 0 {
+            i18n::past(&format_duration(time_until, fmt), opts.language)
         } else {
-            format!(
-                "{} {} (in {})",
-                entry.name,
-                start_time,
-                format_duration(time_until.abs())
-            )
+            i18n::future(&format_duration(time_until.abs(), fmt), opts.language)
+        };
+        format!("{} {} ({})", entry.name, start_time, relative)
+    }
+
+    // Like format_agenda_entry but with relative times phrased the way a person
+    // would say them ("in half an hour", "just started")
+    pub fn format_agenda_entry_humanized(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> String {
+        let lang = opts.language;
+        let since_start = when.signed_duration_since(entry.start);
+        let started = since_start.num_seconds() > 0;
+
+        match opts.mode {
+            DisplayMode::Default => {
+                let relative = if started {
+                    humanize_since(since_start, lang)
+                } else {
+                    humanize_until(since_start.abs(), lang)
+                };
+                format!(
+                    "{} {} ({})",
+                    entry.name,
+                    start_time(opts, entry, when, opts.day_label),
+                    relative
+                )
+            }
+            DisplayMode::Compact => {
+                let relative = if !started {
+                    humanize_until(since_start.abs(), lang)
+                } else if since_start < Duration::minutes(1) {
+                    humanize_since(since_start, lang)
+                } else {
+                    humanize_remaining(entry.start + entry.duration - when, lang)
+                };
+                let name = compact_name(opts, entry, when);
+                format!("{} {} {}", name, opts.glyphs().dot, relative)
+            }
         }
     }
 
-    pub fn process_calendar(
-        calendar: &Calendar,
-        mode: DisplayMode,
-        now: DateTime<Local>,
+    // "Standup 14:00 CANCELLED", without a countdown to something that won't happen
+    pub fn format_cancelled_entry(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
     ) -> String {
-        let current_time = now.naive_local();
-        let extract_start = now - Duration::hours(HOURS_BEHIND);
-        let extract_end = now + Duration::hours(HOURS_AHEAD);
+        let cancelled = i18n::cancelled(opts.language);
+        match opts.mode {
+            DisplayMode::Default => {
+                let time = start_time(opts, entry, when, opts.day_label);
+                format!("{} {} {}", entry.name, time, cancelled)
+            }
+            DisplayMode::Compact => {
+                let name = compact_name(opts, entry, when);
+                format!("{} {} {}", name, opts.glyphs().dot, cancelled)
+            }
+        }
+    }
+
+    // "Holiday All day", after its day if it starts on a later one. Counting down to
+    // midnight says nothing about an event lasting the whole day
+    pub fn format_all_day_entry(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> String {
+        let label = opts
+            .all_day_label
+            .as_deref()
+            .unwrap_or_else(|| i18n::all_day(opts.language));
+        // One that began on an earlier day is still on today
+        let upcoming = entry.start.date() > when.date();
+        match opts.mode {
+            DisplayMode::Default => {
+                let day = upcoming
+                    .then(|| opts.day_label.label(entry.start.date(), when.date(), opts))
+                    .flatten();
+                match day {
+                    Some(day) => format!("{} {} {}", entry.name, day, label),
+                    None => format!("{} {}", entry.name, label),
+                }
+            }
+            DisplayMode::Compact => {
+                let name = if upcoming {
+                    compact_name(opts, entry, when)
+                } else {
+                    entry.name.clone()
+                };
+                format!("{} {} {}", name, opts.glyphs().dot, label)
+            }
+        }
+    }
+
+    // "Report due in 3h", or "Report OVERDUE" once the deadline has passed
+    pub fn format_todo_entry(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        due: NaiveDateTime,
+        when: NaiveDateTime,
+    ) -> String {
+        let lang = opts.language;
+        if due <= when {
+            return format!("{} {}", entry.name, i18n::overdue(lang));
+        }
+        let remaining = if opts.humanize {
+            humanize_until(due - when, lang)
+        } else {
+            i18n::future(&format_duration(due - when, &opts.durations), lang)
+        };
+        format!("{} {}", entry.name, i18n::due(&remaining, lang))
+    }
+
+    // Convert a vdir/CSS style color (#RRGGBB or #RRGGBBAA) into polybar's #AARRGGBB
+    pub fn polybar_color(color: &str) -> Option<String> {
+        let hex = color.trim().strip_prefix('#')?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        match hex.len() {
+            3 | 6 => Some(format!("#{}", hex)),
+            8 => Some(format!("#{}{}", &hex[6..], &hex[..6])),
+            _ => None,
+        }
+    }
+
+    // A vdir/CSS style color without its alpha channel, for the many formats that
+    // only take #RRGGBB
+    pub fn rgb_color(color: &str) -> Option<String> {
+        let hex = color.trim().strip_prefix('#')?;
+        (matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| format!("#{}", &hex[..6]))
+    }
+
+    // Wrap `text` in the markup's tags for a vdir/CSS style color
+    pub fn colorize(text: String, color: Option<&str>, markup: Markup) -> String {
+        match (markup, color) {
+            (Markup::Polybar | Markup::Lemonbar, Some(color)) => match polybar_color(color) {
+                Some(color) => format!("%{{F{}}}{}%{{F-}}", color, text),
+                None => text,
+            },
+            (Markup::Tmux, Some(color)) => match rgb_color(color) {
+                Some(color) => format!("#[fg={}]{}#[fg=default]", color, text),
+                None => text,
+            },
+            (Markup::Pango, Some(color)) => match rgb_color(color) {
+                Some(color) => format!("<span foreground=\"{}\">{}</span>", color, text),
+                None => text,
+            },
+            _ => text,
+        }
+    }
+
+    // The first line of the entry's description with any text in it
+    pub fn description_snippet(opts: &DisplayOptions, entry: &AgendaEntry) -> Option<String> {
+        let line = entry
+            .description
+            .as_deref()?
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())?;
+        Some(match opts.description_width {
+            Some(width) => truncate_visible(line, width, Markup::Plain, opts.glyphs().ellipsis),
+            None => line.to_owned(),
+        })
+    }
+
+    // Apply the per-collection name, color and the source's label and icon on top of the
+    // display mode formatting. A label stands in for the collection's name, and a
+    // template places it itself
+    pub fn format_collection_entry(
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> String {
+        // Shortening the title rather than the entry keeps its time in view
+        let mut name = match opts.title_width {
+            Some(width) => match opts.scroll_step {
+                Some(step) => marquee::window(&entry.name, width, step),
+                None => {
+                    let ellipsis = opts.glyphs().ellipsis;
+                    truncate_visible(&entry.name, width, Markup::Plain, ellipsis)
+                }
+            },
+            None => entry.name.clone(),
+        };
+        // Part of the title, so that a template's {name} carries it too
+        if entry.status == Status::Tentative {
+            let marker = opts.tentative_marker.as_deref().unwrap_or(TENTATIVE_MARKER);
+            name.push_str(marker);
+        }
+        // A template places the location, description and tag itself
+        let templated = opts.template(entry, when).is_some();
+        // After shortening, so that the room isn't cut off with the title
+        let location = (opts.show_location && !templated)
+            .then_some(entry.location.as_ref())
+            .flatten();
+        if let Some(location) = location {
+            name = format!("{} @ {}", name, location);
+        }
+        let entry = &AgendaEntry {
+            name,
+            ..entry.clone()
+        };
+        let mut text = format_agenda_entry(opts, entry, when);
+        let snippet = (opts.show_description && !templated)
+            .then(|| description_snippet(opts, entry))
+            .flatten();
+        if let Some(snippet) = snippet {
+            text = format!("{} {} {}", text, opts.glyphs().dash, snippet);
+        }
+        let calendar = entry.calendar.as_ref().filter(|_| opts.show_calendar);
+        let tag = entry.label.as_ref().or(calendar).filter(|_| !templated);
+        if let Some(tag) = tag {
+            text = format!("[{}] {}", tag, text);
+        }
+        if let Some(icon) = &entry.icon {
+            text = format!("{} {}", icon, text);
+        }
+        let text = match opts.markup {
+            Markup::Plain => return text,
+            // A lone # would start a tmux format
+            Markup::Tmux => text.replace('#', "##"),
+            Markup::Pango => escape_markup(&text),
+            Markup::Polybar | Markup::Lemonbar => text,
+        };
+        let mut text = colorize(text, entry_color(opts, entry, when), opts.markup);
+        // Pango can say more than the color: approaching entries are set in bold too
+        if opts.markup == Markup::Pango && threshold_color(opts, entry, when).is_some() {
+            text = format!("<b>{}</b>", text);
+        }
+        if opts.markup == Markup::Pango && entry.status == Status::Cancelled {
+            text = format!("<s>{}</s>", text);
+        }
+        match (opts.markup, &entry.link) {
+            // lemonbar prints the command when the region is clicked, for a shell
+            // reading its output to run. Colons would end the command
+            (Markup::Lemonbar, Some(link)) => format!(
+                "%{{A:xdg-open '{}':}}{}%{{A}}",
+                link.replace('\'', "%27").replace(':', "\\:"),
+                text
+            ),
+            _ => text,
+        }
+    }
 
+    // Normalize every supported component of a calendar, skipping invalid ones
+    pub fn calendar_records(calendar: &Calendar, default_tz: Option<Tz>) -> Vec<EventRecord> {
         calendar
             .iter()
             .filter_map(|element| match element {
-                CalendarComponent::Event(e) => extract_event(e, extract_start, extract_end).ok(),
-                CalendarComponent::Todo(t) => extract_event(t, extract_start, extract_end).ok(),
-                CalendarComponent::Venue(v) => extract_event(v, extract_start, extract_end).ok(),
+                CalendarComponent::Event(e) => EventRecord::from_component(e, default_tz).ok(),
+                CalendarComponent::Todo(t) => match t.get_status() {
+                    Some(TodoStatus::Completed | TodoStatus::Cancelled) => None,
+                    _ => EventRecord::from_todo(t, default_tz).ok(),
+                },
+                CalendarComponent::Venue(v) => EventRecord::from_component(v, default_tz).ok(),
                 _ => None,
             })
+            .collect()
+    }
+
+    // Expand records into the entries between sod and eod
+    pub fn record_entries(
+        records: &[EventRecord],
+        sod: DateTime<Local>,
+        eod: DateTime<Local>,
+    ) -> Vec<AgendaEntry> {
+        records
+            .iter()
+            .filter_map(|record| record.entries(sod, eod).ok())
             .flatten()
+            .collect()
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum WindowMode {
+        // Hours around the current time, HOURS_BEHIND..HOURS_AHEAD by default
+        Rolling {
+            behind: i64,
+            ahead: i64,
+        },
+        // From the start of today to the end of the day N days from now, so the
+        // window doesn't move as the clock advances through the day
+        Days(i64),
+        // The entries between two times, whether they're over or not
+        Range {
+            from: NaiveDateTime,
+            to: NaiveDateTime,
+        },
+    }
+
+    impl Default for WindowMode {
+        fn default() -> Self {
+            WindowMode::Rolling {
+                behind: HOURS_BEHIND,
+                ahead: HOURS_AHEAD,
+            }
+        }
+    }
+
+    impl WindowMode {
+        // The rolling window, reaching `hours` into the past
+        pub fn behind(self, hours: i64) -> Self {
+            match self {
+                WindowMode::Rolling { ahead, .. } => WindowMode::Rolling {
+                    behind: hours,
+                    ahead,
+                },
+                WindowMode::Days(_) | WindowMode::Range { .. } => WindowMode::Rolling {
+                    behind: hours,
+                    ahead: HOURS_AHEAD,
+                },
+            }
+        }
+
+        // The rolling window, reaching `hours` into the future
+        pub fn ahead(self, hours: i64) -> Self {
+            match self {
+                WindowMode::Rolling { behind, .. } => WindowMode::Rolling {
+                    behind,
+                    ahead: hours,
+                },
+                WindowMode::Days(_) | WindowMode::Range { .. } => WindowMode::Rolling {
+                    behind: HOURS_BEHIND,
+                    ahead: hours,
+                },
+            }
+        }
+    }
+
+    pub fn extraction_window(
+        now: DateTime<Local>,
+        window: WindowMode,
+    ) -> (DateTime<Local>, DateTime<Local>) {
+        match window {
+            WindowMode::Rolling { behind, ahead } => {
+                let hours = |hours: i64| Duration::hours(hours.clamp(0, MAX_WINDOW_HOURS));
+                (now - hours(behind), now + hours(ahead))
+            }
+            WindowMode::Days(days) => (
+                now.beginning_of_day(),
+                (now + Duration::days(days.clamp(0, MAX_WINDOW_HOURS / 24))).end_of_day(),
+            ),
+            WindowMode::Range { from, to } => {
+                let local = |time| Local.from_local_datetime(&time).earliest().unwrap_or(now);
+                (local(from), local(to))
+            }
+        }
+    }
+
+    // The entries still worth showing at `now`, in the order they're shown
+    pub fn upcoming_entries(
+        entries: Vec<AgendaEntry>,
+        opts: &DisplayOptions,
+        now: DateTime<Local>,
+    ) -> Vec<AgendaEntry> {
+        let current_time = now.naive_local();
+        let overdue_for = opts
+            .overdue_for
+            .unwrap_or_else(|| Duration::hours(OVERDUE_HOURS));
+        // The rolling window historically shows upcoming events however far out they are
+        let window_end = match opts.window {
+            WindowMode::Rolling { .. } => None,
+            WindowMode::Days(_) => Some(extraction_window(now, opts.window).1.naive_local()),
+            // Regardless of the current time
+            WindowMode::Range { from, to } => {
+                return entries
+                    .into_iter()
+                    .sorted_unstable_by_key(|item| item.start)
+                    .filter(|item| {
+                        item.start < to && (item.start >= from || item.start + item.duration > from)
+                    })
+                    .collect();
+            }
+        };
+
+        entries
+            .into_iter()
             .sorted_unstable_by_key(|item| item.start)
             .filter(|item| {
-                (item.start + item.duration) >= current_time
-                    && (current_time - item.start).num_hours() < 24
+                let current = match item.due {
+                    Some(due) => due + overdue_for >= current_time,
+                    None => {
+                        (item.start + item.duration) >= current_time
+                            && (current_time - item.start).num_hours() < 24
+                    }
+                };
+                current && window_end.is_none_or(|end| item.start <= end)
             })
-            .take(2)
-            .map(|item| format_agenda_entry(mode, &item, current_time))
-            .intersperse(" » ".to_owned())
             .collect()
     }
+
+    // Cut `line` down to `width` visible characters, ending in `ellipsis`. Characters
+    // are grapheme clusters, so emoji and combining marks are never split. Formatting
+    // tags don't count and are all kept, so that they stay balanced
+    pub fn truncate_visible(line: &str, width: usize, markup: Markup, ellipsis: &str) -> String {
+        let tag = match markup {
+            Markup::Plain => None,
+            Markup::Polybar | Markup::Lemonbar => Some(("%{", '}')),
+            Markup::Tmux => Some(("#[", ']')),
+            Markup::Pango => Some(("<", '>')),
+        };
+        // Split into tags and visible characters, `##` being tmux's escaped #
+        let mut tokens = Vec::new();
+        let mut rest = line;
+        while let Some(grapheme) = rest.graphemes(true).next() {
+            let tag_end = tag
+                .filter(|(open, _)| rest.starts_with(open))
+                .and_then(|(_, close)| rest.find(close));
+            let (len, visible) = match tag_end {
+                Some(end) => (end + 1, false),
+                None if markup == Markup::Tmux && rest.starts_with("##") => (2, true),
+                // An entity like &amp; is a single character
+                None if markup == Markup::Pango && rest.starts_with('&') => {
+                    (rest.find(';').map_or(1, |end| end + 1), true)
+                }
+                None => (grapheme.len(), true),
+            };
+            tokens.push((&rest[..len], visible));
+            rest = &rest[len..];
+        }
+        if tokens.iter().filter(|(_, visible)| *visible).count() <= width {
+            return line.to_owned();
+        }
+
+        let keep = width.saturating_sub(ellipsis.graphemes(true).count());
+        let mut kept = 0;
+        let mut truncated = String::new();
+        for (token, visible) in tokens {
+            if visible {
+                kept += 1;
+                if kept == keep + 1 {
+                    truncated.push_str(ellipsis);
+                }
+                if kept > keep {
+                    continue;
+                }
+            }
+            truncated.push_str(token);
+        }
+        truncated
+    }
+
+    pub fn process_entries(
+        entries: Vec<AgendaEntry>,
+        opts: &DisplayOptions,
+        now: DateTime<Local>,
+    ) -> String {
+        let fit = |line: String| match opts.max_width {
+            Some(width) => truncate_visible(&line, width, opts.markup, opts.glyphs().ellipsis),
+            None => line,
+        };
+        let urgent = opts
+            .urgent_within
+            .is_some_and(|within| is_urgent(&entries, within, now.naive_local()));
+        let single = opts.only_next || opts.countdown_only;
+        if let Some(count) = opts.lines.filter(|_| !single) {
+            let lines = agenda_lines(entries, opts, now, count);
+            return lines.lines().map(|line| fit(line.to_owned())).join("\n");
+        }
+        let Some(line) = agenda_line(entries, opts, now) else {
+            return match &opts.empty {
+                EmptyOutput::Text(text) => {
+                    let text = match opts.markup {
+                        Markup::Pango => escape_markup(text),
+                        _ => text.clone(),
+                    };
+                    colorize(fit(text), opts.empty_color.as_deref(), opts.markup)
+                }
+                EmptyOutput::Blank | EmptyOutput::Nothing => String::new(),
+            };
+        };
+        match opts.markup {
+            Markup::Polybar => opts.line_tags.wrap(fit(line), urgent),
+            _ => fit(line),
+        }
+    }
+
+    // The upcoming entries starting today, including those already shown
+    fn today_count(upcoming: &[AgendaEntry], when: NaiveDateTime) -> usize {
+        upcoming
+            .iter()
+            .filter(|item| item.start.date() <= when.date())
+            .count()
+    }
+
+    // Up to `count` entries one per line, under the header if there is one. Nothing
+    // upcoming leaves just the header, or the empty text
+    fn agenda_lines(
+        entries: Vec<AgendaEntry>,
+        opts: &DisplayOptions,
+        now: DateTime<Local>,
+        count: usize,
+    ) -> String {
+        let current_time = now.naive_local();
+        let upcoming = upcoming_entries(entries, opts, now);
+        let header = opts.header.as_ref().map(|header| {
+            let today = today_count(&upcoming, current_time);
+            header.replace("{count}", &today.to_string())
+        });
+        let mut lines: Vec<String> = header.into_iter().collect();
+        lines.extend(
+            upcoming
+                .iter()
+                .take(count)
+                .map(|item| format_collection_entry(opts, item, current_time)),
+        );
+        if let (true, EmptyOutput::Text(text)) = (upcoming.is_empty(), &opts.empty) {
+            lines.push(colorize(
+                text.clone(),
+                opts.empty_color.as_deref(),
+                opts.markup,
+            ));
+        }
+        lines.join("\n")
+    }
+
+    // The entries and the counter of hidden ones, None if nothing is upcoming
+    fn agenda_line(
+        entries: Vec<AgendaEntry>,
+        opts: &DisplayOptions,
+        now: DateTime<Local>,
+    ) -> Option<String> {
+        let current_time = now.naive_local();
+        let upcoming = upcoming_entries(entries, opts, now);
+        if opts.only_next {
+            return upcoming
+                .first()
+                .map(|item| format_collection_entry(opts, item, current_time));
+        }
+        if opts.countdown_only {
+            let next = upcoming
+                .iter()
+                .find(|item| item.due.is_none() && item.start > current_time)?;
+            return Some(countdown(opts, next, current_time));
+        }
+        let limit = opts.limit.unwrap_or(SHOWN_ENTRIES);
+        let agenda = upcoming
+            .iter()
+            .take(limit)
+            .map(|item| format_collection_entry(opts, item, current_time))
+            .join(opts.separator.as_deref().unwrap_or(opts.glyphs().separator));
+
+        if agenda.is_empty() {
+            return None;
+        }
+
+        let hidden_today = upcoming
+            .iter()
+            .skip(limit)
+            .filter(|item| item.start.date() <= current_time.date())
+            .count();
+
+        let mut line = match &opts.more_format {
+            Some(more) if hidden_today > 0 => {
+                format!(
+                    "{} {}",
+                    agenda,
+                    more.replace("{}", &hidden_today.to_string())
+                )
+            }
+            _ => agenda,
+        };
+        // All of today's, not only the hidden ones
+        let today = today_count(&upcoming, current_time);
+        if opts.show_count && today > 0 {
+            let count = match &opts.count_format {
+                Some(format) => format.replace("{count}", &today.to_string()),
+                None => format!("({})", i18n::today(today, opts.language)),
+            };
+            line = format!("{} {}", line, count);
+        }
+        Some(line)
+    }
 }
 
-use calendar::{process_calendar, DisplayMode};
-use chrono::Local;
-use icalendar::Calendar;
+mod auth;
+mod availability;
+mod cache;
+mod caldav;
+mod config;
+mod eds;
+mod export;
+mod fallback;
+mod google;
+mod graph;
+mod hooks;
+mod i18n;
+mod keyring;
+mod khal;
+mod links;
+mod marquee;
+mod netrc;
+mod oauth;
+mod org;
+mod output;
+mod range;
+mod remind;
+mod remote;
+mod sources;
+mod template;
+mod thunderbird;
+mod watch;
+mod week;
+mod windows_zones;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        return Err("Calendar file not provided".into());
+use calendar::{
+    capped_hours, capped_minutes, extraction_window, is_urgent, process_entries, threshold_color,
+    upcoming_entries, AgendaEntry, Clock, DisplayMode, DisplayOptions, EmptyOutput, Markup,
+    WindowMode, MAX_WINDOW_HOURS,
+};
+use chrono::{Duration, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Weekday};
+use i18n::Language;
+use output::OutputFormat;
+use sources::{LoadOptions, SourceError};
+
+// Shown offline unless --stale-marker is given, `{age}` is replaced with how long ago
+// the oldest calendar shown was downloaded
+const OFFLINE_MARKER: &str = " (offline, updated {age})";
+
+// Exit statuses for scripts to tell a free afternoon from a broken calendar, any other
+// failure exits with 1. Nothing is upcoming, only with --exit-status
+const EXIT_EMPTY: i32 = 2;
+// A calendar couldn't be parsed
+const EXIT_PARSE: i32 = 3;
+// A calendar couldn't be downloaded, or its service signed in to
+const EXIT_NETWORK: i32 = 4;
+
+fn exit_code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    match error.downcast_ref::<SourceError>() {
+        Some(SourceError::Parse(..)) => EXIT_PARSE,
+        Some(SourceError::Http(..) | SourceError::Status(..) | SourceError::Auth(..)) => {
+            EXIT_NETWORK
+        }
+        _ => 1,
     }
+}
+
+// The first argument may select a subcommand, the default is the bar agenda
+enum Command {
+    Agenda,
+    Week,
+    // Open the conference link of the ongoing or next meeting
+    Join,
+    // Print the free blocks of a day
+    Availability,
+    // Sign in to an online calendar service
+    Login,
+    // Print the agenda again whenever it changes, for polybar's `tail = true`
+    Watch,
+    // Print the events of the next hours as an ics calendar
+    ExportIcs,
+}
 
-    let mode = if args.len() >= 3 && args[1] == "--display-compact" {
-        DisplayMode::Compact
-    } else {
-        DisplayMode::Default
+// Parse the value following a flag such as `--output org`
+fn flag_value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|e| format!("Invalid value for {}: {}", flag, e))
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {:?}", error);
+        process::exit(exit_code(error.as_ref()));
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut opts = DisplayOptions::new(DisplayMode::Default);
+    opts.language = Language::from_env();
+    opts.locale = i18n::time_locale();
+    if let Some(locale) = opts.locale {
+        opts.clock = Clock::Locale(locale);
+    }
+    let mut output = OutputFormat::Bar;
+    let mut load_opts = LoadOptions::default();
+    let mut use_cache = true;
+    let mut week_start = Weekday::Mon;
+    // Of the week command, the next seven days as a list rather than this week's grid
+    let mut week_list = false;
+    let mut hooks = hooks::Hooks::default();
+    let mut working_hours = availability::WorkingHours::default();
+    // Of the export-ics command
+    let mut export_hours: u32 = 48;
+    let mut scrub = false;
+    let mut date = None;
+    // Of an explicit window, in place of the one around the current time
+    let mut from = None;
+    let mut to = None;
+    // Appended to the agenda when a calendar couldn't be downloaded
+    let mut stale_marker = None;
+    let mut offline = false;
+    // Each path with the source options given before it
+    let mut paths: Vec<(String, LoadOptions)> = Vec::new();
+
+    // The configuration file is read first, so that flags override its settings
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config_flag = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from);
+    let config = match config_flag.clone().or_else(config::default_path) {
+        Some(path) => config::Config::load(&path)?,
+        None => None,
     };
+    if let (Some(path), None) = (&config_flag, &config) {
+        return Err(format!("{}: no such file", path.display()).into());
+    }
+    let config = config.unwrap_or_default();
+    config.apply(&mut opts, &mut load_opts)?;
+    use_cache = config.cache.unwrap_or(use_cache);
+    let mut template_file = config.template.clone();
+    let mut scroll = config.scroll.unwrap_or(false);
+    let mut urgent_exit = config.urgent_exit;
+    let mut exit_status = config.exit_status.unwrap_or(false);
+    let environment = config::Environment::from_env();
+    environment.apply(&mut opts)?;
 
-    let file_name = args.last().unwrap();
-    let file_contents = fs::read_to_string(file_name)?;
-    let parsed_calendar = file_contents.parse::<Calendar>()?;
+    let mut args = args.into_iter().peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("week") => {
+            args.next();
+            Command::Week
+        }
+        Some("join") => {
+            args.next();
+            Command::Join
+        }
+        Some("availability") => {
+            args.next();
+            Command::Availability
+        }
+        Some("login") => {
+            args.next();
+            Command::Login
+        }
+        Some("watch") => {
+            args.next();
+            Command::Watch
+        }
+        Some("export-ics") => {
+            args.next();
+            Command::ExportIcs
+        }
+        _ => Command::Agenda,
+    };
 
-    let now = Local::now();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--display-compact" => opts.mode = DisplayMode::Compact,
+            "--show-calendar" => opts.show_calendar = true,
+            "--show-location" => opts.show_location = true,
+            "--show-description" => opts.show_description = true,
+            "--description-width" => opts.description_width = Some(flag_value(&mut args, &arg)?),
+            "--humanize" => opts.humanize = true,
+            "--units" => opts.durations.units = flag_value(&mut args, &arg)?,
+            "--durations" => opts.durations.style = flag_value(&mut args, &arg)?,
+            "--rounding" => opts.durations.rounding = flag_value(&mut args, &arg)?,
+            "--lang" => opts.language = flag_value(&mut args, &arg)?,
+            "--output" => output = flag_value(&mut args, &arg)?,
+            "--show-more" => opts.more_format = Some("+{}".to_owned()),
+            "--more-format" => opts.more_format = Some(flag_value(&mut args, &arg)?),
+            "--show-count" => opts.show_count = true,
+            "--only-next" => opts.only_next = true,
+            "--limit" => opts.limit = Some(flag_value(&mut args, &arg)?),
+            "--countdown-only" => opts.countdown_only = true,
+            "--lines" => opts.lines = Some(flag_value(&mut args, &arg)?),
+            "--header" => opts.header = Some(flag_value(&mut args, &arg)?),
+            "--count-format" => {
+                opts.show_count = true;
+                opts.count_format = Some(flag_value(&mut args, &arg)?);
+            }
+            "--empty-text" => opts.empty = EmptyOutput::Text(flag_value(&mut args, &arg)?),
+            "--empty-color" => opts.empty_color = Some(flag_value(&mut args, &arg)?),
+            "--empty-nothing" => opts.empty = EmptyOutput::Nothing,
+            "--no-cache" => use_cache = false,
+            "--offline" => offline = true,
+            "--config" => {
+                args.next();
+            }
+            "--separator" => opts.separator = Some(flag_value(&mut args, &arg)?),
+            "--ascii" => opts.ascii = true,
+            "--progress-bar" => opts.progress_bar = Some(flag_value(&mut args, &arg)?),
+            "--progress-chars" => opts.progress_chars = Some(flag_value(&mut args, &arg)?),
+            "--threshold" => opts.thresholds.push(flag_value(&mut args, &arg)?),
+            "--urgent" => opts.urgent_within = Some(capped_minutes(flag_value(&mut args, &arg)?)),
+            "--urgent-exit" => urgent_exit = Some(flag_value(&mut args, &arg)?),
+            "--exit-status" => exit_status = true,
+            "--exclude" => opts.exclude.push(flag_value(&mut args, &arg)?),
+            "--category" => opts.categories.push(flag_value(&mut args, &arg)?),
+            "--exclude-category" => opts.exclude_categories.push(flag_value(&mut args, &arg)?),
+            "--skip-all-day" => opts.skip_all_day = true,
+            "--all-day-label" => opts.all_day_label = Some(flag_value(&mut args, &arg)?),
+            "--show-cancelled" => opts.show_cancelled = true,
+            "--tentative-marker" => opts.tentative_marker = Some(flag_value(&mut args, &arg)?),
+            "--max-width" => opts.max_width = Some(flag_value(&mut args, &arg)?),
+            "--12h" => opts.clock = Clock::TwelveHour,
+            "--show-end" => opts.show_end = true,
+            "--countdown" => {
+                opts.countdown_below = Some(capped_minutes(flag_value(&mut args, &arg)?))
+            }
+            "--day-label" => opts.day_label = flag_value(&mut args, &arg)?,
+            "--compact-day-label" => opts.compact_day_label = flag_value(&mut args, &arg)?,
+            "--24h" => opts.clock = Clock::TwentyFourHour,
+            "--title-width" => opts.title_width = Some(flag_value(&mut args, &arg)?),
+            "--scroll" => scroll = true,
+            "--format" => opts.format = Some(flag_value(&mut args, &arg)?),
+            "--ongoing-format" => opts.ongoing_format = Some(flag_value(&mut args, &arg)?),
+            "--template" => template_file = Some(flag_value::<PathBuf>(&mut args, &arg)?),
+            "--stale-marker" => stale_marker = Some(flag_value::<String>(&mut args, &arg)?),
+            "--days" => opts.window = WindowMode::Days(flag_value(&mut args, &arg)?),
+            "--today" => opts.window = WindowMode::Days(0),
+            "--from" => from = Some(flag_value::<range::TimePoint>(&mut args, &arg)?),
+            "--to" => to = Some(flag_value::<range::TimePoint>(&mut args, &arg)?),
+            "--hours-behind" => {
+                let hours: u32 = flag_value(&mut args, &arg)?;
+                opts.window = opts.window.behind(hours.into());
+            }
+            "--hours-ahead" => {
+                let hours: u32 = flag_value(&mut args, &arg)?;
+                opts.window = opts.window.ahead(hours.into());
+            }
+            "--week-start" => week_start = flag_value(&mut args, &arg)?,
+            "--list" => week_list = true,
+            "--working-hours" => working_hours = flag_value(&mut args, &arg)?,
+            "--hours" => export_hours = flag_value(&mut args, &arg)?,
+            "--scrub" => scrub = true,
+            "--date" => date = Some(flag_value::<NaiveDate>(&mut args, &arg)?),
+            "--timezone" => load_opts.timezone = Some(flag_value(&mut args, &arg)?),
+            "--encoding" => {
+                let label: String = flag_value(&mut args, &arg)?;
+                let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| format!("Unknown encoding: {}", label))?;
+                load_opts.encoding = Some(encoding);
+            }
+            "--user" => {
+                let credentials = load_opts.credentials.get_or_insert_with(Default::default);
+                credentials.username = flag_value(&mut args, &arg)?;
+            }
+            "--password" => {
+                let credentials = load_opts.credentials.get_or_insert_with(Default::default);
+                credentials.password = flag_value(&mut args, &arg)?;
+            }
+            "--keyring" => load_opts.keyring = Some(flag_value(&mut args, &arg)?),
+            "--calendar" => load_opts.calendars.push(flag_value(&mut args, &arg)?),
+            "--label" => load_opts.label = Some(flag_value(&mut args, &arg)?),
+            "--icon" => load_opts.icon = Some(flag_value(&mut args, &arg)?),
+            "--from-khal" => {
+                let config = khal::config_path().ok_or("khal's configuration file not found")?;
+                for calendar in khal::load(&config)? {
+                    let mut source_opts = load_opts.clone();
+                    source_opts.name = calendar.name;
+                    source_opts.color = calendar.color;
+                    paths.push((calendar.path, source_opts));
+                }
+            }
+            "--client-id" => {
+                let client = load_opts.oauth_client.get_or_insert_with(Default::default);
+                client.id = flag_value(&mut args, &arg)?;
+            }
+            "--client-secret" => {
+                let client = load_opts.oauth_client.get_or_insert_with(Default::default);
+                client.secret = Some(flag_value(&mut args, &arg)?);
+            }
+            "--tenant" => {
+                let client = load_opts.oauth_client.get_or_insert_with(Default::default);
+                client.tenant = Some(flag_value(&mut args, &arg)?);
+            }
+            "--connect-timeout" => {
+                load_opts.network.connect_timeout =
+                    StdDuration::from_secs(flag_value(&mut args, &arg)?)
+            }
+            "--read-timeout" => {
+                load_opts.network.read_timeout =
+                    StdDuration::from_secs(flag_value(&mut args, &arg)?)
+            }
+            "--retries" => load_opts.network.retries = flag_value(&mut args, &arg)?,
+            "--max-age" => {
+                let minutes: u64 = flag_value(&mut args, &arg)?;
+                load_opts.max_age = Some(StdDuration::from_secs(minutes * 60));
+            }
+            "--on-start" => hooks.on_start = Some(flag_value(&mut args, &arg)?),
+            "--on-end" => hooks.on_end = Some(flag_value(&mut args, &arg)?),
+            "--overdue-hours" => {
+                opts.overdue_for = Some(capped_hours(flag_value(&mut args, &arg)?))
+            }
+            _ => paths.push((arg, load_opts.clone())),
+        }
+    }
+
+    if from.is_some() || to.is_some() {
+        let today = Local::now().date_naive();
+        let from = from.map_or(Local::now().naive_local(), |from| from.start(today));
+        // The rest of the day `--from` is on
+        let to = match to {
+            Some(to) => to.end_after(from, today),
+            None => (from.date() + Duration::days(1)).and_time(NaiveTime::MIN),
+        };
+        if to <= from {
+            return Err("--to must be after --from".into());
+        }
+        opts.window = WindowMode::Range { from, to };
+    }
+
+    if scroll && opts.title_width.is_none() {
+        return Err("--scroll needs a --title-width to scroll titles within".into());
+    }
+
+    if let Command::Login = command {
+        let (service, source_opts) = paths.first().ok_or("Service to sign in to not provided")?;
+        let client = source_opts
+            .oauth_client
+            .as_ref()
+            .ok_or("Pass the OAuth client to sign in with --client-id and --client-secret")?;
+        let provider = match service.as_str() {
+            "google" => google::provider(),
+            "outlook" => graph::provider(client),
+            _ => return Err(format!("Unknown service: {}", service).into()),
+        };
+        let token_file =
+            oauth::token_file(provider.name).ok_or("Can't locate the cache directory")?;
+        oauth::login(&provider, client, &token_file, &mut io::stderr())?;
+        return Ok(());
+    }
+
+    // Without any paths, read the calendar from the environment or the sources of the
+    // configuration file, or else a calendar piped into the module
+    if let (true, Some(calendar)) = (paths.is_empty(), &environment.calendar) {
+        paths.push((calendar.clone(), load_opts.clone()));
+    }
+    let from_config = paths.is_empty();
+    if from_config {
+        paths = config.sources(&load_opts)?;
+    }
+    if paths.is_empty() {
+        if io::stdin().is_terminal() {
+            return Err("Calendar file not provided".into());
+        }
+        paths.push((sources::STDIN.to_owned(), load_opts.clone()));
+    }
+    let cache = use_cache
+        .then(cache::ParseCache::default_location)
+        .flatten();
+    let http_cache = use_cache
+        .then(cache::ResponseCache::default_location)
+        .flatten();
+
+    // What tells this module's state from that of others run with other sources or flags
+    let module = (
+        paths
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>(),
+        env::args().skip(1).collect::<Vec<_>>(),
+    );
+
+    // In watch mode, the agenda is printed again whenever a calendar file changes, and
+    // at the start of every minute for the times relative to now
+    let watcher = match command {
+        Command::Watch => {
+            let files: Vec<_> = paths
+                .iter()
+                .flat_map(|(path, _)| sources::local_paths(path))
+                .collect();
+            if paths.iter().any(|(path, _)| path == sources::STDIN) {
+                return Err("A piped calendar can't be watched".into());
+            }
+            Some(watch::Watcher::new(&files)?)
+        }
+        _ => None,
+    };
+    // The entries last loaded in watch mode and the minute they were loaded in, which
+    // the prints every second in between reuse
+    let mut loaded: Option<(i64, Vec<AgendaEntry>, Option<u64>)> = None;
+    loop {
+        let now = Local::now();
+        let day = date.unwrap_or(now.date_naive());
+        let first_day = week::week_start(now.date_naive(), week_start);
+        // Whole local days starting at `first`
+        let days_window = |first: NaiveDate, days: i64| {
+            let start = first.and_time(NaiveTime::MIN);
+            (
+                Local.from_local_datetime(&start).earliest().unwrap_or(now),
+                Local
+                    .from_local_datetime(&(start + Duration::days(days)))
+                    .earliest()
+                    .unwrap_or(now),
+            )
+        };
+        let (extract_start, extract_end) = match command {
+            Command::Agenda | Command::Watch | Command::Join | Command::Login => {
+                extraction_window(now, opts.window)
+            }
+            Command::Week if week_list => days_window(now.date_naive(), 7),
+            Command::Week => days_window(first_day, 7),
+            Command::Availability => days_window(day, 1),
+            Command::ExportIcs => {
+                let hours = i64::from(export_hours).min(MAX_WINDOW_HOURS);
+                (now, now + Duration::hours(hours))
+            }
+        };
 
-    let formatted_agenda = process_calendar(&parsed_calendar, mode, now);
+        let minute = now.timestamp().div_euclid(60);
+        let (entries, stale_since) = match loaded.take() {
+            Some((at, entries, stale_since)) if at == minute => (entries, stale_since),
+            _ => {
+                for (_, source_opts) in &mut paths {
+                    source_opts.cache = cache.clone();
+                    source_opts.http_cache = http_cache.clone();
+                    source_opts.offline = offline;
+                    source_opts.window = Some((extract_start, extract_end));
+                }
 
-    println!("{}", formatted_agenda);
-    Ok(())
+                let mut collections = match (sources::load_all(&paths), &watcher) {
+                    (Ok(collections), _) => collections,
+                    // A file caught mid-write or a dropped connection shouldn't end the
+                    // module, which tries again on the next change or minute
+                    (Err(error), Some(watcher)) => {
+                        eprintln!("Error: {}", error);
+                        watcher.wait(StdDuration::from_secs(60 - u64::from(now.second())));
+                        continue;
+                    }
+                    (Err(error), None) => return Err(error.into()),
+                };
+                // With --calendar, sources that aren't picked whole only keep their
+                // calendars of those names, for the kinds that don't leave out the
+                // others themselves
+                if from_config {
+                    collections.retain(|collection| {
+                        load_opts.wants_calendar(collection.name.as_deref())
+                            || load_opts.wants_calendar(collection.label.as_deref())
+                    });
+                }
+                // The oldest copy shown in place of a download
+                let stale_since = collections
+                    .iter()
+                    .filter_map(|collection| collection.stale)
+                    .min();
+                let entries: Vec<_> = collections
+                    .iter()
+                    .flat_map(|collection| collection.entries(extract_start, extract_end))
+                    .filter(|entry| !opts.is_excluded(entry))
+                    .map(|mut entry| {
+                        opts.icons.apply(&mut entry);
+                        entry
+                    })
+                    .collect();
+                (entries, stale_since)
+            }
+        };
+        if watcher.is_some() {
+            loaded = Some((minute, entries.clone(), stale_since));
+        }
+
+        match command {
+            Command::Week if week_list => {
+                let today = now.date_naive();
+                let list = week::format_week_list(&entries, today, &opts);
+                println!("{}", list);
+                return Ok(());
+            }
+            Command::Week => {
+                println!("{}", week::format_week(&entries, first_day, opts.locale));
+                return Ok(());
+            }
+            Command::Join => {
+                let meeting = links::next_meeting(&entries, now.naive_local())
+                    .ok_or("No upcoming event with a conference link")?;
+                process::Command::new("xdg-open")
+                    .arg(meeting.link.as_deref().unwrap_or_default())
+                    .spawn()?;
+                return Ok(());
+            }
+            Command::Availability => {
+                let blocks =
+                    availability::free_blocks(&entries, day, working_hours, now.naive_local());
+                let zone = availability::local_zone_name(day.and_time(working_hours.start));
+                println!(
+                    "{}",
+                    availability::format_availability(&blocks, &zone, &opts)
+                );
+                return Ok(());
+            }
+            Command::ExportIcs => {
+                let (from, to) = (now.naive_local(), extract_end.naive_local());
+                let selected: Vec<_> = entries
+                    .into_iter()
+                    .filter(|entry| entry.start < to && entry.start + entry.duration > from)
+                    .collect();
+                print!("{}", export::to_ics(&selected, scrub));
+                return Ok(());
+            }
+            Command::Agenda | Command::Watch | Command::Login => {}
+        }
+
+        // A failing hook shouldn't take the bar down with it
+        if !hooks.is_empty() {
+            match cache::cache_dir() {
+                Some(dir) => {
+                    let state = cache::state_path(&dir, "ongoing", &module);
+                    if let Err(error) = hooks.run(&entries, now.naive_local(), &state) {
+                        eprintln!("Error: running a hook: {}", error);
+                    }
+                }
+                None => eprintln!("Error: Can't locate the cache directory for the hooks"),
+            }
+        }
+        if scroll {
+            let dir = cache::cache_dir().ok_or("Can't locate the cache directory")?;
+            let state = cache::state_path(&dir, "scroll", &module);
+            opts.scroll_step = Some(marquee::next_step(&state)?);
+        }
+
+        let marker = stale_marker
+            .as_deref()
+            .or(offline.then_some(OFFLINE_MARKER));
+        let stale_text = match (stale_since, marker) {
+            (Some(since), Some(marker)) => {
+                let age = chrono::DateTime::from_timestamp(since as i64, 0)
+                    .map_or(Duration::zero(), |fetched| {
+                        now.signed_duration_since(fetched)
+                    });
+                marker.replace("{age}", &i18n::humanize_ago(age, opts.language))
+            }
+            _ => String::new(),
+        };
+        // While a countdown runs or starts within the minute, the watch mode prints every
+        // second. Todos show when they're due instead of a countdown
+        let ticking = opts.countdown_below.is_some_and(|below| {
+            entries
+                .iter()
+                .filter(|entry| entry.due.is_none())
+                .any(|entry| {
+                    let left = entry.start - now.naive_local();
+                    left > Duration::zero() && left <= below + Duration::minutes(1)
+                })
+        });
+        let urgent = opts
+            .urgent_within
+            .is_some_and(|within| is_urgent(&entries, within, now.naive_local()));
+        let idle = exit_status && upcoming_entries(entries.clone(), &opts, now).is_empty();
+        // For scripts wrapping the module to tell urgent and empty lines apart
+        let status = if idle {
+            Some(EXIT_EMPTY)
+        } else {
+            urgent_exit.filter(|_| urgent)
+        };
+        let formatted_agenda = if let Some(path) = &template_file {
+            let plain = DisplayOptions {
+                markup: Markup::Plain,
+                ..opts.clone()
+            };
+            let upcoming = upcoming_entries(entries, &opts, now);
+            template::render_file(path, &upcoming, &plain, now.naive_local())?
+        } else {
+            match output {
+                OutputFormat::Bar => process_entries(entries, &opts, now) + &stale_text,
+                OutputFormat::Org => output::format_org(entries),
+                OutputFormat::Html => {
+                    let upcoming = upcoming_entries(entries, &opts, now);
+                    output::format_html(&upcoming, &opts, now.naive_local())
+                }
+                OutputFormat::Lemonbar => {
+                    let lemonbar = DisplayOptions {
+                        markup: Markup::Lemonbar,
+                        ..opts.clone()
+                    };
+                    process_entries(entries, &lemonbar, now) + &stale_text
+                }
+                OutputFormat::Tmux => {
+                    let tmux = DisplayOptions {
+                        markup: Markup::Tmux,
+                        ..opts.clone()
+                    };
+                    process_entries(entries, &tmux, now) + &stale_text
+                }
+                OutputFormat::Pango => {
+                    let pango = DisplayOptions {
+                        markup: Markup::Pango,
+                        ..opts.clone()
+                    };
+                    process_entries(entries, &pango, now) + &stale_text
+                }
+                OutputFormat::Waybar => {
+                    let compact = DisplayOptions {
+                        mode: DisplayMode::Compact,
+                        markup: Markup::Plain,
+                        ..opts.clone()
+                    };
+                    let text = process_entries(entries.clone(), &compact, now) + &stale_text;
+                    let upcoming = upcoming_entries(entries.clone(), &compact, now);
+                    let color = upcoming
+                        .first()
+                        .and_then(|entry| threshold_color(&opts, entry, now.naive_local()));
+                    output::format_waybar(
+                        &text,
+                        &entries,
+                        now.naive_local(),
+                        upcoming.is_empty(),
+                        color,
+                        &opts,
+                    )
+                }
+                OutputFormat::I3blocks => {
+                    let plain = DisplayOptions {
+                        markup: Markup::Plain,
+                        ..opts.clone()
+                    };
+                    let compact = DisplayOptions {
+                        mode: DisplayMode::Compact,
+                        ..plain.clone()
+                    };
+                    let full_text = process_entries(entries.clone(), &plain, now) + &stale_text;
+                    let short_text = process_entries(entries.clone(), &compact, now) + &stale_text;
+                    let color = upcoming_entries(entries, &opts, now)
+                        .first()
+                        .and_then(|entry| output::line_color(&opts, entry, now.naive_local()));
+                    output::format_i3blocks(&full_text, &short_text, color.as_deref())
+                }
+            }
+        };
+
+        // With tail = true, polybar shows the last line until the next one, so the
+        // watch mode always prints one
+        let silent = formatted_agenda.is_empty()
+            && matches!(opts.empty, EmptyOutput::Nothing)
+            && watcher.is_none();
+        if !silent {
+            println!("{}", formatted_agenda);
+        }
+        let Some(watcher) = &watcher else {
+            if let Some(code) = status {
+                process::exit(code);
+            }
+            return Ok(());
+        };
+        let now = Local::now();
+        // A changed calendar is loaded again right away, while the prints every second
+        // only load at the start of the next minute
+        let changed = watcher.wait(if ticking {
+            StdDuration::from_nanos(1_000_000_000u64.saturating_sub(u64::from(now.nanosecond())))
+        } else {
+            StdDuration::from_secs(60 - u64::from(now.second()))
+        });
+        if changed {
+            loaded = None;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,33 @@
+use std::{fs, io, path::Path};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Blank characters between the end of a scrolling title and its start coming round again
+const GAP: usize = 3;
+
+// The number of the current run, counted in the state file so that every run shows
+// titles one character further along
+pub fn next_step(path: &Path) -> io::Result<usize> {
+    let step = fs::read_to_string(path)
+        .ok()
+        .and_then(|state| state.trim().parse().ok())
+        .unwrap_or(0usize);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{}\n", step.wrapping_add(1)))?;
+    Ok(step)
+}
+
+// `width` characters of `text` starting `step` characters in, wrapping around. Text that
+// fits is left as it is
+pub fn window(text: &str, width: usize, step: usize) -> String {
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    if graphemes.len() <= width {
+        return text.to_owned();
+    }
+    let cycle = graphemes.len() + GAP;
+    (step % cycle..)
+        .take(width)
+        .map(|i| graphemes.get(i % cycle).copied().unwrap_or(" "))
+        .collect()
+}
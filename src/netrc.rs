@@ -0,0 +1,86 @@
+use crate::auth::Credentials;
+use std::{env, fs, path::PathBuf};
+
+// A `machine` entry of a netrc file, or the `default` one when `machine` is None
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub machine: Option<String>,
+    pub login: String,
+    pub password: String,
+}
+
+// The entries of a netrc file, in the order they are listed. Tokens are separated by
+// any whitespace, so an entry may span several lines or share one with another
+pub fn parse(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut tokens = text.split_whitespace();
+    let new_entry = |machine| Entry {
+        machine,
+        login: String::new(),
+        password: String::new(),
+    };
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                entries.extend(current.take());
+                current = tokens.next().map(|name| new_entry(Some(name.to_owned())));
+            }
+            "default" => {
+                entries.extend(current.take());
+                current = Some(new_entry(None));
+            }
+            "login" | "password" | "account" => {
+                let (Some(entry), Some(value)) = (current.as_mut(), tokens.next()) else {
+                    continue;
+                };
+                // Quoted values, as curl accepts them. Spaces inside quotes are not supported
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                match token {
+                    "login" => entry.login = value.to_owned(),
+                    "password" => entry.password = value.to_owned(),
+                    _ => {}
+                }
+            }
+            // A macro runs until the next empty line, which the tokens don't show. Its
+            // body is ftp commands, so anything but another entry is skipped
+            "macdef" => {
+                entries.extend(current.take());
+                tokens.next();
+            }
+            _ => {}
+        }
+    }
+    entries.extend(current);
+    entries
+}
+
+// The entry for `host`, falling back to the default one like curl does
+pub fn find<'a>(entries: &'a [Entry], host: &str) -> Option<&'a Entry> {
+    entries
+        .iter()
+        .find(|entry| entry.machine.as_deref() == Some(host))
+        .or_else(|| entries.iter().find(|entry| entry.machine.is_none()))
+}
+
+// `$NETRC`, or `~/.netrc`
+fn path() -> Option<PathBuf> {
+    env::var_os("NETRC")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc")))
+}
+
+// The credentials kept for `host` in the user's netrc file, if there is one
+pub fn credentials(host: &str) -> Option<Credentials> {
+    let text = fs::read_to_string(path()?).ok()?;
+    let entries = parse(&text);
+    let entry = find(&entries, host)?;
+    Some(Credentials {
+        username: entry.login.clone(),
+        password: entry.password.clone(),
+    })
+}
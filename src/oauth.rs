@@ -0,0 +1,245 @@
+use crate::cache::cache_dir;
+use crate::sources::SourceError;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Renew access tokens a bit before they expire, so they don't run out mid-request
+const EXPIRY_MARGIN: u64 = 60;
+
+// An OAuth2 client registered with a provider. Installed apps can't keep the
+// secret confidential, so some providers don't issue one at all
+#[derive(Clone, Debug, Default)]
+pub struct OAuthClient {
+    pub id: String,
+    pub secret: Option<String>,
+    // Directory (tenant) the client is registered in, for Microsoft accounts
+    pub tenant: Option<String>,
+}
+
+// Endpoints of a provider supporting the device authorization grant (RFC 8628)
+pub struct Provider {
+    pub name: &'static str,
+    pub device_url: String,
+    pub token_url: String,
+    pub scope: String,
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    #[serde(alias = "verification_url")]
+    verification_uri: String,
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+// What's kept between runs, in a file only the user can read
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    refresh_token: String,
+    access_token: String,
+    // Seconds since the epoch
+    expires_at: u64,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Where the tokens for `provider` are stored
+pub fn token_file(provider: &str) -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("tokens").join(format!("{}.json", provider)))
+}
+
+fn auth_error(provider: &Provider, message: impl ToString) -> SourceError {
+    SourceError::Auth(provider.name.to_owned(), message.to_string())
+}
+
+// POST a form to the provider, returning the parsed response or the OAuth error code
+fn post<T: DeserializeOwned>(
+    provider: &Provider,
+    url: &str,
+    form: &[(&str, &str)],
+) -> Result<Result<T, String>, SourceError> {
+    let mut response = ureq::post(url)
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .send_form(form.iter().copied())
+        .map_err(|e| auth_error(provider, e))?;
+    if response.status().is_success() {
+        let body = response
+            .body_mut()
+            .read_json()
+            .map_err(|e| auth_error(provider, e))?;
+        return Ok(Ok(body));
+    }
+    let status = response.status().as_u16();
+    Ok(Err(response
+        .body_mut()
+        .read_json::<ErrorResponse>()
+        .map(|e| e.error)
+        .unwrap_or_else(|_| format!("HTTP {}", status))))
+}
+
+fn save(path: &Path, token: &StoredToken) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_vec(token).map_err(io::Error::other)?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(&json)
+}
+
+fn client_form<'a>(client: &'a OAuthClient, form: &mut Vec<(&'a str, &'a str)>) {
+    form.push(("client_id", &client.id));
+    if let Some(secret) = &client.secret {
+        form.push(("client_secret", secret));
+    }
+}
+
+// Sign in interactively: the user opens a link on any device and enters the code
+// printed to `out`, while this waits for the provider to hand out the tokens
+pub fn login(
+    provider: &Provider,
+    client: &OAuthClient,
+    token_file: &Path,
+    out: &mut impl Write,
+) -> Result<(), SourceError> {
+    let device: DeviceCode = post(
+        provider,
+        &provider.device_url,
+        &[("client_id", &client.id), ("scope", &provider.scope)],
+    )?
+    .map_err(|e| auth_error(provider, e))?;
+    let _ = writeln!(
+        out,
+        "Open {} and enter the code {}",
+        device.verification_uri, device.user_code
+    );
+
+    let mut interval = device.interval;
+    let mut form = Vec::new();
+    client_form(client, &mut form);
+    form.push(("device_code", &device.device_code));
+    form.push(("grant_type", "urn:ietf:params:oauth:grant-type:device_code"));
+    loop {
+        thread::sleep(Duration::from_secs(interval));
+        match post::<TokenResponse>(provider, &provider.token_url, &form)? {
+            Ok(token) => {
+                let refresh_token = token
+                    .refresh_token
+                    .ok_or_else(|| auth_error(provider, "no refresh token was issued"))?;
+                let stored = StoredToken {
+                    refresh_token,
+                    access_token: token.access_token,
+                    expires_at: unix_now() + token.expires_in.unwrap_or(0),
+                };
+                return save(token_file, &stored)
+                    .map_err(|e| SourceError::Io(token_file.to_owned(), e));
+            }
+            Err(e) if e == "authorization_pending" => {}
+            Err(e) if e == "slow_down" => interval += 5,
+            Err(e) => return Err(auth_error(provider, e)),
+        }
+    }
+}
+
+// A current access token, renewed with the stored refresh token when it has expired
+pub fn access_token(
+    provider: &Provider,
+    client: &OAuthClient,
+    token_file: &Path,
+) -> Result<String, SourceError> {
+    let not_logged_in = || {
+        auth_error(
+            provider,
+            format!(
+                "not signed in, run `polybar-agenda login {}` first",
+                provider.name
+            ),
+        )
+    };
+    let stored = fs::read(token_file).map_err(|_| not_logged_in())?;
+    let stored: StoredToken = serde_json::from_slice(&stored).map_err(|_| not_logged_in())?;
+    if stored.expires_at > unix_now() + EXPIRY_MARGIN {
+        return Ok(stored.access_token);
+    }
+
+    let mut form = Vec::new();
+    client_form(client, &mut form);
+    form.push(("refresh_token", &stored.refresh_token));
+    form.push(("grant_type", "refresh_token"));
+    let token: TokenResponse = post(provider, &provider.token_url, &form)?
+        .map_err(|e| auth_error(provider, format!("{}, sign in again", e)))?;
+    let renewed = StoredToken {
+        // Providers may rotate the refresh token as well
+        refresh_token: token.refresh_token.unwrap_or(stored.refresh_token),
+        access_token: token.access_token,
+        expires_at: unix_now() + token.expires_in.unwrap_or(0),
+    };
+    // Failing to store the new token just means renewing it again next time
+    let _ = save(token_file, &renewed);
+    Ok(renewed.access_token)
+}
+
+// A token for the client itself rather than a user (the client credentials grant),
+// reused from `token_file` until it expires
+pub fn client_token(
+    provider: &Provider,
+    client: &OAuthClient,
+    token_file: &Path,
+) -> Result<String, SourceError> {
+    let stored = fs::read(token_file)
+        .ok()
+        .and_then(|stored| serde_json::from_slice::<StoredToken>(&stored).ok());
+    if let Some(stored) = stored.filter(|s| s.expires_at > unix_now() + EXPIRY_MARGIN) {
+        return Ok(stored.access_token);
+    }
+
+    let mut form = Vec::new();
+    client_form(client, &mut form);
+    form.push(("scope", &provider.scope));
+    form.push(("grant_type", "client_credentials"));
+    let token: TokenResponse =
+        post(provider, &provider.token_url, &form)?.map_err(|e| auth_error(provider, e))?;
+    let stored = StoredToken {
+        refresh_token: String::new(),
+        access_token: token.access_token,
+        expires_at: unix_now() + token.expires_in.unwrap_or(0),
+    };
+    let _ = save(token_file, &stored);
+    Ok(stored.access_token)
+}
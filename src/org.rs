@@ -0,0 +1,296 @@
+use crate::calendar::AgendaEntry;
+use chrono::{Duration, Months, NaiveDate, NaiveDateTime, NaiveTime};
+use std::{fs, io, path::Path};
+
+// Keywords of headings that are finished and shouldn't show up any more
+const DONE_KEYWORDS: [&str; 3] = ["DONE", "CANCELLED", "CANCELED"];
+const TODO_KEYWORDS: [&str; 4] = ["TODO", "NEXT", "WAITING", "HOLD"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+// The `+1w` of `<2024-05-06 Mon +1w>`. The `++` and `.+` variants only differ in
+// how org shifts the timestamp once the task is marked done, so they're read the same
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repeater {
+    pub count: u32,
+    pub unit: Unit,
+}
+
+// An active timestamp, e.g. `<2024-05-06 Mon 10:00-11:30 +1w -2d>`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timestamp {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub repeater: Option<Repeater>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Planning {
+    Scheduled,
+    Deadline,
+    // A plain timestamp in the heading or its text, an appointment
+    Active,
+}
+
+// A heading with one of its timestamps. A heading that is both scheduled and has
+// a deadline yields two items
+#[derive(Debug, PartialEq)]
+pub struct OrgItem {
+    pub title: String,
+    pub planning: Planning,
+    pub timestamp: Timestamp,
+}
+
+impl Repeater {
+    fn parse(token: &str) -> Option<Self> {
+        let token = token
+            .strip_prefix(".+")
+            .or_else(|| token.strip_prefix("++"))
+            .or_else(|| token.strip_prefix('+'))?;
+        let (count, unit) = token.split_at(token.len().checked_sub(1)?);
+        let unit = match unit {
+            "h" => Unit::Hour,
+            "d" => Unit::Day,
+            "w" => Unit::Week,
+            "m" => Unit::Month,
+            "y" => Unit::Year,
+            _ => return None,
+        };
+        Some(Self {
+            count: count.parse().ok().filter(|c| *c > 0)?,
+            unit,
+        })
+    }
+
+    // The `n`th repetition after `start`
+    fn nth(&self, start: NaiveDateTime, n: u32) -> Option<NaiveDateTime> {
+        let n = self.count.checked_mul(n)?;
+        match self.unit {
+            Unit::Hour => start.checked_add_signed(Duration::hours(n.into())),
+            Unit::Day => start.checked_add_signed(Duration::days(n.into())),
+            Unit::Week => start.checked_add_signed(Duration::weeks(n.into())),
+            Unit::Month => start.checked_add_months(Months::new(n)),
+            Unit::Year => start.checked_add_months(Months::new(n.checked_mul(12)?)),
+        }
+    }
+
+    // A lower bound for the number of repetitions before `when`, so that old
+    // timestamps don't have to be stepped through one by one
+    fn skip(&self, start: NaiveDateTime, when: NaiveDateTime) -> u32 {
+        let elapsed = when - start;
+        let step = match self.unit {
+            Unit::Hour => Duration::hours(self.count.into()),
+            Unit::Day => Duration::days(self.count.into()),
+            Unit::Week => Duration::weeks(self.count.into()),
+            // Months and years vary in length, step by their longest so as to never
+            // skip past `when`
+            Unit::Month => Duration::days(31 * i64::from(self.count)),
+            Unit::Year => Duration::days(366 * i64::from(self.count)),
+        };
+        let skipped = elapsed.num_seconds() / step.num_seconds();
+        skipped.clamp(0, i64::from(u32::MAX)) as u32
+    }
+}
+
+impl Timestamp {
+    // Parse the inside of `<...>`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut tokens = text.split_whitespace();
+        let date = NaiveDate::parse_from_str(tokens.next()?, "%Y-%m-%d").ok()?;
+        let mut timestamp = Self {
+            date,
+            time: None,
+            end_time: None,
+            repeater: None,
+        };
+        for token in tokens {
+            if token.starts_with(['+', '.']) {
+                timestamp.repeater = Some(Repeater::parse(token)?);
+            } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+                let (start, end) = match token.split_once('-') {
+                    Some((start, end)) => (start, Some(end)),
+                    None => (token, None),
+                };
+                timestamp.time = Some(NaiveTime::parse_from_str(start, "%H:%M").ok()?);
+                timestamp.end_time = match end {
+                    Some(end) => Some(NaiveTime::parse_from_str(end, "%H:%M").ok()?),
+                    None => None,
+                };
+            }
+            // Day names and warning periods (`-2d`) don't affect when it happens
+        }
+        Some(timestamp)
+    }
+
+    fn start(&self) -> NaiveDateTime {
+        self.date.and_time(self.time.unwrap_or(NaiveTime::MIN))
+    }
+
+    fn duration(&self) -> Duration {
+        match (self.time, self.end_time) {
+            (Some(start), Some(end)) if end > start => end - start,
+            (Some(_), _) => Duration::zero(),
+            (None, _) => Duration::days(1),
+        }
+    }
+}
+
+impl OrgItem {
+    fn entry(&self, start: NaiveDateTime) -> AgendaEntry {
+        match self.planning {
+            // Like todos with a DUE, a date-only deadline means by the end of that day
+            Planning::Deadline => {
+                let due = match self.timestamp.time {
+                    Some(_) => start,
+                    None => start + Duration::days(1),
+                };
+                let mut entry = AgendaEntry::new(self.title.clone(), due, Duration::zero());
+                entry.due = Some(due);
+                entry
+            }
+            Planning::Scheduled | Planning::Active => {
+                let mut entry =
+                    AgendaEntry::new(self.title.clone(), start, self.timestamp.duration());
+                entry.all_day = self.timestamp.time.is_none();
+                entry
+            }
+        }
+    }
+
+    // Occurrences of the item, repeating ones limited to those between the two dates,
+    // inclusive
+    pub fn entries(&self, first: NaiveDate, last: NaiveDate) -> Vec<AgendaEntry> {
+        let start = self.timestamp.start();
+        let Some(repeater) = self.timestamp.repeater else {
+            return vec![self.entry(start)];
+        };
+        let from = first.and_time(NaiveTime::MIN);
+        let skipped = if from > start {
+            repeater.skip(start, from)
+        } else {
+            0
+        };
+        (skipped..)
+            .map_while(|n| repeater.nth(start, n))
+            .skip_while(|occurrence| occurrence.date() < first)
+            .take_while(|occurrence| occurrence.date() <= last)
+            .map(|occurrence| self.entry(occurrence))
+            .collect()
+    }
+}
+
+// Active timestamps (`<...>`) in a line, inactive ones (`[...]`) are just notes
+fn timestamps(line: &str) -> impl Iterator<Item = (usize, Timestamp)> + '_ {
+    line.match_indices('<').filter_map(move |(offset, _)| {
+        let rest = &line[offset + 1..];
+        let end = rest.find('>')?;
+        Some((offset, Timestamp::parse(&rest[..end])?))
+    })
+}
+
+// The title of a heading without its stars, TODO keyword, priority and tags.
+// Returns None for finished headings
+fn heading_title(line: &str) -> Option<String> {
+    let mut title = line.trim_start_matches('*').trim();
+    let keyword = title.split_whitespace().next().unwrap_or("");
+    if DONE_KEYWORDS.contains(&keyword) {
+        return None;
+    }
+    if TODO_KEYWORDS.contains(&keyword) {
+        title = title[keyword.len()..].trim_start();
+    }
+    if title.starts_with("[#") {
+        title = title
+            .split_once(']')
+            .map_or("", |(_, rest)| rest)
+            .trim_start();
+    }
+    // Trailing tags, e.g. `:work:urgent:`
+    if let Some((rest, tags)) = title.rsplit_once(char::is_whitespace) {
+        if tags.len() > 1 && tags.starts_with(':') && tags.ends_with(':') {
+            title = rest.trim_end();
+        }
+    }
+    // Timestamps in the heading are when it happens, not part of its name
+    let mut cleaned = title.to_owned();
+    let ranges: Vec<_> = timestamps(title)
+        .map(|(offset, _)| offset..offset + title[offset..].find('>').unwrap_or(0) + 1)
+        .collect();
+    for range in ranges.into_iter().rev() {
+        cleaned.replace_range(range, "");
+    }
+    Some(cleaned.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn is_heading(line: &str) -> bool {
+    let stars = line.len() - line.trim_start_matches('*').len();
+    stars > 0 && line[stars..].starts_with(' ')
+}
+
+// Every heading's SCHEDULED, DEADLINE and plain active timestamps. Timestamps
+// before the first heading belong to no item and are ignored
+pub fn parse_org(contents: &str) -> Vec<OrgItem> {
+    let mut items = Vec::new();
+    // None while inside a finished heading, or before the first one
+    let mut title: Option<String> = None;
+    let mut in_block = false;
+    for line in contents.lines() {
+        if is_heading(line) {
+            title = heading_title(line);
+            in_block = false;
+            let Some(title) = &title else { continue };
+            items.extend(timestamps(line).map(|(_, timestamp)| OrgItem {
+                title: title.clone(),
+                planning: Planning::Active,
+                timestamp,
+            }));
+            continue;
+        }
+        let Some(title) = &title else { continue };
+
+        // Source and example blocks may well contain `<...>`
+        let trimmed = line.trim_start();
+        let lower = trimmed.to_ascii_lowercase();
+        if lower.starts_with("#+begin_") {
+            in_block = true;
+        } else if lower.starts_with("#+end_") {
+            in_block = false;
+        }
+        if in_block || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // A planning line such as `SCHEDULED: <...> DEADLINE: <...>`
+        for (offset, timestamp) in timestamps(line) {
+            let before = line[..offset].trim_end();
+            let planning = if before.ends_with("SCHEDULED:") {
+                Planning::Scheduled
+            } else if before.ends_with("DEADLINE:") {
+                Planning::Deadline
+            } else if before.ends_with("CLOSED:") {
+                continue;
+            } else {
+                Planning::Active
+            };
+            items.push(OrgItem {
+                title: title.clone(),
+                planning,
+                timestamp,
+            });
+        }
+    }
+    items
+}
+
+// Read an org file
+pub fn load(path: &Path) -> io::Result<Vec<OrgItem>> {
+    fs::read_to_string(path).map(|contents| parse_org(&contents))
+}
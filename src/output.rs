@@ -0,0 +1,326 @@
+use crate::calendar::{
+    entry_color, format_duration, is_urgent, rgb_color, AgendaEntry, DayLabel, DisplayOptions,
+    EmptyOutput,
+};
+use crate::i18n;
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use itertools::Itertools;
+use serde::Serialize;
+use std::str::FromStr;
+
+// Events starting this soon make the waybar module urgent
+const IMMINENT_MINUTES: i64 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    // The single line shown in the bar
+    Bar,
+    Org,
+    // A JSON object for waybar's custom modules
+    Waybar,
+    // The full_text, short_text and color lines of an i3blocks blocklet
+    I3blocks,
+    // The bar line with lemonbar's formatting codes
+    Lemonbar,
+    // The bar line with tmux's style codes, for its status line
+    Tmux,
+    // A table of the upcoming entries, for web widgets and kiosk displays
+    Html,
+    // The bar line in Pango markup, for wofi, eww or GNOME extensions
+    Pango,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bar" => Ok(OutputFormat::Bar),
+            "org" => Ok(OutputFormat::Org),
+            "waybar" => Ok(OutputFormat::Waybar),
+            "i3blocks" => Ok(OutputFormat::I3blocks),
+            "lemonbar" => Ok(OutputFormat::Lemonbar),
+            "tmux" => Ok(OutputFormat::Tmux),
+            "html" => Ok(OutputFormat::Html),
+            "pango" => Ok(OutputFormat::Pango),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+fn org_timestamp(dt: NaiveDateTime, with_time: bool) -> String {
+    if with_time {
+        dt.format("<%Y-%m-%d %a %H:%M>").to_string()
+    } else {
+        dt.format("<%Y-%m-%d %a>").to_string()
+    }
+}
+
+// Org tags can't contain whitespace or most punctuation
+fn org_tag(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "_@#%".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Todos become TODO headings with their deadline, events get an active timestamp.
+// Locations and links go in the property drawer, which org wants right after the
+// heading and its planning line
+pub fn format_org_entry(entry: &AgendaEntry) -> String {
+    let end = entry.start + entry.duration;
+    let properties = [("LOCATION", &entry.location), ("URL", &entry.link)];
+    let drawer = properties
+        .iter()
+        .filter_map(|(name, value)| Some(format!("\n  :{}: {}", name, value.as_ref()?)))
+        .join("");
+    let drawer = if drawer.is_empty() {
+        drawer
+    } else {
+        format!("\n  :PROPERTIES:{}\n  :END:", drawer)
+    };
+    let heading = |keyword: &str| match &entry.calendar {
+        Some(calendar) => format!("* {}{} :{}:", keyword, entry.name, org_tag(calendar)),
+        None => format!("* {}{}", keyword, entry.name),
+    };
+    if let Some(due) = entry.due {
+        return format!(
+            "{}\n  DEADLINE: {}{}",
+            heading("TODO "),
+            org_timestamp(due, true),
+            drawer
+        );
+    }
+
+    let timestamp = if entry.all_day {
+        // All-day ranges are exclusive of their end date, org's are inclusive
+        let last_day = end - Duration::days(1);
+        if last_day.date() == entry.start.date() {
+            org_timestamp(entry.start, false)
+        } else {
+            format!(
+                "{}--{}",
+                org_timestamp(entry.start, false),
+                org_timestamp(last_day, false)
+            )
+        }
+    } else if end.date() == entry.start.date() && entry.duration > Duration::zero() {
+        format!(
+            "{}-{}>",
+            org_timestamp(entry.start, true).trim_end_matches('>'),
+            end.format("%H:%M")
+        )
+    } else if entry.duration > Duration::zero() {
+        format!(
+            "{}--{}",
+            org_timestamp(entry.start, true),
+            org_timestamp(end, true)
+        )
+    } else {
+        org_timestamp(entry.start, true)
+    };
+
+    format!("{}{}\n  {}", heading(""), drawer, timestamp)
+}
+
+// Waybar renders text and tooltips as Pango markup
+pub fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_html(text: &str) -> String {
+    escape_markup(text).replace('"', "&quot;")
+}
+
+// The entry's name after its icon
+fn title(entry: &AgendaEntry) -> String {
+    match &entry.icon {
+        Some(icon) => format!("{} {}", icon, entry.name),
+        None => entry.name.clone(),
+    }
+}
+
+// The entries of `day`, one per line with their times. All-day entries come first
+pub fn format_day(entries: &[AgendaEntry], day: NaiveDate, opts: &DisplayOptions) -> String {
+    let from = day.and_time(NaiveTime::MIN);
+    let to = from + Duration::days(1);
+    entries
+        .iter()
+        // Yesterday's all-day entries end at midnight, but aren't today's
+        .filter(|entry| {
+            entry.start < to && (entry.start >= from || entry.start + entry.duration > from)
+        })
+        .sorted_by_key(|entry| (!entry.all_day, entry.start))
+        .map(|entry| {
+            let end = entry.start + entry.duration;
+            if entry.all_day {
+                title(entry)
+            } else if entry.duration > Duration::zero() {
+                format!(
+                    "{}{}{}  {}",
+                    opts.clock.format(entry.start.time()),
+                    opts.glyphs().dash,
+                    opts.clock.format(end.time()),
+                    title(entry)
+                )
+            } else {
+                format!(
+                    "{}  {}",
+                    opts.clock.format(entry.start.time()),
+                    title(entry)
+                )
+            }
+        })
+        .join("\n")
+}
+
+#[derive(Serialize)]
+struct WaybarModule {
+    text: String,
+    tooltip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<&'static str>,
+}
+
+// The bar's line as `text`, today's agenda as `tooltip`, and the `urgent` class while
+// an event starts within the `urgent_within` of `opts`, IMMINENT_MINUTES if unset.
+// `idle` lines, when nothing is upcoming, get the `empty` class so they can be styled
+// on their own. The text is set in `color`, that of an approaching threshold
+pub fn format_waybar(
+    text: &str,
+    entries: &[AgendaEntry],
+    now: NaiveDateTime,
+    idle: bool,
+    color: Option<&str>,
+    opts: &DisplayOptions,
+) -> String {
+    let within = opts
+        .urgent_within
+        .unwrap_or_else(|| Duration::minutes(IMMINENT_MINUTES));
+    let imminent = is_urgent(entries, within, now);
+    let class = match (imminent, idle) {
+        (true, _) => Some("urgent"),
+        (false, true) => Some("empty"),
+        (false, false) => None,
+    };
+    let text = match color.and_then(rgb_color) {
+        Some(color) => format!("<span color=\"{}\">{}</span>", color, escape_markup(text)),
+        None => escape_markup(text),
+    };
+    let module = WaybarModule {
+        text,
+        tooltip: escape_markup(&format_day(entries, now.date(), opts)),
+        class,
+    };
+    serde_json::to_string(&module).unwrap_or_default()
+}
+
+// i3blocks colors the whole line, after the first entry shown: red once overdue, else
+// the color of an approaching threshold or of its collection
+pub fn line_color(
+    opts: &DisplayOptions,
+    entry: &AgendaEntry,
+    now: NaiveDateTime,
+) -> Option<String> {
+    entry_color(opts, entry, now).and_then(rgb_color)
+}
+
+// The lines read by i3blocks: the full text, the text used when space runs out, and
+// optionally the color
+pub fn format_i3blocks(full_text: &str, short_text: &str, color: Option<&str>) -> String {
+    let mut lines = vec![full_text, short_text];
+    lines.extend(color);
+    lines.join("\n")
+}
+
+const HTML_CELL: &str = "padding: 2px 8px";
+const HTML_MUTED: &str = "color: #888888";
+
+// When an entry is: its day if it isn't today and its time, or nothing if it lasts all day
+fn html_time(entry: &AgendaEntry, opts: &DisplayOptions, now: NaiveDateTime) -> String {
+    let day = DayLabel::Relative.label(entry.start.date(), now.date(), opts);
+    let time = (!entry.all_day).then(|| opts.clock.format(entry.start.time()));
+    day.into_iter().chain(time).join(" ")
+}
+
+// How long until the entry, or until it ends once it started
+fn html_relative(entry: &AgendaEntry, opts: &DisplayOptions, now: NaiveDateTime) -> String {
+    let lang = opts.language;
+    let end = entry.start + entry.duration;
+    match entry.due {
+        Some(due) if due <= now => i18n::overdue(lang).to_owned(),
+        Some(due) => i18n::due(
+            &i18n::future(&format_duration(due - now, &opts.durations), lang),
+            lang,
+        ),
+        None if entry.start > now => {
+            i18n::future(&format_duration(entry.start - now, &opts.durations), lang)
+        }
+        None => i18n::until(&opts.clock.format(end.time()), lang),
+    }
+}
+
+// The upcoming entries as a table styled inline, so that it can be embedded in a page
+// as it is. Titles take the entries' colors and link to their conference calls
+pub fn format_html(entries: &[AgendaEntry], opts: &DisplayOptions, now: NaiveDateTime) -> String {
+    if entries.is_empty() {
+        return match &opts.empty {
+            EmptyOutput::Text(text) => format!(
+                "<p style=\"font-family: sans-serif; {}\">{}</p>",
+                HTML_MUTED,
+                escape_html(text)
+            ),
+            EmptyOutput::Blank | EmptyOutput::Nothing => String::new(),
+        };
+    }
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            let mut title = escape_html(&title(entry));
+            if let Some(link) = &entry.link {
+                title = format!(
+                    "<a href=\"{}\" style=\"color: inherit\">{}</a>",
+                    escape_html(link),
+                    title
+                );
+            }
+            let color = entry_color(opts, entry, now)
+                .and_then(rgb_color)
+                .map(|color| format!("; color: {}", color))
+                .unwrap_or_default();
+            let muted = format!("; {}", HTML_MUTED);
+            let cells = [
+                (&muted, html_time(entry, opts, now)),
+                (&color, title),
+                (&muted, escape_html(&html_relative(entry, opts, now))),
+            ];
+            let cells = cells
+                .iter()
+                .map(|(style, text)| {
+                    format!("    <td style=\"{}{}\">{}</td>", HTML_CELL, style, text)
+                })
+                .join("\n");
+            format!("  <tr>\n{}\n  </tr>", cells)
+        })
+        .join("\n");
+    format!(
+        "<table style=\"border-collapse: collapse; font-family: sans-serif\">\n{}\n</table>",
+        rows
+    )
+}
+
+// Every entry of the extraction window as an org heading with an active timestamp
+pub fn format_org(entries: Vec<AgendaEntry>) -> String {
+    entries
+        .into_iter()
+        .sorted_by_key(|entry| entry.start)
+        .map(|entry| format_org_entry(&entry))
+        .join("\n")
+}
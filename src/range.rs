@@ -0,0 +1,102 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Day {
+    Date(NaiveDate),
+    // The next one, today included
+    Weekday(Weekday),
+    // Days from today, "today" or "tomorrow"
+    Offset(i64),
+}
+
+// A `--from`/`--to` bound like "2024-05-09 09:00", "thu 09:00", "tomorrow" or "18:00",
+// the day and the time each being optional
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimePoint {
+    day: Option<Day>,
+    time: Option<NaiveTime>,
+}
+
+impl FromStr for Day {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "today" => return Ok(Day::Offset(0)),
+            "tomorrow" => return Ok(Day::Offset(1)),
+            _ => {}
+        }
+        if let Ok(date) = NaiveDate::from_str(s) {
+            return Ok(Day::Date(date));
+        }
+        s.parse()
+            .map(Day::Weekday)
+            .map_err(|_| format!("Invalid day: {}", s))
+    }
+}
+
+impl FromStr for TimePoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let time = |s: &str| NaiveTime::parse_from_str(s, "%H:%M").ok();
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts[..] {
+            [day, at] => Ok(TimePoint {
+                day: Some(day.parse()?),
+                time: Some(time(at).ok_or_else(|| format!("Invalid time: {}", at))?),
+            }),
+            [only] => Ok(match time(only) {
+                Some(at) => TimePoint {
+                    day: None,
+                    time: Some(at),
+                },
+                None => TimePoint {
+                    day: Some(only.parse()?),
+                    time: None,
+                },
+            }),
+            _ => Err(format!("Expected a day and/or HH:MM, got {}", s)),
+        }
+    }
+}
+
+impl TimePoint {
+    fn date(&self, today: NaiveDate) -> NaiveDate {
+        match self.day {
+            Some(Day::Date(date)) => date,
+            Some(Day::Weekday(weekday)) => {
+                let days = weekday.days_since(today.weekday());
+                today + Duration::days(days as i64)
+            }
+            Some(Day::Offset(days)) => today + Duration::days(days),
+            None => today,
+        }
+    }
+
+    // The start of the day unless a time is given
+    pub fn start(&self, today: NaiveDate) -> NaiveDateTime {
+        self.date(today)
+            .and_time(self.time.unwrap_or(NaiveTime::MIN))
+    }
+
+    // The end of the day unless a time is given
+    pub fn end(&self, today: NaiveDate) -> NaiveDateTime {
+        match self.time {
+            Some(time) => self.date(today).and_time(time),
+            None => (self.date(today) + Duration::days(1)).and_time(NaiveTime::MIN),
+        }
+    }
+
+    // The end of a range starting at `from`, on the same day unless one is given, so
+    // "thu 09:00" to "18:00" is Thursday's working day
+    pub fn end_after(&self, from: NaiveDateTime, today: NaiveDate) -> NaiveDateTime {
+        let day = self.day.unwrap_or(Day::Date(from.date()));
+        TimePoint {
+            day: Some(day),
+            ..*self
+        }
+        .end(today)
+    }
+}
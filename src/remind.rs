@@ -0,0 +1,296 @@
+use crate::calendar::{capped_hours, capped_minutes, AgendaEntry};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Guards against INCLUDE cycles
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// A single REM line from a remind(1) file. Only the subset of the date
+// specification that matters for an agenda is understood: day, month, year,
+// weekdays, repeats (`*N`), FROM/UNTIL/THROUGH bounds, AT and DURATION
+#[derive(Debug, Default, PartialEq)]
+pub struct Reminder {
+    pub day: Option<u32>,
+    pub month: Option<u32>,
+    pub year: Option<i32>,
+    pub weekdays: Vec<Weekday>,
+    pub repeat: Option<i64>,
+    pub from: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub time: Option<NaiveTime>,
+    pub duration: Option<Duration>,
+    pub body: String,
+}
+
+impl Reminder {
+    fn base_date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year?, self.month?, self.day?)
+    }
+
+    // Whether the reminder is triggered on `date`
+    pub fn matches(&self, date: NaiveDate) -> bool {
+        if self.from.is_some_and(|from| date < from) || self.until.is_some_and(|u| date > u) {
+            return false;
+        }
+
+        if let (Some(repeat), Some(base)) = (self.repeat, self.base_date()) {
+            let days = (date - base).num_days();
+            return days >= 0 && days % repeat == 0;
+        }
+
+        if !self.weekdays.is_empty() && !self.weekdays.contains(&date.weekday()) {
+            return false;
+        }
+
+        // With a weekday, the day number means "the first matching weekday on or
+        // after that day", e.g. `REM Mon 8` is the second Monday of the month
+        let day_matches = match self.day {
+            Some(day) if !self.weekdays.is_empty() => (day..day + 7).contains(&date.day()),
+            Some(day) => date.day() == day,
+            None => true,
+        };
+
+        day_matches
+            && self.month.is_none_or(|m| date.month() == m)
+            && self.year.is_none_or(|y| date.year() == y)
+    }
+
+    // Occurrences of the reminder between the two dates, inclusive
+    pub fn entries(&self, first: NaiveDate, last: NaiveDate) -> Vec<AgendaEntry> {
+        first
+            .iter_days()
+            .take_while(|date| *date <= last)
+            .filter(|date| self.matches(*date))
+            .map(|date| {
+                let start = NaiveDateTime::new(date, self.time.unwrap_or(NaiveTime::MIN));
+                let duration = match self.time {
+                    Some(_) => self.duration.unwrap_or_else(Duration::zero),
+                    None => Duration::days(1),
+                };
+                let mut entry = AgendaEntry::new(self.body.clone(), start, duration);
+                entry.all_day = self.time.is_none();
+                entry
+            })
+            .collect()
+    }
+}
+
+// Month and weekday names may be abbreviated down to their first three letters
+fn abbreviates(token: &str, name: &str) -> bool {
+    token.len() >= 3 && name.starts_with(&token.to_ascii_lowercase())
+}
+
+fn parse_month(token: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    MONTHS
+        .iter()
+        .position(|m| abbreviates(token, m))
+        .map(|i| i as u32 + 1)
+}
+
+fn parse_weekday(token: &str) -> Option<Weekday> {
+    const WEEKDAYS: [&str; 7] = [
+        "monday",
+        "tuesday",
+        "wednesday",
+        "thursday",
+        "friday",
+        "saturday",
+        "sunday",
+    ];
+    WEEKDAYS
+        .iter()
+        .position(|d| abbreviates(token, d))
+        .and_then(|i| Weekday::try_from(i as u8).ok())
+}
+
+// ISO style dates (2024-05-01 or 2024/05/01), optionally followed by `@HH:MM`
+fn parse_iso_date(token: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    let (date, time) = match token.split_once('@') {
+        Some((date, time)) => (date, Some(parse_time(time)?)),
+        None => (token, None),
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y/%m/%d"))
+        .ok()?;
+    Some((date, time))
+}
+
+// Accepts 24h times (13:30, 13.30) as well as 12h ones (1:30pm, 1pm)
+fn parse_time(token: &str) -> Option<NaiveTime> {
+    let lower = token.to_ascii_lowercase();
+    let (clock, offset) = if let Some(t) = lower.strip_suffix("am") {
+        (t, Some(0))
+    } else if let Some(t) = lower.strip_suffix("pm") {
+        (t, Some(12))
+    } else {
+        (lower.as_str(), None)
+    };
+    let (hours, minutes) = match clock.split_once([':', '.']) {
+        Some((h, m)) => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+    let hours = match offset {
+        Some(offset) if (1..=12).contains(&hours) => hours % 12 + offset,
+        Some(_) => return None,
+        None => hours,
+    };
+    NaiveTime::from_hms_opt(hours, minutes, 0)
+}
+
+// DURATION accepts H:MM or a plain number of minutes, cut to the longest window
+fn parse_duration(token: &str) -> Option<Duration> {
+    match token.split_once(':') {
+        Some((h, m)) => Some(capped_hours(h.parse().ok()?) + capped_minutes(m.parse().ok()?)),
+        None => Some(capped_minutes(token.parse().ok()?)),
+    }
+}
+
+// Parse a date given as the remaining tokens (ISO, or day/month/year words)
+fn parse_date_tokens<'a>(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> Option<NaiveDate> {
+    if let Some((date, _)) = tokens.peek().and_then(|t| parse_iso_date(t)) {
+        tokens.next();
+        return Some(date);
+    }
+
+    let (mut day, mut month, mut year) = (None, None, None);
+    while let Some(token) = tokens.peek() {
+        if let Some(m) = parse_month(token) {
+            month = Some(m);
+        } else if let Ok(n) = token.parse::<i32>() {
+            if n > 31 {
+                year = Some(n);
+            } else {
+                day = Some(n as u32);
+            }
+        } else {
+            break;
+        }
+        tokens.next();
+    }
+    NaiveDate::from_ymd_opt(year?, month?, day?)
+}
+
+// Remind's escape sequences that would otherwise leak into the bar
+fn clean_body(body: &str) -> String {
+    body.replace("%\"", "")
+        .replace("%_", " ")
+        .replace("%%", "%")
+        .trim()
+        .to_owned()
+}
+
+// Parse a single `REM ...` line. Returns None for lines that aren't displayable
+// reminders (e.g. RUN or PS reminders) or whose date specification is not understood
+pub fn parse_rem(line: &str) -> Option<Reminder> {
+    let mut tokens = line.split_whitespace().peekable();
+    if !tokens.next()?.eq_ignore_ascii_case("rem") {
+        return None;
+    }
+
+    // Byte offset of a token within the line, used to slice out the body verbatim
+    let offset = |token: &str| token.as_ptr() as usize - line.as_ptr() as usize;
+    let mut reminder = Reminder::default();
+    let mut body_start = None;
+
+    while let Some(token) = tokens.next() {
+        match token.to_ascii_uppercase().as_str() {
+            "MSG" | "MSF" | "CAL" => {
+                body_start = Some(offset(token) + token.len());
+                break;
+            }
+            "RUN" | "PS" | "PSFILE" | "SPECIAL" | "SATISFY" => return None,
+            "AT" => reminder.time = Some(parse_time(tokens.next()?)?),
+            "DURATION" => reminder.duration = Some(parse_duration(tokens.next()?)?),
+            "UNTIL" => reminder.until = Some(parse_date_tokens(&mut tokens)?),
+            "FROM" => reminder.from = Some(parse_date_tokens(&mut tokens)?),
+            "THROUGH" => {
+                reminder.until = Some(parse_date_tokens(&mut tokens)?);
+                reminder.repeat = Some(1);
+            }
+            "SCHED" | "WARN" | "OMITFUNC" | "PRIORITY" | "TAG" | "INFO" => {
+                tokens.next();
+            }
+            "ONCE" | "SKIP" | "BEFORE" | "AFTER" | "OMIT" => {}
+            _ => {
+                if let Some((date, time)) = parse_iso_date(token) {
+                    reminder.year = Some(date.year());
+                    reminder.month = Some(date.month());
+                    reminder.day = Some(date.day());
+                    reminder.time = time.or(reminder.time);
+                } else if let Some(month) = parse_month(token) {
+                    reminder.month = Some(month);
+                } else if let Some(weekday) = parse_weekday(token) {
+                    reminder.weekdays.push(weekday);
+                } else if let Some(repeat) = token.strip_prefix('*') {
+                    reminder.repeat = Some(repeat.parse().ok().filter(|r| *r > 0)?);
+                } else if token.starts_with(['+', '-']) {
+                    // Advance warnings and back-scanning don't affect when it happens
+                } else if let Ok(n) = token.parse::<i32>() {
+                    if n > 31 {
+                        reminder.year = Some(n);
+                    } else {
+                        reminder.day = Some(n as u32);
+                    }
+                } else {
+                    // Remind treats a missing type keyword as MSG
+                    body_start = Some(offset(token));
+                    break;
+                }
+            }
+        }
+    }
+
+    reminder.body = clean_body(&line[body_start?..]);
+    Some(reminder)
+}
+
+fn parse_file(path: &Path, depth: usize, reminders: &mut Vec<Reminder>) -> io::Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    // Lines ending with a backslash continue on the next line
+    let joined = contents.replace("\\\n", " ");
+    for line in joined.lines().map(str::trim) {
+        if line.starts_with(['#', ';']) {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        match words.next().map(str::to_ascii_uppercase).as_deref() {
+            Some("INCLUDE") if depth < MAX_INCLUDE_DEPTH => {
+                if let Some(file) = words.next() {
+                    let file = PathBuf::from(file.trim_matches('"'));
+                    parse_file(&dir.join(file), depth + 1, reminders)?;
+                }
+            }
+            Some("REM") => reminders.extend(parse_rem(line)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Read a remind file, following INCLUDE directives relative to the including file
+pub fn load(path: &Path) -> io::Result<Vec<Reminder>> {
+    let mut reminders = Vec::new();
+    parse_file(path, 0, &mut reminders)?;
+    Ok(reminders)
+}
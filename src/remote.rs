@@ -0,0 +1,391 @@
+use crate::auth;
+use crate::cache::CachedResponse;
+use crate::netrc;
+use crate::sources::{LoadOptions, SourceError};
+use std::{
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use ureq::{
+    http, http::Response, typestate::WithoutBody, AsSendBody, Body, RequestBuilder, RequestExt,
+    ResponseExt,
+};
+
+// Feeds bigger than this are almost certainly not calendars
+pub const MAX_BODY: u64 = 64 * 1024 * 1024;
+
+// The longest single pause between retries, and the most they may wait in all, so a
+// generous retry count can't stall the bar for minutes
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+// Timeouts and retries of the requests to calendar servers, so a slow or flaky
+// server can't hold up the bar
+#[derive(Clone, Copy, Debug)]
+pub struct NetworkOptions {
+    pub connect_timeout: Duration,
+    // For the response to start arriving, and again for its body
+    pub read_timeout: Duration,
+    // Further attempts after a failure that may go away by itself
+    pub retries: u32,
+    // Waited before the first retry, doubled before every further one up to MAX_BACKOFF
+    pub backoff: Duration,
+}
+
+impl Default for NetworkOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(3),
+            read_timeout: Duration::from_secs(5),
+            retries: 1,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+// Failures worth another try: the connection, timeouts and overloaded servers. A host
+// that can't be looked up almost always means being offline, which a retry won't fix
+fn is_transient(e: &ureq::Error) -> bool {
+    match e {
+        ureq::Error::StatusCode(code) => *code == 408 || *code == 429 || *code >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::BodyStalled => true,
+        _ => false,
+    }
+}
+
+// Make a request, trying again with growing pauses in between while it fails for a
+// transient reason, until the pauses would add up to more than MAX_RETRY_WAIT
+pub fn retry<T>(
+    network: &NetworkOptions,
+    mut attempt: impl FnMut() -> Result<T, ureq::Error>,
+) -> Result<T, ureq::Error> {
+    let mut backoff = network.backoff.min(MAX_BACKOFF);
+    let mut waited = Duration::ZERO;
+    for _ in 0..network.retries {
+        match attempt() {
+            Err(e) if is_transient(&e) && waited + backoff <= MAX_RETRY_WAIT => {
+                thread::sleep(backoff);
+                waited += backoff;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            result => return result,
+        }
+    }
+    attempt()
+}
+
+// A GET request with the configured timeouts
+pub fn get(url: &str, network: &NetworkOptions) -> RequestBuilder<WithoutBody> {
+    ureq::get(url)
+        .config()
+        .timeout_connect(Some(network.connect_timeout))
+        .timeout_recv_response(Some(network.read_timeout))
+        .timeout_recv_body(Some(network.read_timeout))
+        .build()
+}
+
+pub fn is_url(path: &str) -> bool {
+    ["https://", "http://", "webcal://", "webcals://"]
+        .iter()
+        .any(|scheme| path.starts_with(scheme))
+}
+
+// Calendar share links often use the webcal scheme, which is just HTTP(S) underneath.
+// Nearly every server behind one speaks HTTPS, so that's what it's fetched with
+pub fn http_url(url: &str) -> String {
+    match url
+        .strip_prefix("webcal://")
+        .or_else(|| url.strip_prefix("webcals://"))
+    {
+        Some(rest) => format!("https://{}", rest),
+        None => url.to_owned(),
+    }
+}
+
+pub fn http_error(url: &str, e: ureq::Error) -> SourceError {
+    match e {
+        ureq::Error::StatusCode(code) => status_error(url, code),
+        e => SourceError::Http(url.to_owned(), e.to_string()),
+    }
+}
+
+fn status_error(url: &str, code: u16) -> SourceError {
+    SourceError::Status(url.to_owned(), code)
+}
+
+// Escape everything but the unreserved characters, for use in a URL path segment
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 10;
+
+// Statuses are left to the caller, except for those worth retrying
+fn run(
+    request: http::Request<impl AsSendBody>,
+    network: &NetworkOptions,
+) -> Result<Response<Body>, ureq::Error> {
+    let response = request
+        .with_default_agent()
+        .configure()
+        .http_status_as_error(false)
+        .allow_non_standard_methods(true)
+        .max_redirects(0)
+        .timeout_connect(Some(network.connect_timeout))
+        .timeout_recv_response(Some(network.read_timeout))
+        .timeout_recv_body(Some(network.read_timeout))
+        .run()?;
+    let status = response.status().as_u16();
+    if is_transient(&ureq::Error::StatusCode(status)) {
+        return Err(ureq::Error::StatusCode(status));
+    }
+    Ok(response)
+}
+
+// The scheme, host and port a URL is sent to, which credentials are tied to
+fn origin(url: &str) -> Option<(String, String, u16)> {
+    let uri: http::Uri = url.parse().ok()?;
+    let scheme = uri.scheme_str()?.to_ascii_lowercase();
+    let port = uri
+        .port_u16()
+        .unwrap_or(if scheme == "https" { 443 } else { 80 });
+    Some((scheme, uri.host()?.to_ascii_lowercase(), port))
+}
+
+// Resolve a link found at `base`, either a full URL, an absolute path or a relative one
+pub fn resolve_url(base: &str, reference: &str) -> String {
+    if is_url(reference) {
+        return reference.to_owned();
+    }
+    let (scheme, rest) = base.split_once("://").unwrap_or(("https", base));
+    let authority = rest.split('/').next().unwrap_or(rest);
+    if reference.starts_with('/') {
+        return format!("{}://{}{}", scheme, authority, reference);
+    }
+    let path = rest[authority.len()..]
+        .split(['?', '#'])
+        .next()
+        .unwrap_or("");
+    let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+    let dir = if dir.is_empty() { "/" } else { dir };
+    format!("{}://{}{}{}", scheme, authority, dir, reference)
+}
+
+// Send a request to a calendar server, following redirects with the same method (so
+// WebDAV requests survive them). When the server asks for credentials, the configured
+// ones (or else those for the host in ~/.netrc) are sent with Basic or Digest
+// authentication, whichever it prefers. The configured ones only go to the server of
+// `url` itself, never to another one it redirects to. Transient failures are retried.
+// Error statuses are turned into errors, 304 is left to the caller
+pub fn send(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+    opts: &LoadOptions,
+) -> Result<Response<Body>, SourceError> {
+    let attempt = |uri: &str, authorization: Option<&str>| {
+        let mut request = http::Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+        match body {
+            Some(body) => run(request.body(body)?, &opts.network),
+            None => run(request.body(())?, &opts.network),
+        }
+    };
+
+    let mut uri = http_url(url);
+    let own_origin = origin(&uri);
+    let mut authorization = None;
+    for _ in 0..MAX_REDIRECTS {
+        let response = retry(&opts.network, || attempt(&uri, authorization.as_deref()))
+            .map_err(|e| http_error(url, e))?;
+        let status = response.status();
+        let header = |name| response.headers().get(name).and_then(|v| v.to_str().ok());
+
+        if status.is_redirection() && status != 304 {
+            let location = header("location").ok_or_else(|| status_error(url, status.as_u16()))?;
+            uri = resolve_url(&uri, location);
+            // Credentials are only sent where they're asked for
+            authorization = None;
+            continue;
+        }
+        let from_netrc;
+        let same_server =
+            own_origin.is_some() && own_origin == origin(&response.get_uri().to_string());
+        let credentials = match &opts.credentials {
+            Some(credentials) if same_server => Some(credentials),
+            _ if status == 401 => {
+                from_netrc = response.get_uri().host().and_then(netrc::credentials);
+                from_netrc.as_ref()
+            }
+            _ => None,
+        };
+        if let (401, None, Some(credentials)) = (status.as_u16(), &authorization, credentials) {
+            let challenges = response
+                .headers()
+                .get_all("www-authenticate")
+                .iter()
+                .filter_map(|value| value.to_str().ok());
+            let path = response
+                .get_uri()
+                .path_and_query()
+                .map_or("/", |p| p.as_str());
+            authorization = auth::authorization(challenges, credentials, method, path);
+            if authorization.is_some() {
+                continue;
+            }
+        }
+        if status.is_client_error() || status.is_server_error() {
+            return Err(status_error(url, status.as_u16()));
+        }
+        return Ok(response);
+    }
+    Err(SourceError::Http(
+        url.to_owned(),
+        "too many redirects".to_owned(),
+    ))
+}
+
+pub fn read_body(url: &str, response: &mut Response<Body>) -> Result<Vec<u8>, SourceError> {
+    response
+        .body_mut()
+        .with_config()
+        .limit(MAX_BODY)
+        .read_to_vec()
+        .map_err(|e| http_error(url, e))
+}
+
+// A downloaded calendar, or the copy kept from an earlier download
+#[derive(Debug)]
+pub struct Fetched {
+    pub body: Vec<u8>,
+    // When the cached copy used instead was downloaded, in seconds since the epoch, if
+    // the server couldn't be reached or wasn't asked
+    pub stale: Option<u64>,
+}
+
+// GET the calendar, asking the server whether `cached` is still current. None if it is
+fn download(
+    url: &str,
+    cached: Option<&CachedResponse>,
+    opts: &LoadOptions,
+) -> Result<Option<CachedResponse>, SourceError> {
+    let mut headers = Vec::new();
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            headers.push(("If-None-Match", etag.as_str()));
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            headers.push(("If-Modified-Since", last_modified.as_str()));
+        }
+    }
+    let mut response = send("GET", url, &headers, None, opts)?;
+    if response.status() == 304 && cached.is_some() {
+        return Ok(None);
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let etag = header("etag");
+    let last_modified = header("last-modified");
+    Ok(Some(CachedResponse {
+        url: url.to_owned(),
+        etag,
+        last_modified,
+        body: read_body(url, &mut response)?,
+        fetched_at: unix_now(),
+    }))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Download a calendar. With a cache, a copy younger than the source's max age is used
+// without asking the server at all. Otherwise the server is asked whether the previous
+// response is still current and the cached body is used if it is. The cached body
+// is also used when the server can't be reached or fails, e.g. while offline, so the
+// agenda keeps showing the last known events, but not when it refuses the request,
+// e.g. for a calendar that was removed or credentials that no longer work. Offline,
+// only the cached body is used, and a calendar never downloaded comes back empty
+pub fn fetch(url: &str, opts: &LoadOptions) -> Result<Fetched, SourceError> {
+    let cache = opts.http_cache.as_ref();
+    let cached = cache.and_then(|cache| cache.load(url));
+    if opts.offline {
+        return Ok(cached.map_or_else(
+            || Fetched {
+                body: Vec::new(),
+                stale: None,
+            },
+            |cached| Fetched {
+                body: cached.body,
+                stale: Some(cached.fetched_at),
+            },
+        ));
+    }
+    if let (Some(cached), Some(max_age)) = (&cached, opts.max_age) {
+        if unix_now().saturating_sub(cached.fetched_at) < max_age.as_secs() {
+            return Ok(Fetched {
+                body: cached.body.clone(),
+                stale: None,
+            });
+        }
+    }
+    match download(url, cached.as_ref(), opts) {
+        Ok(Some(response)) => {
+            if let Some(cache) = cache {
+                cache.store(&response);
+            }
+            Ok(Fetched {
+                body: response.body,
+                stale: None,
+            })
+        }
+        Ok(None) => {
+            let mut cached = cached.unwrap_or_default();
+            // Confirmed to be current, which starts the max age over
+            cached.fetched_at = unix_now();
+            if let Some(cache) = cache {
+                cache.store(&cached);
+            }
+            Ok(Fetched {
+                body: cached.body,
+                stale: None,
+            })
+        }
+        Err(SourceError::Status(url, code)) if !is_transient(&ureq::Error::StatusCode(code)) => {
+            Err(SourceError::Status(url, code))
+        }
+        Err(e) => match cached {
+            Some(cached) => Ok(Fetched {
+                body: cached.body,
+                stale: Some(cached.fetched_at),
+            }),
+            None => Err(e),
+        },
+    }
+}
@@ -0,0 +1,625 @@
+use crate::auth::Credentials;
+use crate::cache::{self, ParseCache, ResponseCache, Stamp};
+use crate::caldav;
+use crate::calendar::{calendar_records, record_entries, AgendaEntry, EventRecord};
+use crate::eds;
+use crate::fallback;
+use crate::google;
+use crate::graph;
+use crate::keyring;
+use crate::oauth::OAuthClient;
+use crate::org::{self, OrgItem};
+use crate::remind::{self, Reminder};
+use crate::remote::{self, NetworkOptions};
+use crate::thunderbird;
+use chrono::{DateTime, Local};
+use chrono_tz::Tz;
+use encoding_rs::{Encoding, WINDOWS_1252};
+use flate2::read::MultiGzDecoder;
+use icalendar::Calendar;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use std::{
+    env, fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration as StdDuration, Instant},
+};
+
+#[derive(Debug)]
+pub enum SourceError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    // An invalid glob pattern, or one that matches nothing
+    Pattern(String, String),
+    Http(String, String),
+    // The server answered with an error status
+    Status(String, u16),
+    // Signing in to an online calendar service failed
+    Auth(String, String),
+    // A local calendar service, e.g. one on D-Bus, failed
+    Service(String, String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SourceError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            SourceError::Pattern(pattern, e) => write!(f, "{}: {}", pattern, e),
+            SourceError::Http(url, e) => write!(f, "{}: {}", url, e),
+            SourceError::Status(url, code) => {
+                write!(f, "{}: server responded with HTTP {}", url, code)
+            }
+            SourceError::Auth(provider, e) => write!(f, "{}: {}", provider, e),
+            SourceError::Service(service, e) => write!(f, "{}: {}", service, e),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+// The parsed contents of a single source file
+pub enum Document {
+    Ics(Vec<EventRecord>),
+    Remind(Vec<Reminder>),
+    Org(Vec<OrgItem>),
+}
+
+impl Document {
+    pub fn entries(&self, sod: DateTime<Local>, eod: DateTime<Local>) -> Vec<AgendaEntry> {
+        match self {
+            Document::Ics(events) => record_entries(events, sod, eod),
+            Document::Remind(reminders) => reminders
+                .iter()
+                .flat_map(|r| r.entries(sod.date_naive(), eod.date_naive()))
+                .collect(),
+            Document::Org(items) => items
+                .iter()
+                .flat_map(|i| i.entries(sod.date_naive(), eod.date_naive()))
+                .collect(),
+        }
+    }
+}
+
+// A group of documents sharing a display name and color, e.g. a vdir collection
+pub struct Collection {
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub documents: Vec<Document>,
+    // Downloaded earlier because the source couldn't be reached (or wasn't, offline):
+    // when, in seconds since the epoch
+    pub stale: Option<u64>,
+    // Short tag and icon of the source, shown in front of its entries
+    pub label: Option<String>,
+    pub icon: Option<String>,
+}
+
+impl Collection {
+    pub fn entries(&self, sod: DateTime<Local>, eod: DateTime<Local>) -> Vec<AgendaEntry> {
+        self.documents
+            .iter()
+            .flat_map(|document| document.entries(sod, eod))
+            .map(|mut entry| {
+                entry.calendar = self.name.clone();
+                entry.color = self.color.clone();
+                entry.label = self.label.clone();
+                entry.icon = self.icon.clone();
+                entry
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct LoadOptions {
+    // Reuse the events parsed by an earlier run when a file hasn't changed
+    pub cache: Option<ParseCache>,
+    // Ask servers whether a downloaded calendar changed instead of fetching it again
+    pub http_cache: Option<ResponseCache>,
+    // Sent to servers that require authentication
+    pub credentials: Option<Credentials>,
+    // Name the source's password is stored under in the keyring
+    pub keyring: Option<String>,
+    pub network: NetworkOptions,
+    // How long a downloaded calendar is used before asking the server again
+    pub max_age: Option<StdDuration>,
+    // Use cached downloads only, without any network requests
+    pub offline: bool,
+    // Client used to sign in to online calendar services
+    pub oauth_client: Option<OAuthClient>,
+    // Events wanted from services that are queried for a time range
+    pub window: Option<(DateTime<Local>, DateTime<Local>)>,
+    // Display names of the calendars to show from a server, all if empty
+    pub calendars: Vec<String>,
+    // Display name and color given to the source's collections in place of their own
+    pub name: Option<String>,
+    pub color: Option<String>,
+    // Tag (e.g. "W" for work) and icon marking the entries of the source
+    pub label: Option<String>,
+    pub icon: Option<String>,
+    // Zone of floating times, local time if unset
+    pub timezone: Option<Tz>,
+    // Encoding of files that aren't UTF-8
+    pub encoding: Option<&'static Encoding>,
+}
+
+impl LoadOptions {
+    // Whether a calendar found on a server was picked with --calendar
+    pub fn wants_calendar(&self, name: Option<&str>) -> bool {
+        self.calendars.is_empty()
+            || name.is_some_and(|name| self.calendars.iter().any(|c| c.eq_ignore_ascii_case(name)))
+    }
+}
+
+// Files with a byte order mark are decoded accordingly. Otherwise they're read in the
+// configured encoding, or as UTF-8 with a fallback to Windows-1252 (a superset of
+// Latin-1) for legacy exports
+pub fn decode(bytes: &[u8], encoding: Option<&'static Encoding>) -> String {
+    if let Some((bom_encoding, _)) = Encoding::for_bom(bytes) {
+        return bom_encoding.decode_with_bom_removal(bytes).0.into_owned();
+    }
+    match (encoding, std::str::from_utf8(bytes)) {
+        (None, Ok(text)) => text.to_owned(),
+        (encoding, _) => encoding
+            .unwrap_or(WINDOWS_1252)
+            .decode_without_bom_handling(bytes)
+            .0
+            .into_owned(),
+    }
+}
+
+// Normalize the events of an ics document, `path` is only used in errors
+pub fn parse_calendar(
+    text: &str,
+    path: &Path,
+    opts: &LoadOptions,
+) -> Result<Vec<EventRecord>, SourceError> {
+    match text.parse::<Calendar>() {
+        Ok(calendar) => Ok(calendar_records(&calendar, opts.timezone)),
+        // Salvage what we can from files the strict parser rejects
+        Err(e) => {
+            let events = fallback::parse(text, opts.timezone);
+            if events.is_empty() {
+                return Err(SourceError::Parse(path.to_owned(), e));
+            }
+            Ok(events)
+        }
+    }
+}
+
+// Gzip compressed calendars, such as archived exports or a `.ics.gz` served as is, are
+// recognized by their magic bytes. Responses sent with `Content-Encoding: gzip` are
+// already decompressed by ureq
+pub fn decompress(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes);
+    }
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(bytes.as_slice())
+        .take(remote::MAX_BODY)
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+fn read_calendar(path: &Path, opts: &LoadOptions) -> Result<Vec<EventRecord>, SourceError> {
+    let stamp = opts.cache.as_ref().and_then(|_| cache::stamp(path));
+    let unchanged = opts
+        .cache
+        .as_ref()
+        .zip(stamp)
+        .and_then(|(cache, stamp)| cache.load_unchanged(path, stamp, &cache_settings(opts)));
+    if let Some(events) = unchanged {
+        return Ok(events);
+    }
+    let bytes = fs::read(path)
+        .and_then(decompress)
+        .map_err(|e| SourceError::Io(path.to_owned(), e))?;
+    parse_cached(&decode(&bytes, opts.encoding), path, stamp, opts)
+}
+
+// Events read in another encoding or parsed with a different default timezone can't
+// be reused
+fn cache_settings(opts: &LoadOptions) -> String {
+    format!(
+        "{};{}",
+        opts.encoding.map_or("", |encoding| encoding.name()),
+        opts.timezone.map_or("", |tz| tz.name())
+    )
+}
+
+// Parse a calendar, or reuse the events parsed from the same text before
+fn parse_cached(
+    text: &str,
+    path: &Path,
+    stamp: Option<Stamp>,
+    opts: &LoadOptions,
+) -> Result<Vec<EventRecord>, SourceError> {
+    let settings = cache_settings(opts);
+    if let Some(events) = opts
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.load(path, text, &settings))
+    {
+        return Ok(events);
+    }
+
+    let events = parse_calendar(text, path, opts)?;
+    if let Some(cache) = &opts.cache {
+        cache.store(path, stamp, text, &settings, &events);
+    }
+    Ok(events)
+}
+
+// remind(1) files are conventionally named `~/.reminders` or end in `.rem`
+fn is_remind_file(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    name == ".reminders"
+        || path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("rem"))
+}
+
+fn is_org_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("org"))
+}
+
+fn read_document(path: &Path, opts: &LoadOptions) -> Result<Document, SourceError> {
+    if is_remind_file(path) {
+        return remind::load(path)
+            .map(Document::Remind)
+            .map_err(|e| SourceError::Io(path.to_owned(), e));
+    }
+    if is_org_file(path) {
+        return org::load(path)
+            .map(Document::Org)
+            .map_err(|e| SourceError::Io(path.to_owned(), e));
+    }
+    read_calendar(path, opts).map(Document::Ics)
+}
+
+// Read a vdir metadata file (`displayname`, `color`), ignoring it when missing or empty
+fn read_metadata(dir: &Path, name: &str) -> Option<String> {
+    let value = fs::read_to_string(dir.join(name)).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+// vdirsyncer keeps temporary files as dotfiles while writing, so skip those.
+// Compressed `.ics.gz` files are read as well
+fn is_ics_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let hidden = name.is_empty() || name.starts_with('.');
+    let ics = name.ends_with(".ics") || name.ends_with(".ics.gz");
+    !hidden && ics && path.is_file()
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, SourceError> {
+    let mut paths = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|e| e.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .map_err(|e| SourceError::Io(dir.to_owned(), e))?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn load_collection(dir: &Path, opts: &LoadOptions) -> Result<Collection, SourceError> {
+    // vdirs hold one file per event, so parse them across all cores
+    let documents = list_dir(dir)?
+        .par_iter()
+        .filter(|p| is_ics_file(p))
+        .map(|p| read_calendar(p, opts).map(Document::Ics))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let name = read_metadata(dir, "displayname").or_else(|| {
+        dir.file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.to_owned())
+    });
+
+    Ok(Collection {
+        name,
+        color: read_metadata(dir, "color"),
+        documents,
+        stale: None,
+        label: None,
+        icon: None,
+    })
+}
+
+// Guards against symlink loops when looking for collections
+const MAX_DEPTH: usize = 8;
+
+// Directories below `dir` (including itself) that directly hold ics files
+pub fn find_collections(dir: &Path, depth: usize) -> Result<Vec<PathBuf>, SourceError> {
+    let children = list_dir(dir)?;
+    let mut collections = Vec::new();
+    if children.iter().any(|p| is_ics_file(p)) {
+        collections.push(dir.to_owned());
+    }
+    if depth < MAX_DEPTH {
+        for child in &children {
+            let hidden = child
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_none_or(|n| n.starts_with('.'));
+            if child.is_dir() && !hidden {
+                collections.extend(find_collections(child, depth + 1)?);
+            }
+        }
+    }
+    Ok(collections)
+}
+
+// Load the calendars found at `path`, which may be a single ics or remind file, a vdir collection
+// (a directory of ics files) or any directory tree of collections, such as vdirsyncer's
+// `~/.calendars/` holding one collection per subdirectory
+pub fn load(path: &Path, opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+    if !path.is_dir() {
+        return Ok(vec![Collection {
+            name: None,
+            color: None,
+            documents: vec![read_document(path, opts)?],
+            stale: None,
+            label: None,
+            icon: None,
+        }]);
+    }
+
+    let collections = find_collections(path, 0)?;
+    if collections.is_empty() {
+        return Ok(vec![load_collection(path, opts)?]);
+    }
+
+    collections
+        .par_iter()
+        .map(|dir| load_collection(dir, opts))
+        .collect()
+}
+
+// Expand a leading `~/` and glob patterns such as `~/calendars/**/*.ics`, so they work
+// quoted or without a shell that understands `**`. Plain paths are kept as they are
+pub fn resolve(path: &str) -> Result<Vec<PathBuf>, SourceError> {
+    let path = match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    };
+    let pattern = path.to_string_lossy();
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+
+    let invalid = |e: String| SourceError::Pattern(pattern.to_string(), e);
+    let paths = glob::glob(&pattern)
+        .map_err(|e| invalid(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+    if paths.is_empty() {
+        return Err(invalid("no files match".to_owned()));
+    }
+    Ok(paths)
+}
+
+// The files and directories a source reads, for watching them for changes. Piped,
+// downloaded and service calendars have none
+pub fn local_paths(path: &str) -> Vec<PathBuf> {
+    let schemes = [
+        caldav::SCHEME,
+        eds::SCHEME,
+        graph::SCHEME,
+        google::SCHEME,
+        thunderbird::SCHEME,
+        EXEC,
+    ];
+    if path == STDIN || remote::is_url(path) || schemes.iter().any(|s| path.starts_with(s)) {
+        return Vec::new();
+    }
+    resolve(path).unwrap_or_default()
+}
+
+// Path standing for an ics calendar piped into the module
+pub const STDIN: &str = "-";
+
+// A calendar that isn't read from a file, e.g. piped in or downloaded
+fn unnamed_calendar(
+    bytes: Vec<u8>,
+    label: &str,
+    opts: &LoadOptions,
+) -> Result<Collection, SourceError> {
+    let bytes = decompress(bytes).map_err(|e| SourceError::Io(PathBuf::from(label), e))?;
+    let text = decode(&bytes, opts.encoding);
+    let events = parse_cached(&text, Path::new(label), None, opts)?;
+    Ok(Collection {
+        name: None,
+        color: None,
+        documents: vec![Document::Ics(events)],
+        stale: None,
+        label: None,
+        icon: None,
+    })
+}
+
+// Prefix of a command that prints an ics calendar, e.g. `exec:gcalcli agenda --ics`
+pub const EXEC: &str = "exec:";
+
+// How long a command may take before it's given up on, so a hung one can't hold up
+// the bar
+const COMMAND_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+// Run a command to the end, killing it once it has taken longer than `timeout`
+pub fn run_command(command: &mut Command, timeout: StdDuration) -> io::Result<Output> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read while it runs, so a command printing more than a pipe holds isn't stalled
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut bytes);
+            }
+            bytes
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {} seconds", timeout.as_secs_f32()),
+            ));
+        }
+        thread::sleep(StdDuration::from_millis(10));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+// Run a command through `sh -c` and read the calendar it prints. The agenda window is
+// passed in AGENDA_START and AGENDA_END, for commands that can limit their output
+fn load_command(command: &str, label: &str, opts: &LoadOptions) -> Result<Collection, SourceError> {
+    let mut child = Command::new("sh");
+    child.arg("-c").arg(command).stdin(Stdio::null());
+    if let Some((start, end)) = opts.window {
+        child
+            .env("AGENDA_START", start.to_rfc3339())
+            .env("AGENDA_END", end.to_rfc3339());
+    }
+    let output = run_command(&mut child, COMMAND_TIMEOUT)
+        .map_err(|e| SourceError::Io(PathBuf::from(label), e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SourceError::Service(
+            command.to_owned(),
+            format!("{} {}", output.status, stderr.trim()),
+        ));
+    }
+    unnamed_calendar(output.stdout, label, opts)
+}
+
+// Read an ics calendar from `input`, e.g. `gcal-export | polybar-agenda -`
+pub fn load_reader(input: &mut impl Read, opts: &LoadOptions) -> Result<Collection, SourceError> {
+    let mut bytes = Vec::new();
+    input
+        .read_to_end(&mut bytes)
+        .map_err(|e| SourceError::Io(PathBuf::from(STDIN), e))?;
+    unnamed_calendar(bytes, STDIN, opts)
+}
+
+// A single source: piped in, an online service, a URL or local files
+fn load_source(path: &str, opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+    if path == STDIN {
+        return Ok(vec![load_reader(&mut io::stdin().lock(), opts)?]);
+    }
+    if let Some(command) = path.strip_prefix(EXEC) {
+        return Ok(vec![load_command(command, path, opts)?]);
+    }
+    let online_service = [caldav::SCHEME, graph::SCHEME, google::SCHEME]
+        .iter()
+        .any(|scheme| path.starts_with(scheme));
+    // Nothing of theirs is kept to show offline, and failing would hide the others
+    if opts.offline && online_service {
+        return Ok(Vec::new());
+    }
+    if let Some(url) = path.strip_prefix(caldav::SCHEME) {
+        return caldav::load(url, opts);
+    }
+    if path == eds::SCHEME {
+        return eds::load(opts);
+    }
+    if let Some(profile) = path.strip_prefix(thunderbird::SCHEME) {
+        return thunderbird::load(profile, opts);
+    }
+    if let Some(user) = path.strip_prefix(graph::SCHEME) {
+        return Ok(vec![graph::load(user, opts)?]);
+    }
+    if let Some(calendar) = path.strip_prefix(google::SCHEME) {
+        return Ok(vec![google::load(calendar, opts)?]);
+    }
+    if remote::is_url(path) {
+        let fetched = remote::fetch(path, opts)?;
+        // Offline without a cached copy there's nothing to show, like an online service
+        if opts.offline && fetched.body.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut collection = unnamed_calendar(fetched.body, path, opts)?;
+        collection.stale = fetched.stale;
+        return Ok(vec![collection]);
+    }
+    let mut collections = Vec::new();
+    for path in resolve(path)? {
+        collections.extend(load(&path, opts)?);
+    }
+    Ok(collections)
+}
+
+// Sources loaded at the same time. Most of the time is spent waiting for servers, so
+// this is independent of the number of cores
+const MAX_CONCURRENT_SOURCES: usize = 8;
+
+// Load several sources, each with its own options, into one list of collections.
+// Sources are loaded concurrently, so a refresh takes about as long as the slowest one
+pub fn load_all(
+    sources: &[(impl AsRef<str> + Sync, LoadOptions)],
+) -> Result<Vec<Collection>, SourceError> {
+    let load = || {
+        sources
+            .par_iter()
+            .map(|(path, opts)| {
+                let with_password;
+                let opts = match keyring::credentials(opts)? {
+                    Some(credentials) => {
+                        with_password = LoadOptions {
+                            credentials: Some(credentials),
+                            ..opts.clone()
+                        };
+                        &with_password
+                    }
+                    None => opts,
+                };
+                let mut collections = load_source(path.as_ref(), opts)?;
+                for collection in &mut collections {
+                    if opts.name.is_some() {
+                        collection.name = opts.name.clone();
+                    }
+                    if opts.color.is_some() {
+                        collection.color = opts.color.clone();
+                    }
+                    collection.label = opts.label.clone();
+                    collection.icon = opts.icon.clone();
+                }
+                Ok(collections)
+            })
+            .collect::<Result<Vec<_>, SourceError>>()
+    };
+    let loaded = match ThreadPoolBuilder::new()
+        .num_threads(MAX_CONCURRENT_SOURCES)
+        .build()
+    {
+        Ok(pool) => pool.install(load)?,
+        Err(_) => load()?,
+    };
+    Ok(loaded.into_iter().flatten().collect())
+}
@@ -0,0 +1,267 @@
+use crate::calendar::{
+    description_snippet, elapsed_fraction, format_collection_entry, format_duration, AgendaEntry,
+    DayLabel, DisplayOptions,
+};
+use crate::i18n::{humanize_remaining, humanize_until};
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDateTime,
+};
+use serde::Serialize;
+use std::{error::Error, fs, path::Path, str::FromStr};
+
+// How times are passed to template files, which Tera's `date` filter reads
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Field {
+    Name,
+    // With a strftime format, or the time of day on the configured clock
+    Start(Option<String>),
+    End(Option<String>),
+    // "tomorrow", the weekday or the date, empty for today
+    Day,
+    Duration,
+    // Until the start, or until the end once the entry has started
+    Countdown,
+    // Percent of the entry that has passed, 0 until it starts
+    Progress,
+    Location,
+    // The description's first line
+    Description,
+    Organizer,
+    // How many are invited
+    Attendees,
+    // The source's label, or the name of the calendar
+    Source,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+// A `--format` template like "{start:%H:%M} {name} ({countdown})", replacing the display
+// mode's layout of an entry. `{{` and `}}` are literal braces
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryTemplate(Vec<Segment>);
+
+fn time_format(format: Option<&str>) -> Result<Option<String>, String> {
+    match format {
+        Some(format) if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) => {
+            Err(format!("Invalid time format: {}", format))
+        }
+        format => Ok(format.map(str::to_owned)),
+    }
+}
+
+fn format_time(time: NaiveDateTime, format: &Option<String>, opts: &DisplayOptions) -> String {
+    match format {
+        Some(format) => time.format(format).to_string(),
+        None => opts.clock.format(time.time()),
+    }
+}
+
+// Whole percent, so that it only reaches 100 at the end
+fn progress(entry: &AgendaEntry, when: NaiveDateTime) -> u8 {
+    (elapsed_fraction(entry, when) * 100.0).floor() as u8
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, format) = match s.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (s, None),
+        };
+        match (name, format) {
+            ("start", format) => Ok(Field::Start(time_format(format)?)),
+            ("end", format) => Ok(Field::End(time_format(format)?)),
+            ("name", None) => Ok(Field::Name),
+            ("day", None) => Ok(Field::Day),
+            ("duration", None) => Ok(Field::Duration),
+            ("countdown" | "until", None) => Ok(Field::Countdown),
+            ("progress", None) => Ok(Field::Progress),
+            ("location", None) => Ok(Field::Location),
+            ("description", None) => Ok(Field::Description),
+            ("organizer", None) => Ok(Field::Organizer),
+            ("attendees", None) => Ok(Field::Attendees),
+            ("source", None) => Ok(Field::Source),
+            _ => Err(format!("Unknown placeholder: {{{}}}", s)),
+        }
+    }
+}
+
+impl FromStr for EntryTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("Unclosed placeholder in {}", s))?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(rest[..end].parse()?));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("Unmatched }} in {}", s)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(EntryTemplate(segments))
+    }
+}
+
+impl Field {
+    fn render(&self, opts: &DisplayOptions, entry: &AgendaEntry, when: NaiveDateTime) -> String {
+        let end = entry.start + entry.duration;
+        match self {
+            Field::Name => entry.name.clone(),
+            Field::Start(format) => format_time(entry.start, format, opts),
+            Field::End(format) => format_time(end, format, opts),
+            Field::Day => DayLabel::Relative
+                .label(entry.start.date(), when.date(), opts)
+                .unwrap_or_default(),
+            Field::Duration => format_duration(entry.duration, &opts.durations),
+            Field::Countdown => match (entry.start > when, opts.humanize) {
+                (true, false) => format_duration(entry.start - when, &opts.durations),
+                (false, false) => format_duration(end - when, &opts.durations),
+                (true, true) => humanize_until(entry.start - when, opts.language),
+                (false, true) => humanize_remaining(end - when, opts.language),
+            },
+            Field::Progress => progress(entry, when).to_string(),
+            Field::Location => entry.location.clone().unwrap_or_default(),
+            Field::Description => description_snippet(opts, entry).unwrap_or_default(),
+            Field::Organizer => entry.organizer.clone().unwrap_or_default(),
+            Field::Attendees => entry.attendees.to_string(),
+            Field::Source => entry
+                .label
+                .as_ref()
+                .or(entry.calendar.as_ref())
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl EntryTemplate {
+    pub fn render(
+        &self,
+        opts: &DisplayOptions,
+        entry: &AgendaEntry,
+        when: NaiveDateTime,
+    ) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Field(field) => field.render(opts, entry, when),
+            })
+            .collect()
+    }
+}
+
+// An entry as template files see it
+#[derive(Serialize)]
+struct TemplateEntry<'a> {
+    name: &'a str,
+    start: String,
+    end: String,
+    duration: String,
+    countdown: String,
+    progress: u8,
+    // As it's shown on the bar
+    text: String,
+    all_day: bool,
+    started: bool,
+    due: Option<String>,
+    location: Option<&'a str>,
+    description: Option<&'a str>,
+    organizer: Option<&'a str>,
+    attendees: usize,
+    link: Option<&'a str>,
+    calendar: Option<&'a str>,
+    label: Option<&'a str>,
+    icon: Option<&'a str>,
+    color: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    entries: Vec<TemplateEntry<'a>>,
+    now: String,
+}
+
+// Render a Tera template file, for layouts a `--format` string can't express. The
+// template gets the upcoming `entries` and `now`
+pub fn render_file(
+    path: &Path,
+    entries: &[AgendaEntry],
+    opts: &DisplayOptions,
+    now: NaiveDateTime,
+) -> Result<String, String> {
+    let template = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let context = TemplateContext {
+        entries: entries
+            .iter()
+            .map(|entry| TemplateEntry {
+                name: &entry.name,
+                start: entry.start.format(TIMESTAMP_FORMAT).to_string(),
+                end: (entry.start + entry.duration)
+                    .format(TIMESTAMP_FORMAT)
+                    .to_string(),
+                duration: format_duration(entry.duration, &opts.durations),
+                countdown: Field::Countdown.render(opts, entry, now),
+                progress: progress(entry, now),
+                text: format_collection_entry(opts, entry, now),
+                all_day: entry.all_day,
+                started: entry.start <= now,
+                due: entry
+                    .due
+                    .map(|due| due.format(TIMESTAMP_FORMAT).to_string()),
+                location: entry.location.as_deref(),
+                description: entry.description.as_deref(),
+                organizer: entry.organizer.as_deref(),
+                attendees: entry.attendees,
+                link: entry.link.as_deref(),
+                calendar: entry.calendar.as_deref(),
+                label: entry.label.as_deref(),
+                icon: entry.icon.as_deref(),
+                color: entry.color.as_deref(),
+            })
+            .collect(),
+        now: now.format(TIMESTAMP_FORMAT).to_string(),
+    };
+    let context = tera::Context::from_serialize(context).map_err(|e| e.to_string())?;
+    // Tera's own message only names the template, the cause is in the sources
+    tera::Tera::one_off(&template, &context, false).map_err(|e| {
+        let mut message = format!("{}: {}", path.display(), e);
+        let mut source = e.source();
+        while let Some(cause) = source {
+            message = format!("{}: {}", message, cause);
+            source = cause.source();
+        }
+        message
+    })
+}
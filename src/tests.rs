@@ -4,6 +4,55 @@ use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
 use icalendar::{Calendar, CalendarComponent, Component, Event, EventLike};
 use itertools::Itertools;
 
+// Normalize and expand a single component, like the sources do for whole calendars
+fn extract_event(
+    event: &impl Component,
+    sod: chrono::DateTime<Local>,
+    eod: chrono::DateTime<Local>,
+) -> Result<Vec<AgendaEntry>, CalendarError> {
+    EventRecord::from_component(event, None)?.entries(sod, eod)
+}
+
+// A directory of its own under the system's temporary one, removed again when the
+// test ends, failed or not
+struct TempDir(std::path::PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("polybar-agenda-{}-{}", name, std::process::id()));
+        // Left over from a run that was killed
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// An entry given as dates only, from the start of `day` for `days`
+fn all_day_entry(name: &str, day: NaiveDate, days: i64) -> AgendaEntry {
+    let mut entry = AgendaEntry::new(
+        name.to_owned(),
+        day.and_time(chrono::NaiveTime::MIN),
+        Duration::days(days),
+    );
+    entry.all_day = true;
+    entry
+}
+
 // Helper function to create a test event
 fn create_test_event(summary: &str, start: NaiveDateTime, duration: Duration) -> Event {
     let mut event = Event::new();
@@ -15,18 +64,81 @@ fn create_test_event(summary: &str, start: NaiveDateTime, duration: Duration) ->
 
 #[test]
 fn test_format_duration() {
-    assert_eq!(format_duration(Duration::hours(2)), "2h");
-    assert_eq!(format_duration(Duration::minutes(30)), "30min");
-    assert_eq!(format_duration(Duration::seconds(45)), "45s");
+    let fmt = DurationFormat::default();
+    assert_eq!(format_duration(Duration::hours(2), &fmt), "2h");
+    assert_eq!(format_duration(Duration::minutes(30), &fmt), "30min");
+    assert_eq!(format_duration(Duration::seconds(45), &fmt), "45s");
     assert_eq!(
-        format_duration(Duration::hours(1) + Duration::minutes(30)),
+        format_duration(Duration::hours(1) + Duration::minutes(30), &fmt),
         "1.5h"
     );
-    assert_eq!(format_duration(Duration::zero()), "0s");
+    assert_eq!(format_duration(Duration::zero(), &fmt), "0s");
+    // Hours are rounded up to the next quarter
+    assert_eq!(format_duration(Duration::minutes(61), &fmt), "1.25h");
+}
+
+#[test]
+fn test_composite_durations() {
+    let fmt = |units| DurationFormat {
+        units,
+        style: DurationStyle::Composite,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_duration(Duration::minutes(90), &fmt(UnitStyle::Short)),
+        "1h 30m"
+    );
+    assert_eq!(
+        format_duration(Duration::minutes(61), &fmt(UnitStyle::Default)),
+        "1h 1min"
+    );
+    assert_eq!(
+        format_duration(Duration::minutes(121), &fmt(UnitStyle::Long)),
+        "2 hours 1 minute"
+    );
+    assert_eq!(
+        format_duration(Duration::hours(2), &fmt(UnitStyle::Short)),
+        "2h"
+    );
+    // Below an hour nothing changes
+    assert_eq!(
+        format_duration(Duration::minutes(45), &fmt(UnitStyle::Short)),
+        "45m"
+    );
+    assert!("hms".parse::<DurationStyle>().is_err());
+}
+
+#[test]
+fn test_duration_rounding() {
+    let fmt = |rounding| DurationFormat {
+        rounding,
+        ..Default::default()
+    };
+    let left = Duration::seconds(17 * 60 + 40);
+    assert_eq!(format_duration(left, &fmt(Rounding::Minutes)), "17min");
+    assert_eq!(format_duration(left, &fmt(Rounding::FiveMinutes)), "20min");
+    assert_eq!(format_duration(left, &fmt(Rounding::Exact)), "17min 40s");
+    // Close to the start the countdown is still exact to the minute
+    let soon = Duration::seconds(4 * 60 + 10);
+    assert_eq!(format_duration(soon, &fmt(Rounding::FiveMinutes)), "4min");
+    assert_eq!(
+        format_duration(Duration::seconds(50), &fmt(Rounding::FiveMinutes)),
+        "50s"
+    );
+    assert_eq!(
+        format_duration(Duration::minutes(58), &fmt(Rounding::FiveMinutes)),
+        "1h"
+    );
+    assert_eq!(
+        format_duration(Duration::minutes(3), &fmt(Rounding::Exact)),
+        "3min"
+    );
+    assert!("hourly".parse::<Rounding>().is_err());
 }
 
 #[test]
 fn test_format_agenda_entry_default() {
+    let opts = DisplayOptions::new(DisplayMode::Default);
     let now = NaiveDate::from_ymd_opt(2023, 5, 1)
         .unwrap()
         .and_hms_opt(14, 0, 0)
@@ -39,7 +151,7 @@ fn test_format_agenda_entry_default() {
         Duration::hours(1),
     );
     assert_eq!(
-        format_agenda_entry_default(&future_event, now),
+        format_agenda_entry_default(&opts, &future_event, now),
         "Future Event 14:30 (in 30min)"
     );
 
@@ -50,13 +162,14 @@ fn test_format_agenda_entry_default() {
         Duration::hours(1),
     );
     assert_eq!(
-        format_agenda_entry_default(&past_event, now),
+        format_agenda_entry_default(&opts, &past_event, now),
         "Past Event 13:30 (30min ago)"
     );
 }
 
 #[test]
 fn test_format_agenda_entry_compact() {
+    let opts = DisplayOptions::new(DisplayMode::Compact);
     let now = NaiveDate::from_ymd_opt(2023, 5, 1)
         .unwrap()
         .and_hms_opt(14, 0, 0)
@@ -69,7 +182,7 @@ fn test_format_agenda_entry_compact() {
         Duration::hours(1),
     );
     assert_eq!(
-        format_agenda_entry_compact(&future_event, now),
+        format_agenda_entry_compact(&opts, &future_event, now),
         "Future Event · 45min"
     );
 
@@ -80,7 +193,7 @@ fn test_format_agenda_entry_compact() {
         Duration::hours(2),
     );
     assert_eq!(
-        format_agenda_entry_compact(&ongoing_event, now),
+        format_agenda_entry_compact(&opts, &ongoing_event, now),
         "Ongoing Event · 45min/1.25h"
     );
 }
@@ -95,7 +208,7 @@ fn test_as_naive() {
             .unwrap(),
     );
     assert_eq!(
-        as_naive(floating).unwrap(),
+        as_naive(floating, None).unwrap(),
         NaiveDate::from_ymd_opt(2023, 5, 1)
             .unwrap()
             .and_hms_opt(14, 0, 0)
@@ -110,7 +223,7 @@ fn test_as_naive() {
             .unwrap(),
         chrono::Utc,
     ));
-    assert!(as_naive(utc).is_ok());
+    assert!(as_naive(utc, None).is_ok());
 
     // Test WithTimezone
     let with_tz = icalendar::CalendarDateTime::WithTimezone {
@@ -120,7 +233,7 @@ fn test_as_naive() {
             .unwrap(),
         tzid: "America/New_York".to_string(),
     };
-    assert!(as_naive(with_tz).is_ok());
+    assert!(as_naive(with_tz, None).is_ok());
 
     // Test invalid timezone
     let invalid_tz = icalendar::CalendarDateTime::WithTimezone {
@@ -130,7 +243,7 @@ fn test_as_naive() {
             .unwrap(),
         tzid: "Invalid/Timezone".to_string(),
     };
-    assert!(as_naive(invalid_tz).is_err());
+    assert!(as_naive(invalid_tz, None).is_err());
 }
 
 #[test]
@@ -140,10 +253,12 @@ fn test_extract_event() {
     let eod = now + Duration::hours(HOURS_AHEAD);
 
     // Test single event
-    let single_event = create_test_event("Single Event", now.naive_local(), Duration::hours(1));
+    let mut single_event = create_test_event("Single Event", now.naive_local(), Duration::hours(1));
+    single_event.add_property("LOCATION", "Room 4\\, 2nd floor");
     let extracted = extract_event(&single_event, sod, eod).unwrap();
     assert_eq!(extracted.len(), 1);
     assert_eq!(extracted[0].name, "Single Event");
+    assert_eq!(extracted[0].location.as_deref(), Some("Room 4, 2nd floor"));
 
     // Test recurring event
     let mut recurring_event =
@@ -177,11 +292,11 @@ fn test_format_agenda_entry() {
     );
 
     assert_eq!(
-        format_agenda_entry(DisplayMode::Default, &event, now),
+        format_agenda_entry(&DisplayOptions::new(DisplayMode::Default), &event, now),
         "Test Event 14:30 (in 30min)"
     );
     assert_eq!(
-        format_agenda_entry(DisplayMode::Compact, &event, now),
+        format_agenda_entry(&DisplayOptions::new(DisplayMode::Compact), &event, now),
         "Test Event · 30min"
     );
 }
@@ -237,7 +352,7 @@ fn test_calendar_processing() {
                 && (now.naive_local() - item.start).num_hours() < 24
         })
         .take(2)
-        .map(|item| format_agenda_entry(DisplayMode::Default, &item, now.naive_local()))
+        .map(|item| format_agenda_entry(&DisplayOptions::default(), &item, now.naive_local()))
         .intersperse(" » ".to_owned())
         .collect();
 
@@ -253,3 +368,3938 @@ fn test_calendar_processing() {
     assert!(!formatted_agenda.contains("Past Event"));
     assert!(!formatted_agenda.contains("Far Future Event"));
 }
+
+#[test]
+fn test_polybar_color() {
+    assert_eq!(polybar_color("#ff0000"), Some("#ff0000".to_string()));
+    assert_eq!(polybar_color(" #ff000080\n"), Some("#80ff0000".to_string()));
+    assert_eq!(polybar_color("red"), None);
+    assert_eq!(polybar_color("#zzzzzz"), None);
+}
+
+#[test]
+fn test_load_vdir_collections() {
+    let root = TempDir::new("vdir");
+    let work = root.join("work");
+    let home = root.join("home");
+    std::fs::create_dir_all(&work).unwrap();
+    std::fs::create_dir_all(&home).unwrap();
+
+    let now = Local::now();
+    let write_event = |dir: &std::path::Path, name: &str| {
+        let mut calendar = Calendar::new();
+        calendar.push(create_test_event(
+            name,
+            now.naive_local(),
+            Duration::hours(1),
+        ));
+        std::fs::write(
+            dir.join(format!("{}.ics", name)),
+            calendar.done().to_string(),
+        )
+        .unwrap();
+    };
+    write_event(&work, "Standup");
+    write_event(&home, "Dentist");
+    std::fs::write(work.join("displayname"), "Work\n").unwrap();
+    std::fs::write(work.join("color"), "#00ff00").unwrap();
+
+    let collections = crate::sources::load(&root, &Default::default()).unwrap();
+
+    assert_eq!(collections.len(), 2);
+    let (sod, eod) = extraction_window(now, WindowMode::default());
+    let entries: Vec<AgendaEntry> = collections
+        .iter()
+        .flat_map(|c| c.entries(sod, eod))
+        .collect();
+
+    let standup = entries.iter().find(|e| e.name == "Standup").unwrap();
+    assert_eq!(standup.calendar.as_deref(), Some("Work"));
+    assert_eq!(standup.color.as_deref(), Some("#00ff00"));
+
+    // Collections without a displayname fall back to their directory name
+    let dentist = entries.iter().find(|e| e.name == "Dentist").unwrap();
+    assert_eq!(dentist.calendar.as_deref(), Some("home"));
+    assert_eq!(dentist.color, None);
+}
+
+#[test]
+fn test_parse_remind() {
+    use crate::remind::parse_rem;
+    use chrono::NaiveTime;
+
+    let date = |d| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+
+    let meeting = parse_rem("REM 6 May 2024 AT 10:30 DURATION 1:15 MSG Team %\"sync%\"").unwrap();
+    assert_eq!(meeting.body, "Team sync");
+    assert_eq!(meeting.time, NaiveTime::from_hms_opt(10, 30, 0));
+    assert_eq!(meeting.duration, Some(Duration::minutes(75)));
+    let entries = meeting.entries(date(1), date(31));
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].start, date(6).and_hms_opt(10, 30, 0).unwrap());
+
+    // Weekly reminders without a type keyword default to MSG
+    let standup = parse_rem("REM Mon Wed AT 9am +15 Standup").unwrap();
+    assert_eq!(standup.body, "Standup");
+    assert_eq!(standup.entries(date(6), date(12)).len(), 2);
+
+    // First Monday of the month
+    let review = parse_rem("REM Mon 1 MSG Review").unwrap();
+    let entries = review.entries(date(1), date(31));
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].start, date(6).and_hms_opt(0, 0, 0).unwrap());
+    assert_eq!(entries[0].duration, Duration::days(1));
+    // Without a time, it's for the whole day
+    assert!(entries[0].all_day);
+    assert!(!meeting.entries(date(1), date(31))[0].all_day);
+
+    // Repeats from a base date up to UNTIL
+    let pills = parse_rem("REM 2024-05-01 *2 UNTIL 2024-05-07 MSG Pills").unwrap();
+    assert_eq!(pills.entries(date(1), date(31)).len(), 4);
+
+    // A nonsensical DURATION drops the reminder rather than the bar
+    assert!(parse_rem("REM Mon AT 10:00 DURATION 99999999999:00 MSG Forever").is_none());
+    let long = parse_rem("REM Mon AT 10:00 DURATION 4000000000 MSG Long").unwrap();
+    assert_eq!(long.duration, Some(Duration::hours(MAX_WINDOW_HOURS)));
+    assert_eq!(long.entries(date(6), date(6)).len(), 1);
+
+    assert!(parse_rem("REM Mon RUN notify-send hi").is_none());
+    assert!(parse_rem("SET foo 1").is_none());
+}
+
+#[test]
+fn test_parse_org() {
+    use crate::org::{parse_org, Planning};
+
+    let date = |d| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+    let items = parse_org(
+        "#+TITLE: Tasks <2024-05-01 Wed>\n\
+         * TODO [#A] Write report :work:\n\
+         DEADLINE: <2024-05-10 Fri> SCHEDULED: <2024-05-08 Wed 09:00>\n\
+         * Standup <2024-05-06 Mon 09:30-09:45 +1w>\n\
+         * DONE Old task\n\
+         SCHEDULED: <2024-05-07 Tue>\n\
+         ** Water plants\n\
+         SCHEDULED: <2024-04-01 Mon .+3d>\n\
+         #+begin_src\n\
+         <2024-05-09 Thu>\n\
+         #+end_src\n\
+         Noted [2024-05-09 Thu]\n",
+    );
+    let titles: Vec<_> = items
+        .iter()
+        .map(|i| (i.title.as_str(), i.planning))
+        .collect();
+    assert_eq!(
+        titles,
+        [
+            ("Write report", Planning::Deadline),
+            ("Write report", Planning::Scheduled),
+            ("Standup", Planning::Active),
+            ("Water plants", Planning::Scheduled),
+        ]
+    );
+
+    // A date-only deadline is due by the end of the day
+    let deadline = items[0].entries(date(1), date(31));
+    assert_eq!(
+        deadline[0].due,
+        Some(date(11).and_hms_opt(0, 0, 0).unwrap())
+    );
+    let scheduled = items[1].entries(date(1), date(31));
+    assert_eq!(scheduled[0].start, date(8).and_hms_opt(9, 0, 0).unwrap());
+    assert_eq!(scheduled[0].duration, Duration::zero());
+
+    // Repeaters are expanded within the window
+    let standup = items[2].entries(date(1), date(20));
+    assert_eq!(standup.len(), 3);
+    assert_eq!(standup[2].start, date(20).and_hms_opt(9, 30, 0).unwrap());
+    assert_eq!(standup[0].duration, Duration::minutes(15));
+    let plants = items[3].entries(date(1), date(7));
+    assert_eq!(plants.len(), 3);
+    assert_eq!(plants[1].start, date(4).and_hms_opt(0, 0, 0).unwrap());
+    assert_eq!(plants[0].duration, Duration::days(1));
+
+    // Years of months that are shorter than the longest don't skip past the window
+    let items = parse_org("* Rent <2022-01-15 Sat +1m>\n* Review <2016-02-29 Mon +1y>\n");
+    let day = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    let rent = items[0].entries(day(2024, 1, 15), day(2024, 1, 15));
+    assert_eq!(rent.len(), 1);
+    assert_eq!(
+        rent[0].start,
+        day(2024, 1, 15).and_hms_opt(0, 0, 0).unwrap()
+    );
+    let review = items[1].entries(day(2024, 2, 29), day(2024, 3, 1));
+    assert_eq!(review.len(), 1);
+    assert_eq!(
+        review[0].start,
+        day(2024, 2, 29).and_hms_opt(0, 0, 0).unwrap()
+    );
+}
+
+#[test]
+fn test_format_org_entry() {
+    use crate::output::format_org_entry;
+
+    let start = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(10, 30, 0)
+        .unwrap();
+    let mut meeting = AgendaEntry::new("Team sync".to_string(), start, Duration::minutes(45));
+    meeting.calendar = Some("Work Stuff".to_string());
+    assert_eq!(
+        format_org_entry(&meeting),
+        "* Team sync :Work_Stuff:\n  <2024-05-06 Mon 10:30-11:15>"
+    );
+
+    let holiday = all_day_entry("Holiday", start.date(), 1);
+    assert_eq!(format_org_entry(&holiday), "* Holiday\n  <2024-05-06 Mon>");
+
+    let trip = all_day_entry("Trip", start.date(), 3);
+    assert_eq!(
+        format_org_entry(&trip),
+        "* Trip\n  <2024-05-06 Mon>--<2024-05-08 Wed>"
+    );
+
+    meeting.location = Some("Room 4".to_string());
+    meeting.link = Some("https://meet.example.com/abc".to_string());
+    assert_eq!(
+        format_org_entry(&meeting),
+        "* Team sync :Work_Stuff:\n  :PROPERTIES:\n  :LOCATION: Room 4\n  \
+         :URL: https://meet.example.com/abc\n  :END:\n  <2024-05-06 Mon 10:30-11:15>"
+    );
+    let mut report = AgendaEntry::new("Report".to_string(), start, Duration::zero());
+    report.due = Some(start);
+    assert_eq!(
+        format_org_entry(&report),
+        "* TODO Report\n  DEADLINE: <2024-05-06 Mon 10:30>"
+    );
+}
+
+#[test]
+fn test_format_agenda_entry_humanized() {
+    use crate::i18n::Language;
+
+    let now = NaiveDate::from_ymd_opt(2023, 5, 1)
+        .unwrap()
+        .and_hms_opt(14, 0, 0)
+        .unwrap();
+    let soon = AgendaEntry::new(
+        "Standup".to_string(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    let ongoing = AgendaEntry::new(
+        "Review".to_string(),
+        now - Duration::seconds(20),
+        Duration::minutes(20),
+    );
+
+    let opts = |mode, language| DisplayOptions {
+        humanize: true,
+        language,
+        ..DisplayOptions::new(mode)
+    };
+
+    assert_eq!(
+        format_agenda_entry_humanized(&opts(DisplayMode::Default, Language::English), &soon, now),
+        "Standup 14:30 (in half an hour)"
+    );
+    assert_eq!(
+        format_agenda_entry_humanized(
+            &opts(DisplayMode::Compact, Language::English),
+            &ongoing,
+            now
+        ),
+        "Review · just started"
+    );
+    assert_eq!(
+        format_agenda_entry_humanized(
+            &opts(DisplayMode::Compact, Language::German),
+            &ongoing,
+            now + Duration::minutes(5)
+        ),
+        "Review · endet in 15 Minuten"
+    );
+    assert_eq!(
+        format_agenda_entry(
+            &opts(DisplayMode::Default, Language::French),
+            &soon,
+            now + Duration::hours(2)
+        ),
+        "Standup 14:30 (il y a 2 heures)"
+    );
+}
+
+#[test]
+fn test_format_duration_units() {
+    let fmt = |units| DurationFormat {
+        units,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        format_duration(Duration::minutes(30), &fmt(UnitStyle::Short)),
+        "30m"
+    );
+    assert_eq!(
+        format_duration(Duration::minutes(90), &fmt(UnitStyle::Medium)),
+        "1.5hr"
+    );
+    assert_eq!(
+        format_duration(Duration::seconds(45), &fmt(UnitStyle::Medium)),
+        "45sec"
+    );
+    assert_eq!(
+        format_duration(Duration::minutes(1), &fmt(UnitStyle::Long)),
+        "1 minute"
+    );
+    assert_eq!(
+        format_duration(Duration::hours(2), &fmt(UnitStyle::Long)),
+        "2 hours"
+    );
+}
+
+#[test]
+fn test_day_anchored_window() {
+    let now = Local::now();
+    let (start, end) = extraction_window(now, WindowMode::Days(1));
+    assert_eq!(start.date_naive(), now.date_naive());
+    assert_eq!(start.naive_local().time(), chrono::NaiveTime::MIN);
+    assert_eq!(end.date_naive(), now.date_naive() + Duration::days(1));
+
+    // Later in the day the window stays the same
+    let later = extraction_window(now + Duration::minutes(1), WindowMode::Days(1));
+    if later.0.date_naive() == now.date_naive() {
+        assert_eq!(later, (start, end));
+    }
+}
+
+#[test]
+fn test_rolling_window_hours() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+
+    let now = Local::now();
+    let window = WindowMode::default().ahead(168).behind(2);
+    assert_eq!(
+        extraction_window(now, window),
+        (now - Duration::hours(2), now + Duration::hours(168))
+    );
+    // In place of a day-anchored window
+    let (start, end) = extraction_window(now, WindowMode::Days(1).ahead(4));
+    assert_eq!(end - now, Duration::hours(4));
+    assert_eq!(now - start, Duration::hours(HOURS_BEHIND));
+    // However far they're asked to reach, windows end within what dates can represent
+    let window = WindowMode::default()
+        .ahead(u32::MAX.into())
+        .behind(u32::MAX.into());
+    assert_eq!(
+        extraction_window(now, window),
+        (
+            now - Duration::hours(MAX_WINDOW_HOURS),
+            now + Duration::hours(MAX_WINDOW_HOURS)
+        )
+    );
+    let (_, end) = extraction_window(now, WindowMode::Days(i64::MAX));
+    assert!(end - now <= Duration::hours(MAX_WINDOW_HOURS + 24));
+
+    let config = Config::parse("days = 2\nhours-ahead = 12").unwrap();
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert!(matches!(
+        opts.window,
+        WindowMode::Rolling {
+            behind: HOURS_BEHIND,
+            ahead: 12
+        }
+    ));
+}
+
+#[test]
+fn test_explicit_range() {
+    use crate::range::TimePoint;
+    use chrono::TimeZone;
+
+    // A Monday
+    let today = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+    let at = |day: u32, hour: u32| {
+        NaiveDate::from_ymd_opt(2024, 5, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    };
+    let point = |s: &str| s.parse::<TimePoint>().unwrap();
+    assert_eq!(point("thu 09:00").start(today), at(9, 9));
+    assert_eq!(point("Thursday").start(today), at(9, 0));
+    assert_eq!(point("thu").end(today), at(10, 0));
+    assert_eq!(point("mon").start(today), at(6, 0));
+    assert_eq!(point("tomorrow 18:00").end(today), at(7, 18));
+    assert_eq!(point("2024-05-20").start(today), at(20, 0));
+    assert_eq!(point("14:00").start(today), at(6, 14));
+    // A time alone ends the range on the day it starts
+    let from = point("thu 09:00").start(today);
+    assert_eq!(point("18:00").end_after(from, today), at(9, 18));
+    assert_eq!(point("fri").end_after(from, today), at(11, 0));
+    assert_eq!(point("tomorrow 12:00").end_after(from, today), at(7, 12));
+    assert!("thu 25:00".parse::<TimePoint>().is_err());
+    assert!("someday".parse::<TimePoint>().is_err());
+    assert!("".parse::<TimePoint>().is_err());
+
+    // Entries in the window are shown whether they're over or not
+    let now = Local.with_ymd_and_hms(2024, 5, 9, 15, 0, 0).unwrap();
+    let entries = vec![
+        AgendaEntry::new("Breakfast".to_owned(), at(9, 8), Duration::hours(2)),
+        AgendaEntry::new("Standup".to_owned(), at(9, 10), Duration::minutes(15)),
+        AgendaEntry::new("Dinner".to_owned(), at(9, 19), Duration::hours(1)),
+    ];
+    let opts = DisplayOptions {
+        window: WindowMode::Range {
+            from: at(9, 9),
+            to: at(9, 18),
+        },
+        ..Default::default()
+    };
+    let names: Vec<_> = upcoming_entries(entries, &opts, now)
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    assert_eq!(names, ["Breakfast", "Standup"]);
+    assert_eq!(
+        extraction_window(now, opts.window).0.naive_local(),
+        at(9, 9)
+    );
+}
+
+#[test]
+fn test_today_window() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+    use chrono::TimeZone;
+
+    let config = Config::parse("today = true").unwrap();
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert!(matches!(opts.window, WindowMode::Days(0)));
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let at = |hours| now.naive_local() + Duration::hours(hours);
+    let entries = vec![
+        AgendaEntry::new("Breakfast".to_owned(), at(-2), Duration::hours(1)),
+        AgendaEntry::new("Standup".to_owned(), at(1), Duration::minutes(15)),
+        AgendaEntry::new("Late call".to_owned(), at(14), Duration::minutes(30)),
+        AgendaEntry::new("Gym".to_owned(), at(22), Duration::hours(1)),
+    ];
+    let names: Vec<_> = upcoming_entries(entries, &opts, now)
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    assert_eq!(names, ["Standup", "Late call"]);
+}
+
+#[test]
+fn test_hidden_event_counter() {
+    use chrono::TimeZone;
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let entries = || {
+        [1, 2, 3, 4, 30]
+            .iter()
+            .map(|h| {
+                AgendaEntry::new(
+                    format!("Event {}", h),
+                    now.naive_local() + Duration::hours(*h),
+                    Duration::hours(1),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut opts = DisplayOptions::new(DisplayMode::Compact);
+    assert_eq!(
+        process_entries(entries(), &opts, now),
+        "Event 1 · 1h » Event 2 · 2h"
+    );
+
+    // Only the two remaining events of today are counted, not tomorrow's
+    opts.more_format = Some("%{F#888}+{}%{F-}".to_string());
+    assert_eq!(
+        process_entries(entries(), &opts, now),
+        "Event 1 · 1h » Event 2 · 2h %{F#888}+2%{F-}"
+    );
+}
+
+#[test]
+fn test_empty_text() {
+    let now = Local::now();
+    let mut opts = DisplayOptions::default();
+    assert_eq!(process_entries(Vec::new(), &opts, now), "");
+
+    opts.empty = EmptyOutput::Text("No events 🎉".to_string());
+    assert_eq!(process_entries(Vec::new(), &opts, now), "No events 🎉");
+
+    // The text gets the output's color tags
+    opts.empty_color = Some("#88888880".to_string());
+    assert_eq!(
+        process_entries(Vec::new(), &opts, now),
+        "%{F#80888888}No events 🎉%{F-}"
+    );
+    opts.markup = Markup::Tmux;
+    assert_eq!(
+        process_entries(Vec::new(), &opts, now),
+        "#[fg=#888888]No events 🎉#[fg=default]"
+    );
+    opts.markup = Markup::Plain;
+    assert_eq!(process_entries(Vec::new(), &opts, now), "No events 🎉");
+}
+
+#[test]
+fn test_all_day_exclusive_end() {
+    let now = Local::now();
+    let (sod, eod) = extraction_window(now, WindowMode::default());
+    let today = now.date_naive();
+
+    let mut holiday = Event::new();
+    holiday.summary("Holiday");
+    holiday.starts(today);
+    holiday.ends(today + Duration::days(1));
+    let extracted = extract_event(&holiday, sod, eod).unwrap();
+    assert_eq!(extracted[0].start, today.and_hms_opt(0, 0, 0).unwrap());
+    assert_eq!(extracted[0].duration, Duration::days(1));
+
+    let mut conference = Event::new();
+    conference.summary("Conference");
+    conference.starts(today);
+    conference.ends(today + Duration::days(3));
+    let extracted = extract_event(&conference, sod, eod).unwrap();
+    assert_eq!(extracted[0].duration, Duration::days(3));
+
+    // Date-only events without DTEND last a single day
+    let mut birthday = Event::new();
+    birthday.summary("Birthday");
+    birthday.starts(today);
+    let extracted = extract_event(&birthday, sod, eod).unwrap();
+    assert_eq!(extracted[0].duration, Duration::days(1));
+}
+
+#[test]
+fn test_windows_timezones() {
+    assert_eq!(
+        resolve_timezone("W. Europe Standard Time"),
+        Some(chrono_tz::Europe::Berlin)
+    );
+    assert_eq!(
+        resolve_timezone("Eastern Standard Time"),
+        Some(chrono_tz::America::New_York)
+    );
+    assert_eq!(
+        resolve_timezone("Europe/Vienna"),
+        Some(chrono_tz::Europe::Vienna)
+    );
+    assert_eq!(resolve_timezone("Invalid/Timezone"), None);
+
+    let outlook = icalendar::CalendarDateTime::WithTimezone {
+        date_time: NaiveDate::from_ymd_opt(2023, 5, 1)
+            .unwrap()
+            .and_hms_opt(14, 0, 0)
+            .unwrap(),
+        tzid: "Romance Standard Time".to_string(),
+    };
+    assert!(as_naive(outlook, None).is_ok());
+}
+
+#[test]
+fn test_globally_unique_tzids() {
+    for tzid in [
+        "/freeassociation.sourceforge.net/America/New_York",
+        "/freeassociation.sourceforge.net/Tzfile/America/New_York",
+        "/mozilla.org/20050126_1/America/New_York",
+        "/America/New_York",
+    ] {
+        assert_eq!(
+            resolve_timezone(tzid),
+            Some(chrono_tz::America::New_York),
+            "{}",
+            tzid
+        );
+    }
+    assert_eq!(resolve_timezone("/example.com/Not/A_Zone"), None);
+}
+
+#[test]
+fn test_parse_cache() {
+    use crate::cache::{stamp, ParseCache};
+    use crate::sources::{load, Document, LoadOptions};
+
+    let dir = TempDir::new("cache");
+    let file = dir.join("calendar.ics");
+    std::fs::write(&file, "BEGIN:VCALENDAR\nEND:VCALENDAR\n").unwrap();
+
+    let start = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    let events = vec![EventRecord {
+        name: "Standup".to_string(),
+        start,
+        end: start + Duration::minutes(15),
+        all_day: false,
+        recurrence: None,
+        last_end: None,
+        due: None,
+        link: None,
+        location: None,
+        categories: Vec::new(),
+        description: None,
+        organizer: None,
+        attendees: 0,
+        status: Status::Confirmed,
+    }];
+
+    let contents = "BEGIN:VCALENDAR\nEND:VCALENDAR\n";
+    let cache = ParseCache::new(dir.join("cache"));
+    let before = stamp(&file).unwrap();
+    assert_eq!(cache.load(&file, contents, ""), None);
+    cache.store(&file, Some(before), contents, "", &events);
+    assert_eq!(cache.load(&file, contents, ""), Some(events.clone()));
+    // An untouched file isn't even read
+    assert_eq!(
+        cache.load_unchanged(&file, before, ""),
+        Some(events.clone())
+    );
+    // Nor are events parsed with different settings reused
+    assert_eq!(cache.load(&file, contents, "Europe/Vienna"), None);
+    assert_eq!(cache.load_unchanged(&file, before, "Europe/Vienna"), None);
+
+    // Any change to the file invalidates the cached events
+    let changed = "BEGIN:VCALENDAR\nVERSION:2.0\nEND:VCALENDAR\n";
+    std::fs::write(&file, changed).unwrap();
+    assert_ne!(stamp(&file), Some(before));
+    assert_eq!(cache.load_unchanged(&file, stamp(&file).unwrap(), ""), None);
+    assert_eq!(cache.load(&file, changed, ""), None);
+
+    // Nor are the events of an untouched file once it is read in another encoding
+    let legacy = dir.join("legacy.ics");
+    std::fs::write(
+        &legacy,
+        "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Café\nDTSTART:20240506T100000\n\
+         END:VEVENT\nEND:VCALENDAR\n",
+    )
+    .unwrap();
+    let name = |encoding| {
+        let opts = LoadOptions {
+            cache: Some(ParseCache::new(dir.join("cache"))),
+            encoding,
+            ..Default::default()
+        };
+        match &load(&legacy, &opts).unwrap()[0].documents[..] {
+            [Document::Ics(events)] => events[0].name.clone(),
+            _ => panic!("expected an ics document"),
+        }
+    };
+    assert_eq!(name(None), "Café");
+    assert_eq!(name(Some(encoding_rs::WINDOWS_1252)), "CafÃ©");
+}
+
+#[test]
+fn test_fallback_parser() {
+    use crate::fallback::{parse, parse_duration};
+
+    // Stray text and a bogus DTSTAMP make the strict parser give up
+    let text = "BEGIN:VCALENDAR\r\n\
+        this line is not a property\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTAMP:yesterday\r\n\
+        SUMMARY:Team\r\n  sync\\, weekly\r\n\
+        DTSTART:20240506T100000\r\n\
+        DURATION:PT1H30M\r\n\
+        BEGIN:VALARM\r\n\
+        SUMMARY:Reminder\r\n\
+        END:VALARM\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        SUMMARY:Holiday\r\n\
+        LOCATION:C:\\\\new\\, home\r\n\
+        DTSTART;VALUE=DATE:20240507\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        SUMMARY:No start\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    let start = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    let holiday = NaiveDate::from_ymd_opt(2024, 5, 7)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    assert_eq!(
+        parse(text, None),
+        vec![
+            EventRecord {
+                name: "Team sync, weekly".to_string(),
+                start,
+                end: start + Duration::minutes(90),
+                all_day: false,
+                recurrence: None,
+                last_end: None,
+                due: None,
+                link: None,
+                location: None,
+                categories: Vec::new(),
+                description: None,
+                organizer: None,
+                attendees: 0,
+                status: Status::Confirmed,
+            },
+            EventRecord {
+                name: "Holiday".to_string(),
+                start: holiday,
+                end: holiday + Duration::days(1),
+                all_day: true,
+                recurrence: None,
+                last_end: None,
+                due: None,
+                link: None,
+                // An escaped backslash doesn't start another escape
+                location: Some("C:\\new, home".to_string()),
+                categories: Vec::new(),
+                description: None,
+                organizer: None,
+                attendees: 0,
+                status: Status::Confirmed,
+            },
+        ]
+    );
+
+    assert_eq!(parse_duration("P1DT2H"), Some(Duration::hours(26)));
+    assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+    assert_eq!(parse_duration("P2W"), Some(Duration::weeks(2)));
+    assert_eq!(parse_duration("PT1X"), None);
+    // Mangled files shouldn't bring the parser down
+    assert_eq!(parse_duration("P1é"), None);
+    assert_eq!(parse_duration("P99999999999999W"), None);
+}
+
+#[test]
+fn test_format_week() {
+    use crate::week::{format_week, format_week_list, week_start};
+    use chrono::Weekday;
+
+    let wednesday = NaiveDate::from_ymd_opt(2024, 5, 8).unwrap();
+    let monday = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+    assert_eq!(week_start(wednesday, Weekday::Mon), monday);
+    assert_eq!(
+        week_start(wednesday, Weekday::Sun),
+        monday.pred_opt().unwrap()
+    );
+    assert_eq!(week_start(monday, Weekday::Mon), monday);
+
+    let entries = vec![
+        AgendaEntry::new(
+            "Standup".to_string(),
+            monday.and_hms_opt(9, 0, 0).unwrap(),
+            Duration::hours(2),
+        ),
+        all_day_entry("Holiday", wednesday, 1),
+    ];
+    let grid = format_week(&entries, monday, None);
+    let lines: Vec<&str> = grid.lines().collect();
+
+    assert!(lines[0].trim_start().starts_with("Mon 06       Tue 07"));
+    assert_eq!(lines[1], format!("all day {:26}Holiday", ""));
+    assert_eq!(lines[2], "08:00");
+    assert_eq!(lines[3], "09:00   Standup");
+    assert_eq!(lines[4], "10:00   |");
+    // 08:00 through 18:00, plus the header and all-day rows
+    assert_eq!(lines.len(), 13);
+
+    let list = format_week_list(&entries, monday, &DisplayOptions::default());
+    assert_eq!(
+        list,
+        "Monday 6 May\n  09:00–11:00  Standup\n\nWednesday 8 May\n  Holiday"
+    );
+    assert_eq!(
+        format_week_list(
+            &entries,
+            monday + Duration::days(3),
+            &DisplayOptions::default()
+        ),
+        ""
+    );
+}
+
+#[test]
+fn test_todo_deadlines() {
+    let now = Local::now();
+    let when = now.naive_local();
+    let opts = DisplayOptions::default();
+
+    let todo = |name: &str, due: NaiveDateTime| {
+        let mut entry = AgendaEntry::new(name.to_string(), due, Duration::zero());
+        entry.due = Some(due);
+        entry
+    };
+
+    let report = todo("Report", when + Duration::hours(3));
+    assert_eq!(
+        format_collection_entry(&opts, &report, when),
+        "Report due in 3h"
+    );
+
+    let taxes = todo("Taxes", when - Duration::hours(2));
+    assert_eq!(
+        format_collection_entry(&opts, &taxes, when),
+        "%{F#ff5555}Taxes OVERDUE%{F-}"
+    );
+
+    // Overdue todos stay on the bar for a while, then disappear
+    let ancient = todo("Ancient", when - Duration::hours(OVERDUE_HOURS + 1));
+    assert_eq!(
+        process_entries(vec![taxes, ancient], &opts, now),
+        "%{F#ff5555}Taxes OVERDUE%{F-}"
+    );
+
+    let mut short = opts.clone();
+    short.overdue_for = Some(Duration::hours(1));
+    let taxes = todo("Taxes", when - Duration::hours(2));
+    assert_eq!(process_entries(vec![taxes.clone()], &short, now), "");
+
+    // However long they're asked to stay, within what dates can represent
+    let config = crate::config::Config::parse("overdue-hours = 4294967295").unwrap();
+    config
+        .apply(&mut short, &mut crate::sources::LoadOptions::default())
+        .unwrap();
+    assert_eq!(short.overdue_for, Some(Duration::hours(MAX_WINDOW_HOURS)));
+    assert_eq!(
+        process_entries(vec![taxes], &short, now),
+        "%{F#ff5555}Taxes OVERDUE%{F-}"
+    );
+    assert!(crate::config::Config::parse("overdue-hours = -1").is_err());
+}
+
+#[test]
+fn test_conference_links() {
+    use crate::links::{conference_link, next_meeting};
+
+    assert_eq!(
+        conference_link([
+            ("LOCATION", "Room 4 (https://example.com/rooms/4)"),
+            (
+                "DESCRIPTION",
+                "Join:\\n<https://us02web.zoom.us/j/123?pwd=abc>\\nPasscode: 42"
+            ),
+        ]),
+        Some("https://us02web.zoom.us/j/123?pwd=abc".to_string())
+    );
+    assert_eq!(
+        conference_link([("X-GOOGLE-CONFERENCE", "https://meet.example.org/abc")]),
+        Some("https://meet.example.org/abc".to_string())
+    );
+    assert_eq!(conference_link([("URL", "https://notzoom.us/j/1")]), None);
+
+    let now = Local::now().naive_local();
+    let meeting = |name: &str, start, link: Option<&str>| {
+        let mut entry = AgendaEntry::new(name.to_string(), start, Duration::hours(1));
+        entry.link = link.map(str::to_string);
+        entry
+    };
+    let entries = vec![
+        meeting(
+            "Retro",
+            now + Duration::hours(2),
+            Some("https://meet.jit.si/retro"),
+        ),
+        meeting("Lunch", now + Duration::minutes(30), None),
+        meeting(
+            "Past",
+            now - Duration::hours(2),
+            Some("https://meet.jit.si/past"),
+        ),
+        meeting(
+            "Standup",
+            now - Duration::minutes(5),
+            Some("https://meet.jit.si/standup"),
+        ),
+    ];
+    assert_eq!(next_meeting(&entries, now).unwrap().name, "Standup");
+    assert!(next_meeting(&entries[1..3], now).is_none());
+}
+
+#[test]
+fn test_event_hooks_state() {
+    use crate::cache::state_path;
+    use crate::hooks::{ongoing, read_state, transitions, write_state};
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(10, 30, 0)
+        .unwrap();
+    let mut standup = AgendaEntry::new(
+        "Standup\twith tab".to_string(),
+        now - Duration::minutes(15),
+        Duration::minutes(30),
+    );
+    standup.calendar = Some("Work".to_string());
+    let entries = vec![
+        standup,
+        AgendaEntry::new(
+            "Lunch".to_string(),
+            now + Duration::hours(2),
+            Duration::hours(1),
+        ),
+    ];
+
+    let current = ongoing(&entries, now);
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].calendar, "Work");
+
+    let (started, ended) = transitions(&[], &current);
+    assert_eq!((started.len(), ended.len()), (1, 0));
+    let (started, ended) = transitions(&current, &current);
+    assert_eq!((started.len(), ended.len()), (0, 0));
+
+    let later = ongoing(&entries, now + Duration::minutes(20));
+    let (started, ended) = transitions(&current, &later);
+    assert_eq!((started.len(), ended.len()), (0, 1));
+
+    // Characters that can't be stored are replaced, so the state survives a round trip
+    assert_eq!(current[0].name, "Standup with tab");
+    let temp = TempDir::new("hooks");
+    let path = temp.join("ongoing");
+    write_state(&path, &current).unwrap();
+    assert_eq!(read_state(&path), current);
+
+    // Modules with other sources keep their state apart
+    let dir = std::path::Path::new("/cache");
+    let work = (vec!["~/.calendars/work"], vec!["--on-start", "notify"]);
+    let personal = (vec!["~/.calendars/personal"], vec!["--on-start", "notify"]);
+    assert_eq!(
+        state_path(dir, "ongoing", &work),
+        state_path(dir, "ongoing", &work)
+    );
+    assert_ne!(
+        state_path(dir, "ongoing", &work),
+        state_path(dir, "ongoing", &personal)
+    );
+}
+
+#[test]
+fn test_default_timezone() {
+    use chrono::{TimeZone, Utc};
+
+    let naive = NaiveDate::from_ymd_opt(2024, 1, 15)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    let floating = icalendar::CalendarDateTime::Floating(naive);
+    let in_vienna = chrono_tz::Europe::Vienna
+        .from_local_datetime(&naive)
+        .unwrap()
+        .with_timezone(&Local)
+        .naive_local();
+    let in_utc = Utc
+        .from_utc_datetime(&naive)
+        .with_timezone(&Local)
+        .naive_local();
+
+    assert_eq!(as_naive(floating.clone(), None).unwrap(), naive);
+    assert_eq!(
+        as_naive(floating, Some(chrono_tz::Europe::Vienna)).unwrap(),
+        in_vienna
+    );
+
+    // Explicit zones aren't affected by the default
+    let utc = icalendar::CalendarDateTime::Utc(Utc.from_utc_datetime(&naive));
+    assert_eq!(
+        as_naive(utc, Some(chrono_tz::Europe::Vienna)).unwrap(),
+        in_utc
+    );
+
+    let text = "BEGIN:VEVENT\nSUMMARY:Lecture\nDTSTART:20240115T100000\n\
+        DTEND:20240115T120000\nRRULE:FREQ=WEEKLY\nEND:VEVENT\n";
+    let records = crate::fallback::parse(text, Some(chrono_tz::Europe::Vienna));
+    assert_eq!(records[0].start, in_vienna);
+    assert_eq!(
+        records[0].recurrence.as_deref(),
+        Some("DTSTART;TZID=Europe/Vienna:20240115T100000\nRRULE:FREQ=WEEKLY\n")
+    );
+}
+
+#[test]
+fn test_decode_legacy_encodings() {
+    use crate::sources::decode;
+
+    assert_eq!(decode("Café".as_bytes(), None), "Café");
+    assert_eq!(decode(b"\xEF\xBB\xBFCaf\xC3\xA9", None), "Café");
+    assert_eq!(decode(b"\xFF\xFEC\0a\0f\0\xE9\0", None), "Café");
+    // Invalid UTF-8 falls back to Windows-1252
+    assert_eq!(decode(b"Caf\xE9 \x80", None), "Café €");
+    assert_eq!(
+        decode(b"\xC4rzte", encoding_rs::Encoding::for_label(b"iso-8859-2")),
+        "Ärzte"
+    );
+}
+
+#[test]
+fn test_decompress_gzip() {
+    use crate::sources::decompress;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let ics = b"BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(ics).unwrap();
+    let compressed = encoder.finish().unwrap();
+    assert_eq!(decompress(compressed.clone()).unwrap(), ics);
+    // Uncompressed input is passed through
+    assert_eq!(decompress(ics.to_vec()).unwrap(), ics);
+    assert!(decompress(compressed[..12].to_vec()).is_err());
+}
+
+#[test]
+fn test_recurrence_prefilter() {
+    let start = NaiveDate::from_ymd_opt(2020, 1, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let end = start + Duration::hours(1);
+    let bound =
+        |rule: &str| recurrence_end(&format!("DTSTART:20200106T090000\n{}\n", rule), start, end);
+
+    assert_eq!(
+        bound("RRULE:FREQ=WEEKLY;UNTIL=20200301T000000Z"),
+        Some(
+            NaiveDate::from_ymd_opt(2020, 3, 3)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap()
+        )
+    );
+    assert_eq!(
+        bound("RRULE:FREQ=DAILY;INTERVAL=2;COUNT=5"),
+        Some(end + Duration::days(10))
+    );
+    assert_eq!(bound("RRULE:FREQ=WEEKLY"), None);
+    assert_eq!(bound("RRULE:FREQ=MONTHLY;BYMONTHDAY=31;COUNT=3"), None);
+    // Too far out to be a date at all
+    assert_eq!(bound("RRULE:FREQ=DAILY;COUNT=10000000000"), None);
+
+    // Monthly from Jan 31 skips February and April, the third is on May 31
+    let jan31 = NaiveDate::from_ymd_opt(2024, 1, 31)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let rule = "DTSTART:20240131T090000\nRRULE:FREQ=MONTHLY;COUNT=3\n";
+    assert_eq!(
+        recurrence_end(rule, jan31, jan31 + Duration::hours(1)),
+        None
+    );
+    let feb29 = NaiveDate::from_ymd_opt(2024, 2, 29)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let rule = "DTSTART:20240229T090000\nRRULE:FREQ=YEARLY;COUNT=2\n";
+    assert_eq!(
+        recurrence_end(rule, feb29, feb29 + Duration::hours(1)),
+        None
+    );
+    assert_eq!(
+        bound("RRULE:FREQ=DAILY;COUNT=3\nRDATE:20300101T090000"),
+        None
+    );
+
+    // Series that ended long ago aren't expanded at all
+    let record = EventRecord {
+        name: "Old standup".to_string(),
+        start,
+        end,
+        all_day: false,
+        recurrence: Some("DTSTART:20200106T090000\nRRULE:FREQ=DAILY;COUNT=3\n".to_string()),
+        last_end: bound("RRULE:FREQ=DAILY;COUNT=3"),
+        due: None,
+        link: None,
+        location: None,
+        categories: Vec::new(),
+        description: None,
+        organizer: None,
+        attendees: 0,
+        status: Status::Confirmed,
+    };
+    let (sod, eod) = extraction_window(Local::now(), WindowMode::default());
+    assert!(record.entries(sod, eod).unwrap().is_empty());
+}
+
+#[test]
+fn test_availability() {
+    use crate::availability::{format_availability, free_blocks, WorkingHours};
+
+    let day = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+    let at = |h, m| day.and_hms_opt(h, m, 0).unwrap();
+    let hours: WorkingHours = "09:00-17:00".parse().unwrap();
+    assert_eq!(hours, WorkingHours::default());
+    assert!("17:00-09:00".parse::<WorkingHours>().is_err());
+
+    let entries = vec![
+        AgendaEntry::new("Standup".to_string(), at(9, 0), Duration::minutes(30)),
+        AgendaEntry::new("Review".to_string(), at(11, 30), Duration::hours(2)),
+        AgendaEntry::new("Overlap".to_string(), at(13, 0), Duration::hours(1)),
+        all_day_entry("Holiday", at(0, 0).date(), 1),
+        AgendaEntry::new("Late".to_string(), at(16, 50), Duration::hours(1)),
+    ];
+
+    let blocks = free_blocks(&entries, day, hours, at(7, 0));
+    assert_eq!(
+        blocks,
+        vec![(at(9, 30), at(11, 30)), (at(14, 0), at(16, 50))]
+    );
+    assert_eq!(
+        format_availability(&blocks, "CEST", &DisplayOptions::default()),
+        "free 09:30–11:30, 14:00–16:50 CEST"
+    );
+    let ascii = DisplayOptions {
+        ascii: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_availability(&blocks, "CEST", &ascii),
+        "free 09:30-11:30, 14:00-16:50 CEST"
+    );
+
+    // Later in the day only what's left counts, starting at the next quarter hour
+    let blocks = free_blocks(&entries, day, hours, at(14, 7));
+    assert_eq!(blocks, vec![(at(14, 15), at(16, 50))]);
+    assert_eq!(
+        format_availability(&[], "CEST", &DisplayOptions::default()),
+        "no free time"
+    );
+}
+
+#[test]
+fn test_load_multiple_sources() {
+    use crate::sources::{load_all, LoadOptions};
+
+    let dir = TempDir::new("multi");
+    let today = Local::now().date_naive();
+    let work = dir.join("work.rem");
+    let home = dir.join("home.rem");
+    std::fs::write(&work, format!("REM {} AT 15:00 MSG Review\n", today)).unwrap();
+    std::fs::write(&home, format!("REM {} AT 09:00 MSG Gym\n", today)).unwrap();
+
+    let sources = [
+        (work.to_string_lossy(), LoadOptions::default()),
+        (home.to_string_lossy(), LoadOptions::default()),
+    ];
+    let collections = load_all(&sources).unwrap();
+
+    let (sod, eod) = extraction_window(Local::now(), WindowMode::Days(0));
+    let names: Vec<String> = collections
+        .iter()
+        .flat_map(|c| c.entries(sod, eod))
+        .sorted_by_key(|e| e.start)
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, ["Gym", "Review"]);
+
+    // A missing source is an error rather than silently skipped
+    assert!(load_all(&[("/nonexistent.rem", LoadOptions::default())]).is_err());
+}
+
+#[test]
+fn test_pick_config_sources() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+
+    let config = Config::parse(
+        r#"
+        [[source]]
+        path = "~/.calendars/work"
+        name = "Work"
+
+        [[source]]
+        path = "https://example.com/oncall.ics"
+        label = "Oncall"
+
+        [[source]]
+        path = "caldav:https://cloud.example.com"
+        calendars = ["Family"]
+        "#,
+    )
+    .unwrap();
+    let defaults = LoadOptions {
+        calendars: vec!["work".to_owned(), "oncall".to_owned()],
+        ..Default::default()
+    };
+    let sources = config.sources(&defaults).unwrap();
+    // Picked whole by their name or label, the server isn't even asked
+    assert_eq!(sources.len(), 2);
+    assert!(sources[0].1.calendars.is_empty());
+    assert!(sources[1].1.calendars.is_empty());
+
+    // Names of no source are looked for on the servers of the others
+    let defaults = LoadOptions {
+        calendars: vec!["work".to_owned(), "Personal".to_owned()],
+        ..Default::default()
+    };
+    let sources = config.sources(&defaults).unwrap();
+    assert_eq!(sources.len(), 3);
+    assert!(sources[0].1.calendars.is_empty());
+    assert_eq!(sources[1].1.calendars, ["Personal"]);
+    assert_eq!(sources[2].1.calendars, ["Personal", "Family"]);
+
+    let sources = config.sources(&LoadOptions::default()).unwrap();
+    assert_eq!(sources.len(), 3);
+    assert_eq!(sources[2].1.calendars, ["Family"]);
+}
+
+#[test]
+fn test_config_file() {
+    use crate::config::{Config, Environment};
+    use crate::sources::LoadOptions;
+
+    let config = Config::parse(
+        r##"
+        display = "compact"
+        days = 2
+        separator = " | "
+        exclude = ["Lunch"]
+
+        [[source]]
+        path = "~/.calendars/work"
+        name = "Work"
+        color = "#00cd00"
+
+        [[source]]
+        path = "caldav:https://cloud.example.com"
+        calendars = ["Family"]
+        user = "me"
+        "##,
+    )
+    .unwrap();
+    let mut opts = DisplayOptions::default();
+    let mut load_opts = LoadOptions::default();
+    config.apply(&mut opts, &mut load_opts).unwrap();
+    assert!(matches!(opts.mode, DisplayMode::Compact));
+    assert!(matches!(opts.window, WindowMode::Days(2)));
+    assert_eq!(opts.separator.as_deref(), Some(" | "));
+
+    let start = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    let lunch = AgendaEntry::new("Team lunch".to_owned(), start, Duration::hours(1));
+    assert!(opts.is_excluded(&lunch));
+
+    let sources = config.sources(&load_opts).unwrap();
+    assert_eq!(sources.len(), 2);
+    assert_eq!(sources[0].1.name.as_deref(), Some("Work"));
+    assert_eq!(sources[1].1.calendars, ["Family"]);
+    assert_eq!(sources[1].1.credentials.as_ref().unwrap().username, "me");
+
+    // The environment overrides the file
+    let environment = Environment {
+        mode: Some("default".to_owned()),
+        ..Default::default()
+    };
+    environment.apply(&mut opts).unwrap();
+    assert!(matches!(opts.mode, DisplayMode::Default));
+
+    // Typos are reported rather than silently ignored
+    assert!(Config::parse("dispaly = \"compact\"").is_err());
+    assert!(Config::parse("display = \"tiny\"")
+        .unwrap()
+        .apply(&mut opts, &mut load_opts)
+        .is_err());
+}
+
+#[test]
+fn test_command_source() {
+    use crate::config::Config;
+    use crate::sources::{load_all, run_command, Document, LoadOptions};
+
+    let config = Config::parse(
+        r#"
+        [[source]]
+        exec = "test -n \"$AGENDA_START\" && printf 'BEGIN:VCALENDAR\\r\\nEND:VCALENDAR\\r\\n'"
+        "#,
+    )
+    .unwrap();
+    let opts = LoadOptions {
+        window: Some((Local::now(), Local::now() + Duration::days(1))),
+        ..Default::default()
+    };
+    let sources = config.sources(&opts).unwrap();
+    assert!(sources[0].0.starts_with("exec:test"));
+    let collections = load_all(&sources).unwrap();
+    assert_eq!(collections.len(), 1);
+    assert!(matches!(&collections[0].documents[..], [Document::Ics(events)] if events.is_empty()));
+
+    // A failing command is an error rather than an empty calendar
+    assert!(load_all(&[("exec:echo oops >&2; exit 3", opts)]).is_err());
+    // And one that hangs is given up on
+    let started = std::time::Instant::now();
+    let mut sleep = std::process::Command::new("sleep");
+    sleep.arg("10");
+    let error = run_command(&mut sleep, std::time::Duration::from_millis(200)).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let output = run_command(
+        std::process::Command::new("sh").args(["-c", "echo out; echo err >&2"]),
+        std::time::Duration::from_secs(5),
+    )
+    .unwrap();
+    assert_eq!(
+        (&output.stdout[..], &output.stderr[..]),
+        (&b"out\n"[..], &b"err\n"[..])
+    );
+    assert!(
+        Config::parse("[[source]]\nexec = \"true\"\npath = \"a.ics\"")
+            .unwrap()
+            .sources(&LoadOptions::default())
+            .is_err()
+    );
+}
+
+#[test]
+fn test_source_labels() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+
+    let config = Config::parse(
+        r#"
+        [[source]]
+        path = "work.ics"
+        label = "W"
+        icon = "💼"
+
+        [[source]]
+        path = "noisy.ics"
+        enabled = false
+        "#,
+    )
+    .unwrap();
+    let sources = config.sources(&LoadOptions::default()).unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].1.label.as_deref(), Some("W"));
+
+    let when = Local::now().naive_local();
+    let mut entry = AgendaEntry::new(
+        "Todo".to_owned(),
+        when + Duration::hours(3),
+        Duration::zero(),
+    );
+    entry.due = Some(entry.start);
+    entry.calendar = Some("Work calendar".to_owned());
+    entry.label = Some("W".to_owned());
+    entry.icon = Some("💼".to_owned());
+    // The label is shown even without --show-calendar, in place of the name
+    let mut opts = DisplayOptions::default();
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "💼 [W] Todo due in 3h"
+    );
+    opts.show_calendar = true;
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "💼 [W] Todo due in 3h"
+    );
+}
+
+#[test]
+fn test_show_location() {
+    let when = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 50, 0)
+        .unwrap();
+    let mut entry = AgendaEntry::new(
+        "Standup".to_owned(),
+        when + Duration::minutes(10),
+        Duration::minutes(15),
+    );
+    entry.location = Some("B2.14".to_owned());
+    let mut opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Standup · 10min"
+    );
+    opts.show_location = true;
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Standup @ B2.14 · 10min"
+    );
+
+    // Only the title gives way to the width
+    opts.title_width = Some(5);
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Stan… @ B2.14 · 10min"
+    );
+    entry.location = None;
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Stan… · 10min"
+    );
+}
+
+#[test]
+fn test_description_snippet() {
+    let when = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let records = crate::fallback::parse(
+        "BEGIN:VCALENDAR\nBEGIN:VTODO\nSUMMARY:Deploy\nDUE:20240506T120000\n\
+         DESCRIPTION:\\nRoll back if the canary fails\\, then page\\nSecond line\n\
+         END:VTODO\nEND:VCALENDAR\n",
+        None,
+    );
+    assert_eq!(
+        records[0].description.as_deref(),
+        Some("\nRoll back if the canary fails, then page\nSecond line")
+    );
+    assert_eq!(unescape_text("a\\\\nb\\;c"), "a\\nb;c");
+
+    let mut entry = records[0]
+        .entries(Local::now(), Local::now())
+        .unwrap()
+        .remove(0);
+    let mut opts = DisplayOptions::default();
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Deploy due in 3h"
+    );
+    opts.show_description = true;
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Deploy due in 3h – Roll back if the canary fails, then page"
+    );
+    opts.description_width = Some(12);
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Deploy due in 3h – Roll back i…"
+    );
+    opts.format = Some("{name}: {description}".parse().unwrap());
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Deploy: Roll back i…"
+    );
+    entry.description = None;
+    opts.format = None;
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Deploy due in 3h"
+    );
+}
+
+#[test]
+fn test_organizer_and_attendees() {
+    let when = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let records = crate::fallback::parse(
+        "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Sync\nDTSTART:20240506T100000\n\
+         DTEND:20240506T103000\nORGANIZER;CN=\"Ada Lovelace\":mailto:ada@example.com\n\
+         ATTENDEE:mailto:ada@example.com\nATTENDEE;CN=Bob:mailto:bob@example.com\n\
+         BEGIN:VALARM\nATTENDEE:mailto:ada@example.com\nEND:VALARM\nEND:VEVENT\n\
+         BEGIN:VEVENT\nSUMMARY:Focus\nDTSTART:20240506T110000\nDTEND:20240506T120000\n\
+         END:VEVENT\nEND:VCALENDAR\n",
+        None,
+    );
+    assert_eq!(records[0].organizer.as_deref(), Some("Ada Lovelace"));
+    assert_eq!(records[0].attendees, 2);
+    assert_eq!(records[1].organizer, None);
+    assert_eq!(records[1].attendees, 0);
+
+    assert_eq!(person("MAILTO:bob@example.com", None), "bob@example.com");
+    assert_eq!(
+        person("mailto:bob@example.com", Some(" ")),
+        "bob@example.com"
+    );
+
+    let opts = DisplayOptions {
+        format: Some("{name} ({organizer}, {attendees})".parse().unwrap()),
+        ..Default::default()
+    };
+    let entry = records[0]
+        .entries(Local::now(), Local::now())
+        .unwrap()
+        .remove(0);
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Sync (Ada Lovelace, 2)"
+    );
+}
+
+#[test]
+fn test_progress_bar() {
+    let start = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(14, 0, 0)
+        .unwrap();
+    let entry = AgendaEntry::new("Review".to_owned(), start, Duration::minutes(75));
+    let when = start + Duration::minutes(45);
+    let mut opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_agenda_entry(&opts, &entry, when),
+        "Review · 45min/30min"
+    );
+    opts.progress_bar = Some(5);
+    assert_eq!(format_agenda_entry(&opts, &entry, when), "Review · ▰▰▰▱▱");
+    assert_eq!(format_agenda_entry(&opts, &entry, start), "Review · ▱▱▱▱▱");
+    // Upcoming events keep their countdown
+    assert_eq!(
+        format_agenda_entry(&opts, &entry, start - Duration::minutes(10)),
+        "Review · 10min"
+    );
+
+    opts.progress_chars = Some("█░".parse().unwrap());
+    assert_eq!(format_agenda_entry(&opts, &entry, when), "Review · ███░░");
+    opts.progress_chars = None;
+    opts.ascii = true;
+    assert_eq!(format_agenda_entry(&opts, &entry, when), "Review - ###--");
+    assert!("▰".parse::<ProgressChars>().is_err());
+}
+
+#[test]
+fn test_khal_config() {
+    use crate::khal::{parse_config, KhalCalendar};
+    use crate::sources::{load_all, LoadOptions};
+
+    let calendars = parse_config(
+        "[calendars]\n\
+         # Synced with vdirsyncer\n\
+         [[work]]\n\
+         path = ~/.calendars/work/\n\
+         color = dark green\n\
+         [[shared]]\n\
+         path = \"~/.calendars/shared/*\"\n\
+         type = discover\n\
+         color = auto\n\
+         [locale]\n\
+         timeformat = %H:%M\n",
+    );
+    assert_eq!(
+        calendars,
+        [
+            KhalCalendar {
+                path: "~/.calendars/work/".to_owned(),
+                name: Some("work".to_owned()),
+                color: Some("#00cd00".to_owned()),
+            },
+            KhalCalendar {
+                path: "~/.calendars/shared/*".to_owned(),
+                name: None,
+                color: None,
+            },
+        ]
+    );
+
+    // The configured name and color replace the collection's own
+    let dir = TempDir::new("khal");
+    let file = dir.join("work.rem");
+    std::fs::write(&file, "REM Mon MSG Standup\n").unwrap();
+    let opts = LoadOptions {
+        name: Some("Work".to_owned()),
+        color: Some("#00cd00".to_owned()),
+        ..Default::default()
+    };
+    let collections = load_all(&[(file.to_string_lossy(), opts)]).unwrap();
+    assert_eq!(collections[0].name.as_deref(), Some("Work"));
+    assert_eq!(collections[0].color.as_deref(), Some("#00cd00"));
+}
+
+#[test]
+fn test_find_nested_collections() {
+    use crate::sources::find_collections;
+
+    let root = TempDir::new("tree");
+    let personal = root.join("fastmail").join("personal");
+    let work = root.join("google").join("work");
+    let hidden = root.join(".trash").join("old");
+    for dir in [&personal, &work, &hidden] {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("event.ics"), "BEGIN:VCALENDAR\nEND:VCALENDAR\n").unwrap();
+    }
+    std::fs::write(root.join("fastmail").join("notes.txt"), "").unwrap();
+
+    let found = find_collections(&root, 0).unwrap();
+    assert_eq!(found, vec![personal, work]);
+}
+
+#[test]
+fn test_resolve_glob_paths() {
+    use crate::sources::resolve;
+
+    let root = TempDir::new("glob");
+    std::fs::create_dir_all(root.join("work")).unwrap();
+    for file in ["home.ics", "work/team.ics", "work/notes.txt"] {
+        std::fs::write(root.join(file), "").unwrap();
+    }
+
+    let pattern = format!("{}/**/*.ics", root.display());
+    let found = resolve(&pattern);
+    let missing = resolve(&format!("{}/*.rem", root.display()));
+
+    assert_eq!(
+        found.unwrap(),
+        vec![root.join("home.ics"), root.join("work/team.ics")]
+    );
+    assert!(missing.is_err());
+    // Plain paths are passed through, even if they don't exist (yet)
+    assert_eq!(
+        resolve("/nonexistent.ics").unwrap(),
+        vec![std::path::PathBuf::from("/nonexistent.ics")]
+    );
+}
+
+#[test]
+fn test_load_from_reader() {
+    use crate::sources::{load_reader, LoadOptions};
+
+    let now = Local::now();
+    let mut calendar = Calendar::new();
+    calendar.push(create_test_event(
+        "Piped",
+        now.naive_local(),
+        Duration::hours(1),
+    ));
+    let text = calendar.done().to_string();
+
+    let collection = load_reader(&mut text.as_bytes(), &LoadOptions::default()).unwrap();
+    let (sod, eod) = extraction_window(now, WindowMode::default());
+    let entries = collection.entries(sod, eod);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "Piped");
+}
+
+// Serve canned HTTP responses on a local port, one connection each, and hand back
+// the requests that came in
+fn serve(responses: Vec<String>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|response| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                // Only the head is needed, the body may be in the same read
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                String::from_utf8_lossy(&request).into_owned()
+            })
+            .collect()
+    });
+    (url, handle)
+}
+
+fn http_response(status: &str, headers: &[&str], body: &str) -> String {
+    let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    for header in headers {
+        response += &format!("{}\r\n", header);
+    }
+    response + &format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+#[test]
+fn test_fetch_remote_calendar() {
+    use crate::remote::{fetch, is_url};
+    use crate::sources::LoadOptions;
+
+    assert!(is_url("https://calendar.example.com/basic.ics"));
+    assert!(!is_url("/home/me/calendar.ics"));
+    assert!(is_url("webcal://p01-caldav.icloud.com/published/2/abc"));
+    assert_eq!(
+        crate::remote::http_url("webcal://p01-caldav.icloud.com/published/2/abc"),
+        "https://p01-caldav.icloud.com/published/2/abc"
+    );
+    assert_eq!(
+        crate::remote::http_url("http://localhost/cal.ics"),
+        "http://localhost/cal.ics"
+    );
+
+    let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let (url, server) = serve(vec![
+        http_response("302 Found", &["Location: /moved.ics"], ""),
+        http_response("200 OK", &["Content-Type: text/calendar"], body),
+        http_response("404 Not Found", &[], "gone"),
+    ]);
+
+    assert_eq!(
+        fetch(&format!("{}/basic.ics", url), &LoadOptions::default())
+            .unwrap()
+            .body,
+        body.as_bytes()
+    );
+    let error = fetch(&format!("{}/basic.ics", url), &LoadOptions::default()).unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with("server responded with HTTP 404"));
+
+    let requests = server.join().unwrap();
+    assert!(requests[1].starts_with("GET /moved.ics "));
+}
+
+#[test]
+fn test_concurrent_sources() {
+    use crate::remote::NetworkOptions;
+    use crate::sources::{load_all, LoadOptions};
+    use std::io::{Read, Write};
+    use std::sync::{mpsc, Arc, Barrier};
+
+    // Each server only answers once the other one has been asked as well, which
+    // never happens if the sources are downloaded one after the other
+    let barrier = Arc::new(Barrier::new(2));
+    let (done, finished) = mpsc::channel();
+    let urls: Vec<String> = (0..2)
+        .map(|_| {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/cal.ics", listener.local_addr().unwrap());
+            let (barrier, done) = (barrier.clone(), done.clone());
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = stream.read(&mut [0; 1024]).unwrap();
+                barrier.wait();
+                let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+                let response = http_response("200 OK", &[], body);
+                stream.write_all(response.as_bytes()).unwrap();
+                done.send(()).unwrap();
+            });
+            url
+        })
+        .collect();
+
+    let opts = LoadOptions {
+        network: NetworkOptions {
+            read_timeout: std::time::Duration::from_secs(2),
+            retries: 0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let sources: Vec<_> = urls.into_iter().map(|url| (url, opts.clone())).collect();
+    let collections = load_all(&sources).unwrap();
+    assert_eq!(collections.len(), 2);
+    for _ in 0..2 {
+        finished.recv().unwrap();
+    }
+}
+
+#[test]
+fn test_retry_transient_failures() {
+    use crate::remote::{fetch, NetworkOptions};
+    use crate::sources::LoadOptions;
+    use std::time::Duration as StdDuration;
+
+    let opts = LoadOptions {
+        network: NetworkOptions {
+            backoff: StdDuration::from_millis(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let (url, server) = serve(vec![
+        http_response("503 Service Unavailable", &[], ""),
+        http_response("200 OK", &[], body),
+        http_response("404 Not Found", &[], ""),
+    ]);
+    let url = format!("{}/basic.ics", url);
+    assert_eq!(fetch(&url, &opts).unwrap().body, body.as_bytes());
+    // Client errors won't go away by trying again
+    let error = fetch(&url, &opts).unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with("server responded with HTTP 404"));
+    assert_eq!(server.join().unwrap().len(), 3);
+}
+
+#[test]
+fn test_conditional_fetch() {
+    use crate::cache::ResponseCache;
+    use crate::remote::{fetch, NetworkOptions};
+    use crate::sources::{LoadOptions, SourceError};
+    use crate::{exit_code, EXIT_NETWORK};
+
+    let dir = TempDir::new("http");
+    let opts = LoadOptions {
+        http_cache: Some(ResponseCache::new(dir.to_path_buf())),
+        network: NetworkOptions {
+            retries: 0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let (url, server) = serve(vec![
+        http_response(
+            "200 OK",
+            &[
+                "ETag: \"v1\"",
+                "Last-Modified: Mon, 06 May 2024 10:00:00 GMT",
+            ],
+            body,
+        ),
+        http_response("304 Not Modified", &["ETag: \"v1\""], ""),
+        http_response("503 Service Unavailable", &[], ""),
+        http_response("404 Not Found", &[], ""),
+    ]);
+    let url = format!("{}/basic.ics", url);
+
+    assert_eq!(fetch(&url, &opts).unwrap().body, body.as_bytes());
+    assert_eq!(fetch(&url, &opts).unwrap().body, body.as_bytes());
+    // When the server fails, the cached copy is shown as stale
+    let fetched = fetch(&url, &opts).unwrap();
+    assert!(fetched.stale.is_some());
+    assert_eq!(fetched.body, body.as_bytes());
+    // But a calendar that's gone isn't shown as if it were still there
+    let error = fetch(&url, &opts).unwrap_err();
+    assert!(matches!(error, SourceError::Status(_, 404)));
+    assert_eq!(exit_code(&error), EXIT_NETWORK);
+
+    // Header names are case-insensitive
+    let requests: Vec<_> = server
+        .join()
+        .unwrap()
+        .iter()
+        .map(|r| r.to_lowercase())
+        .collect();
+    assert!(!requests[0].contains("if-none-match"));
+    assert!(requests[1].contains("if-none-match: \"v1\""));
+    assert!(requests[1].contains("if-modified-since: mon, 06 may 2024 10:00:00 gmt"));
+}
+
+#[test]
+fn test_max_age() {
+    use crate::cache::ResponseCache;
+    use crate::remote::fetch;
+    use crate::sources::LoadOptions;
+    use std::time::Duration as StdDuration;
+
+    let dir = TempDir::new("max-age");
+    let mut opts = LoadOptions {
+        http_cache: Some(ResponseCache::new(dir.to_path_buf())),
+        max_age: Some(StdDuration::from_secs(3600)),
+        ..Default::default()
+    };
+    let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let (url, server) = serve(vec![
+        http_response("200 OK", &["ETag: \"v1\""], body),
+        http_response("304 Not Modified", &["ETag: \"v1\""], ""),
+    ]);
+    let url = format!("{}/holidays.ics", url);
+
+    assert_eq!(fetch(&url, &opts).unwrap().body, body.as_bytes());
+    // Fresh enough, the server isn't asked
+    let fetched = fetch(&url, &opts).unwrap();
+    assert!(fetched.stale.is_none());
+    assert_eq!(fetched.body, body.as_bytes());
+    // Once too old, it is
+    opts.max_age = Some(StdDuration::ZERO);
+    assert_eq!(fetch(&url, &opts).unwrap().body, body.as_bytes());
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].to_lowercase().contains("if-none-match: \"v1\""));
+}
+
+#[test]
+fn test_offline() {
+    use crate::cache::ResponseCache;
+    use crate::i18n::{humanize_ago, Language};
+    use crate::remote::fetch;
+    use crate::sources::{load_all, LoadOptions};
+
+    let dir = TempDir::new("offline");
+    let mut opts = LoadOptions {
+        http_cache: Some(ResponseCache::new(dir.to_path_buf())),
+        ..Default::default()
+    };
+    let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let (url, server) = serve(vec![http_response("200 OK", &[], body)]);
+    let cached_url = format!("{}/cached.ics", url);
+    let fetched = fetch(&cached_url, &opts).unwrap();
+    assert!(fetched.stale.is_none());
+
+    // Offline, the cached copy is used and marked with when it was downloaded
+    opts.offline = true;
+    let fetched = fetch(&cached_url, &opts).unwrap();
+    assert_eq!(fetched.body, body.as_bytes());
+    assert!(fetched.stale.is_some());
+    let other_url = format!("{}/other.ics", url);
+    assert!(fetch(&other_url, &opts).unwrap().body.is_empty());
+    // Online services and calendars never downloaded are skipped rather than blanking
+    // the other sources
+    let collections = load_all(&[
+        ("caldav:https://cloud.example.com", opts.clone()),
+        (other_url.as_str(), opts.clone()),
+        (cached_url.as_str(), opts.clone()),
+    ])
+    .unwrap();
+    assert_eq!(collections.len(), 1);
+    assert_eq!(server.join().unwrap().len(), 1);
+
+    assert_eq!(
+        humanize_ago(Duration::seconds(10), Language::English),
+        "a minute ago"
+    );
+    assert_eq!(
+        humanize_ago(Duration::hours(3), Language::English),
+        "3 hours ago"
+    );
+}
+
+#[test]
+fn test_http_authentication() {
+    use crate::auth::{digest_authorization, Challenge, Credentials};
+    use crate::remote::fetch;
+    use crate::sources::LoadOptions;
+
+    // RFC 2617, section 3.5
+    let challenge = Challenge::parse(
+        "Digest realm=\"testrealm@host.com\", qop=\"auth,auth-int\", \
+         nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", opaque=\"5ccc069c403ebaf9f0171e9517f40e41\"",
+    )
+    .unwrap();
+    assert_eq!(challenge.scheme, "digest");
+    let mufasa = Credentials {
+        username: "Mufasa".to_owned(),
+        password: "Circle Of Life".to_owned(),
+    };
+    let authorization =
+        digest_authorization(&challenge, &mufasa, "GET", "/dir/index.html", "0a4f113b").unwrap();
+    assert!(authorization.contains("response=\"6629fae49393a05397450978507c4ef1\""));
+    assert!(authorization.contains("opaque=\"5ccc069c403ebaf9f0171e9517f40e41\""));
+
+    let body = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+    let (url, server) = serve(vec![
+        http_response(
+            "401 Unauthorized",
+            &["WWW-Authenticate: Basic realm=\"Radicale\""],
+            "",
+        ),
+        http_response("200 OK", &[], body),
+        http_response("401 Unauthorized", &[], ""),
+    ]);
+    let opts = LoadOptions {
+        credentials: Some(Credentials {
+            username: "user".to_owned(),
+            password: "pass".to_owned(),
+        }),
+        ..Default::default()
+    };
+    let url = format!("{}/user/calendar.ics", url);
+    assert_eq!(fetch(&url, &opts).unwrap().body, body.as_bytes());
+    // Without a challenge to answer, there's nothing to retry
+    let error = fetch(&url, &opts).unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with("server responded with HTTP 401"));
+
+    let requests = server.join().unwrap();
+    assert!(!requests[0].to_lowercase().contains("authorization"));
+    assert!(requests[1].contains("Basic dXNlcjpwYXNz"));
+
+    // A server the calendar moved to doesn't get the password
+    let (elsewhere, other) = serve(vec![http_response(
+        "401 Unauthorized",
+        &["WWW-Authenticate: Basic realm=\"Elsewhere\""],
+        "",
+    )]);
+    let location = format!("Location: {}/calendar.ics", elsewhere);
+    let (url, server) = serve(vec![http_response("302 Found", &[&location], "")]);
+    let error = fetch(&format!("{}/user/calendar.ics", url), &opts).unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with("server responded with HTTP 401"));
+    server.join().unwrap();
+    let requests = other.join().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(!requests[0].to_lowercase().contains("authorization"));
+}
+
+#[test]
+fn test_google_events() {
+    use crate::google::EventList;
+
+    let page = r#"{
+        "summary": "Work",
+        "items": [
+            {
+                "status": "confirmed",
+                "summary": "Standup",
+                "start": {"dateTime": "2024-05-06T10:00:00Z"},
+                "end": {"dateTime": "2024-05-06T10:15:00Z"},
+                "hangoutLink": "https://meet.google.com/abc-defg-hij"
+            },
+            {
+                "summary": "Offsite",
+                "start": {"date": "2024-05-07"},
+                "end": {"date": "2024-05-08"},
+                "location": "Berlin"
+            },
+            {"status": "cancelled", "summary": "Moved", "start": {"date": "2024-05-07"}}
+        ]
+    }"#;
+    let list: EventList = serde_json::from_str(page).unwrap();
+    let records = list.records();
+    assert_eq!(records.len(), 2);
+
+    let standup = &records[0];
+    let utc = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    assert_eq!(
+        standup.start,
+        utc.and_utc().with_timezone(&Local).naive_local()
+    );
+    assert_eq!(standup.end - standup.start, Duration::minutes(15));
+    assert_eq!(
+        standup.link.as_deref(),
+        Some("https://meet.google.com/abc-defg-hij")
+    );
+
+    let offsite = &records[1];
+    assert!(offsite.all_day);
+    assert!(!standup.all_day);
+    assert_eq!(offsite.name, "Offsite");
+    assert_eq!(
+        offsite.start,
+        NaiveDate::from_ymd_opt(2024, 5, 7)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    );
+    assert_eq!(offsite.end - offsite.start, Duration::days(1));
+    assert_eq!(offsite.link, None);
+}
+
+#[test]
+fn test_oauth_device_flow() {
+    use crate::oauth::{access_token, login, OAuthClient, Provider};
+
+    let json = ["Content-Type: application/json"];
+    let (url, server) = serve(vec![
+        http_response(
+            "200 OK",
+            &json,
+            r#"{"device_code": "dev", "user_code": "ABCD-EFGH",
+                "verification_url": "https://www.google.com/device", "interval": 0}"#,
+        ),
+        http_response(
+            "428 Precondition Required",
+            &json,
+            r#"{"error": "authorization_pending"}"#,
+        ),
+        http_response(
+            "200 OK",
+            &json,
+            r#"{"access_token": "first", "expires_in": 0, "refresh_token": "refresh"}"#,
+        ),
+        http_response(
+            "200 OK",
+            &json,
+            r#"{"access_token": "second", "expires_in": 3600}"#,
+        ),
+    ]);
+    let provider = Provider {
+        name: "test",
+        device_url: format!("{}/device", url),
+        token_url: format!("{}/token", url),
+        scope: "calendar".to_owned(),
+    };
+    let client = OAuthClient {
+        id: "client".to_owned(),
+        secret: Some("secret".to_owned()),
+        ..Default::default()
+    };
+    let dir = TempDir::new("token");
+    let token_file = dir.join("token.json");
+
+    let mut out = Vec::new();
+    login(&provider, &client, &token_file, &mut out).unwrap();
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .contains("https://www.google.com/device and enter the code ABCD-EFGH"));
+
+    // The first token expired right away, so it's renewed, and the new one is kept
+    assert_eq!(
+        access_token(&provider, &client, &token_file).unwrap(),
+        "second"
+    );
+    assert_eq!(
+        access_token(&provider, &client, &token_file).unwrap(),
+        "second"
+    );
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("POST /device "));
+    assert!(requests[3].starts_with("POST /token "));
+}
+
+#[test]
+fn test_caldav_report() {
+    use crate::caldav::{calendar_data, calendar_query, load};
+    use crate::sources::LoadOptions;
+    use chrono::TimeZone;
+
+    let multistatus = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/dav/calendars/me/personal/standup.ics</d:href>
+    <d:propstat>
+      <d:prop><cal:calendar-data>BEGIN:VCALENDAR&#13;
+SUMMARY:Tom &amp; Jerry&#13;
+END:VCALENDAR</cal:calendar-data></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/dav/calendars/me/personal/review.ics</d:href>
+    <d:propstat>
+      <d:prop><cal:calendar-data><![CDATA[BEGIN:VCALENDAR
+END:VCALENDAR]]></cal:calendar-data></d:prop>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+    let documents = calendar_data(multistatus).unwrap();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(
+        documents[0],
+        "BEGIN:VCALENDAR\r\nSUMMARY:Tom & Jerry\r\nEND:VCALENDAR"
+    );
+    assert_eq!(documents[1], "BEGIN:VCALENDAR\nEND:VCALENDAR");
+
+    let empty = r#"<d:multistatus xmlns:d="DAV:"/>"#;
+    let (url, server) = serve(vec![http_response(
+        "207 Multi-Status",
+        &["Content-Type: application/xml"],
+        empty,
+    )]);
+    let start = Local.with_ymd_and_hms(2024, 5, 6, 0, 0, 0).unwrap();
+    let opts = LoadOptions {
+        window: Some((start, start + Duration::days(1))),
+        ..Default::default()
+    };
+    let collections = load(&format!("{}/dav/calendars/me/personal/", url), &opts).unwrap();
+    assert_eq!(collections.len(), 1);
+    assert!(collections[0]
+        .entries(start, start + Duration::days(1))
+        .is_empty());
+
+    let request = &server.join().unwrap()[0];
+    assert!(request.starts_with("REPORT /dav/calendars/me/personal/ "));
+    assert!(request.to_lowercase().contains("depth: 1"));
+    let time_range = format!(
+        "start=\"{}\"",
+        start.with_timezone(&chrono::Utc).format("%Y%m%dT%H%M%SZ")
+    );
+    assert!(calendar_query(opts.window).contains(&time_range));
+}
+
+#[test]
+fn test_caldav_discovery() {
+    use crate::caldav::load;
+    use crate::remote::resolve_url;
+    use crate::sources::LoadOptions;
+
+    assert_eq!(
+        resolve_url(
+            "https://cloud.example.com/dav/principals/me/",
+            "/dav/calendars/me/"
+        ),
+        "https://cloud.example.com/dav/calendars/me/"
+    );
+    assert_eq!(
+        resolve_url("https://cloud.example.com/dav/calendars/me/", "work/"),
+        "https://cloud.example.com/dav/calendars/me/work/"
+    );
+    assert_eq!(
+        resolve_url("https://cloud.example.com", "https://dav.example.com/"),
+        "https://dav.example.com/"
+    );
+
+    let xml = ["Content-Type: application/xml; charset=utf-8"];
+    let (url, server) = serve(vec![
+        http_response("301 Moved Permanently", &["Location: /dav/"], ""),
+        http_response(
+            "207 Multi-Status",
+            &xml,
+            r#"<d:multistatus xmlns:d="DAV:"><d:response><d:href>/dav/</d:href>
+<d:propstat><d:prop><d:current-user-principal><d:href>/dav/principals/me/</d:href>
+</d:current-user-principal></d:prop></d:propstat></d:response></d:multistatus>"#,
+        ),
+        http_response(
+            "207 Multi-Status",
+            &xml,
+            r#"<multistatus xmlns="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav"><response>
+<href>/dav/principals/me/</href><propstat><prop><C:calendar-home-set>
+<href>/dav/calendars/me/</href></C:calendar-home-set></prop></propstat></response></multistatus>"#,
+        ),
+        http_response(
+            "207 Multi-Status",
+            &xml,
+            r##"<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav"
+xmlns:a="http://apple.com/ns/ical/">
+<d:response><d:href>/dav/calendars/me/</d:href><d:propstat><d:prop>
+<d:resourcetype><d:collection/></d:resourcetype><d:displayname>me</d:displayname>
+</d:prop></d:propstat></d:response>
+<d:response><d:href>/dav/calendars/me/personal/</d:href><d:propstat><d:prop>
+<d:resourcetype><d:collection/><c:calendar/></d:resourcetype>
+<d:displayname>Personal</d:displayname><a:calendar-color>#FF0000</a:calendar-color>
+</d:prop></d:propstat></d:response>
+<d:response><d:href>/dav/calendars/me/work/</d:href><d:propstat><d:prop>
+<d:resourcetype><d:collection/><c:calendar/></d:resourcetype>
+<d:displayname>Work</d:displayname><a:calendar-color>#0000FF</a:calendar-color>
+</d:prop></d:propstat></d:response>
+</d:multistatus>"##,
+        ),
+        http_response(
+            "207 Multi-Status",
+            &xml,
+            r#"<d:multistatus xmlns:d="DAV:"/>"#,
+        ),
+    ]);
+    let opts = LoadOptions {
+        calendars: vec!["work".to_owned()],
+        ..Default::default()
+    };
+    let collections = load(&url, &opts).unwrap();
+    assert_eq!(collections.len(), 1);
+    assert_eq!(collections[0].name.as_deref(), Some("Work"));
+    assert_eq!(collections[0].color.as_deref(), Some("#0000FF"));
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("PROPFIND /.well-known/caldav "));
+    assert!(requests[1].starts_with("PROPFIND /dav/ "));
+    assert!(requests[3].to_lowercase().contains("depth: 1"));
+    assert!(requests[4].starts_with("REPORT /dav/calendars/me/work/ "));
+}
+
+#[test]
+fn test_graph_events() {
+    use crate::graph::EventList;
+
+    let page = r#"{
+        "@odata.context": "https://graph.microsoft.com/v1.0/$metadata#users('me')/calendarView",
+        "value": [
+            {
+                "subject": "1:1",
+                "start": {"dateTime": "2024-05-06T13:00:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2024-05-06T13:30:00.0000000", "timeZone": "UTC"},
+                "isAllDay": false,
+                "isCancelled": false,
+                "showAs": "busy",
+                "onlineMeeting": {"joinUrl": "https://teams.microsoft.com/l/meetup-join/abc"},
+                "location": {"displayName": "Microsoft Teams Meeting"}
+            },
+            {
+                "subject": "Holiday",
+                "start": {"dateTime": "2024-05-09T00:00:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2024-05-10T00:00:00.0000000", "timeZone": "UTC"},
+                "isAllDay": true,
+                "showAs": "tentative"
+            },
+            {
+                "subject": "Cancelled sync",
+                "start": {"dateTime": "2024-05-06T15:00:00.0000000", "timeZone": "UTC"},
+                "isCancelled": true
+            }
+        ]
+    }"#;
+    let list: EventList = serde_json::from_str(page).unwrap();
+    let records = list.records();
+    assert_eq!(records.len(), 2);
+
+    let utc = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(13, 0, 0)
+        .unwrap();
+    assert_eq!(
+        records[0].start,
+        utc.and_utc().with_timezone(&Local).naive_local()
+    );
+    assert_eq!(records[0].end - records[0].start, Duration::minutes(30));
+    assert_eq!(
+        records[0].link.as_deref(),
+        Some("https://teams.microsoft.com/l/meetup-join/abc")
+    );
+    // All-day events stay on their dates whatever the local zone
+    assert_eq!(
+        records[1].start,
+        NaiveDate::from_ymd_opt(2024, 5, 9)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    );
+    assert_eq!(records[1].end - records[1].start, Duration::days(1));
+    assert!(records[1].all_day);
+    assert!(!records[0].all_day);
+    assert_eq!(records[0].status, Status::Confirmed);
+    assert_eq!(records[1].status, Status::Tentative);
+}
+
+#[test]
+fn test_eds_sources() {
+    use crate::eds::{parse_source, time_range_query};
+    use chrono::TimeZone;
+
+    let calendar = "[Data Source]\nDisplayName=Work\nEnabled=true\nParent=google-account\n\n\
+                    [Calendar]\nBackendName=caldav\nColor=#62a0ea\nSelected=true\n";
+    assert_eq!(
+        parse_source("a1b2", calendar),
+        Some(crate::eds::EdsSource {
+            uid: "a1b2".to_owned(),
+            name: Some("Work".to_owned()),
+            color: Some("#62a0ea".to_owned()),
+        })
+    );
+    let disabled = calendar.replace("Enabled=true", "Enabled=false");
+    assert_eq!(parse_source("a1b2", &disabled), None);
+    let address_book = "[Data Source]\nDisplayName=Contacts\n\n[Address Book]\nBackendName=local\n";
+    assert_eq!(parse_source("c3d4", address_book), None);
+
+    let start = chrono::Utc.with_ymd_and_hms(2024, 5, 6, 8, 0, 0).unwrap();
+    let window = Some((
+        start.with_timezone(&Local),
+        (start + Duration::hours(32)).with_timezone(&Local),
+    ));
+    assert_eq!(
+        time_range_query(window),
+        "(occur-in-time-range? (make-time \"20240506T080000Z\") (make-time \"20240507T160000Z\"))"
+    );
+}
+
+#[test]
+fn test_thunderbird_profile() {
+    use crate::thunderbird::{default_profile, parse_prefs, to_ics, StoredEvent, TbCalendar};
+    use std::path::{Path, PathBuf};
+
+    let dir = Path::new("/home/me/.thunderbird");
+    let profiles = "[Profile1]\nName=old\nIsRelative=1\nPath=aaaa.old\nDefault=1\n\n\
+                    [Profile0]\nName=default-release\nIsRelative=1\nPath=bbbb.default-release\n\n\
+                    [Install4F96D1932A9F858E]\nDefault=bbbb.default-release\nLocked=1\n";
+    assert_eq!(
+        default_profile(dir, profiles),
+        Some(dir.join("bbbb.default-release"))
+    );
+    let legacy = "[General]\nStartWithLastProfile=1\n\n[Profile0]\nName=default\nIsRelative=0\n\
+                  Path=/srv/profile\nDefault=1\n";
+    assert_eq!(
+        default_profile(dir, legacy),
+        Some(PathBuf::from("/srv/profile"))
+    );
+
+    let prefs = r##"user_pref("calendar.registry.0f3c.cache.enabled", true);
+user_pref("calendar.registry.0f3c.color", "#a8c2e1");
+user_pref("calendar.registry.0f3c.name", "Work \"Team\"");
+user_pref("calendar.registry.0f3c.uri", "https://cloud.example.com/dav/work/");
+user_pref("calendar.registry.9d1e.disabled", true);
+user_pref("calendar.registry.9d1e.name", "Old");
+user_pref("mail.smtpserver.smtp1.hostname", "smtp.example.com");"##;
+    assert_eq!(
+        parse_prefs(prefs),
+        [
+            TbCalendar {
+                id: "0f3c".to_owned(),
+                name: Some("Work \"Team\"".to_owned()),
+                color: Some("#a8c2e1".to_owned()),
+                disabled: false,
+            },
+            TbCalendar {
+                id: "9d1e".to_owned(),
+                name: Some("Old".to_owned()),
+                color: None,
+                disabled: true,
+            },
+        ]
+    );
+
+    // 2024-05-06 08:00 UTC, in microseconds
+    let start = 1_714_982_400_000_000;
+    let hour = 3_600_000_000;
+    let events = [
+        StoredEvent {
+            id: "standup".to_owned(),
+            title: "Standup, daily".to_owned(),
+            flags: 16,
+            start,
+            start_tz: "Europe/Berlin".to_owned(),
+            end: start + hour / 4,
+            end_tz: "Europe/Berlin".to_owned(),
+            ..Default::default()
+        },
+        // The standup of the next day, moved by an hour
+        StoredEvent {
+            id: "standup".to_owned(),
+            title: "Standup, daily".to_owned(),
+            start: start + 25 * hour,
+            start_tz: "Europe/Berlin".to_owned(),
+            end: start + 25 * hour + hour / 4,
+            end_tz: "Europe/Berlin".to_owned(),
+            recurrence_id: Some(start + 24 * hour),
+            recurrence_id_tz: Some("Europe/Berlin".to_owned()),
+            ..Default::default()
+        },
+        StoredEvent {
+            id: "holiday".to_owned(),
+            title: "Holiday".to_owned(),
+            flags: 8,
+            start: start - 8 * hour,
+            start_tz: "floating".to_owned(),
+            end: start + 16 * hour,
+            end_tz: "floating".to_owned(),
+            ..Default::default()
+        },
+    ];
+    let recurrence = [("standup".to_owned(), "RRULE:FREQ=DAILY;COUNT=5".to_owned())];
+    let ics = to_ics(&events, &recurrence);
+    assert!(ics.contains(
+        "SUMMARY:Standup\\, daily\r\n\
+         DTSTART;TZID=Europe/Berlin:20240506T100000\r\n\
+         DTEND;TZID=Europe/Berlin:20240506T101500\r\n\
+         RRULE:FREQ=DAILY;COUNT=5\r\n\
+         EXDATE;TZID=Europe/Berlin:20240507T100000\r\n"
+    ));
+    assert!(ics.contains("DTSTART;TZID=Europe/Berlin:20240507T110000\r\n"));
+    assert!(ics.contains("DTSTART;VALUE=DATE:20240506\r\nDTEND;VALUE=DATE:20240507\r\n"));
+    assert_eq!(ics.matches("RRULE").count(), 1);
+}
+
+#[test]
+fn test_watch_calendar_files() {
+    use crate::sources::local_paths;
+    use crate::watch::Watcher;
+    use std::time::{Duration as StdDuration, Instant};
+
+    assert!(local_paths("https://example.com/basic.ics").is_empty());
+    assert!(local_paths("exec:gcalcli agenda --ics").is_empty());
+    assert!(local_paths("-").is_empty());
+
+    let dir = TempDir::new("watch");
+    let file = dir.join("basic.ics");
+    std::fs::write(&file, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+    let paths = local_paths(&file.to_string_lossy());
+    assert_eq!(paths, std::slice::from_ref(&file));
+
+    let watcher = Watcher::new(&paths).unwrap();
+    // Files next to the calendar don't count
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    let started = Instant::now();
+    assert!(!watcher.wait(StdDuration::from_millis(500)));
+    assert!(started.elapsed() >= StdDuration::from_millis(500));
+
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(StdDuration::from_millis(100));
+        std::fs::write(&file, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+    });
+    let started = Instant::now();
+    assert!(watcher.wait(StdDuration::from_secs(30)));
+    assert!(started.elapsed() < StdDuration::from_secs(10));
+    writer.join().unwrap();
+}
+
+#[test]
+fn test_keyring_credentials() {
+    use crate::auth::Credentials;
+    use crate::config::Config;
+    use crate::keyring::credentials;
+    use crate::sources::LoadOptions;
+
+    let config = Config::parse(
+        r#"
+        [[source]]
+        path = "caldav:https://cloud.example.com"
+        user = "me"
+        keyring = "nextcloud"
+        "#,
+    )
+    .unwrap();
+    let sources = config.sources(&LoadOptions::default()).unwrap();
+    assert_eq!(sources[0].1.keyring.as_deref(), Some("nextcloud"));
+
+    // The keyring is only asked for missing passwords
+    assert_eq!(credentials(&LoadOptions::default()).unwrap(), None);
+    let with_password = LoadOptions {
+        keyring: Some("nextcloud".to_owned()),
+        credentials: Some(Credentials {
+            username: "me".to_owned(),
+            password: "secret".to_owned(),
+        }),
+        ..Default::default()
+    };
+    assert_eq!(credentials(&with_password).unwrap(), None);
+    #[cfg(not(feature = "keyring"))]
+    assert!(credentials(&sources[0].1).is_err());
+}
+
+#[test]
+fn test_netrc() {
+    use crate::netrc::{find, parse, Entry};
+
+    let entries = parse(
+        "machine cloud.example.com\n  login me\n  password \"s3cret\"\n\
+         machine ftp.example.com login anonymous password guest\n\
+         macdef init\ncd /pub\n\n\
+         default login fallback password other\n",
+    );
+    assert_eq!(
+        entries[0],
+        Entry {
+            machine: Some("cloud.example.com".to_owned()),
+            login: "me".to_owned(),
+            password: "s3cret".to_owned(),
+        }
+    );
+    assert_eq!(entries.len(), 3);
+    assert_eq!(
+        find(&entries, "ftp.example.com").unwrap().login,
+        "anonymous"
+    );
+    assert_eq!(
+        find(&entries, "calendar.example.org").unwrap().login,
+        "fallback"
+    );
+    assert_eq!(find(&entries[..2], "calendar.example.org"), None);
+}
+
+#[test]
+fn test_waybar_output() {
+    use crate::output::format_waybar;
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 55, 0)
+        .unwrap();
+    let entries = [
+        AgendaEntry::new(
+            "Standup & planning".to_owned(),
+            now + Duration::minutes(5),
+            Duration::minutes(30),
+        ),
+        all_day_entry("Holiday", now.date(), 1),
+        AgendaEntry::new(
+            "Tomorrow".to_owned(),
+            now + Duration::days(1),
+            Duration::hours(1),
+        ),
+    ];
+    let json: serde_json::Value = serde_json::from_str(&format_waybar(
+        "Standup in 5m",
+        &entries,
+        now,
+        false,
+        None,
+        &DisplayOptions::default(),
+    ))
+    .unwrap();
+    assert_eq!(json["text"], "Standup in 5m");
+    assert_eq!(
+        json["tooltip"],
+        "Holiday\n10:00–10:30  Standup &amp; planning"
+    );
+    assert_eq!(json["class"], "urgent");
+
+    let later = now - Duration::hours(1);
+    let json: serde_json::Value = serde_json::from_str(&format_waybar(
+        "Review · 1h",
+        &entries,
+        later,
+        false,
+        None,
+        &DisplayOptions::default(),
+    ))
+    .unwrap();
+    assert!(json.get("class").is_none());
+    let json: serde_json::Value = serde_json::from_str(&format_waybar(
+        "Free",
+        &entries,
+        later,
+        true,
+        None,
+        &DisplayOptions::default(),
+    ))
+    .unwrap();
+    assert_eq!(json["class"], "empty");
+}
+
+#[test]
+fn test_i3blocks_output() {
+    use crate::output::{format_i3blocks, line_color};
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut entry = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    entry.color = Some("#62a0eaff".to_owned());
+    // No polybar tags in plain lines
+    let opts = DisplayOptions {
+        markup: Markup::Plain,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        format_agenda_entry(&opts, &entry, now)
+    );
+    assert_eq!(line_color(&opts, &entry, now).as_deref(), Some("#62a0ea"));
+    entry.due = Some(now - Duration::hours(1));
+    assert_eq!(line_color(&opts, &entry, now).as_deref(), Some("#ff5555"));
+
+    assert_eq!(
+        format_i3blocks("Standup in 30m", "Standup 30m", Some("#62a0ea")),
+        "Standup in 30m\nStandup 30m\n#62a0ea"
+    );
+    assert_eq!(format_i3blocks("", "", None), "\n");
+}
+
+#[test]
+fn test_lemonbar_output() {
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut entry = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    entry.color = Some("#62a0ea".to_owned());
+    entry.link = Some("https://meet.example.com/abc".to_owned());
+    let opts = DisplayOptions {
+        markup: Markup::Lemonbar,
+        ..Default::default()
+    };
+    let text = format_agenda_entry(&opts, &entry, now);
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        format!(
+            "%{{A:xdg-open 'https\\://meet.example.com/abc':}}%{{F#62a0ea}}{}%{{F-}}%{{A}}",
+            text
+        )
+    );
+    // Entries without a link aren't clickable
+    entry.link = None;
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        format!("%{{F#62a0ea}}{}%{{F-}}", text)
+    );
+}
+
+#[test]
+fn test_tmux_output() {
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut entry = AgendaEntry::new(
+        "Review #42".to_owned(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    entry.color = Some("#62a0eaff".to_owned());
+    let opts = DisplayOptions {
+        markup: Markup::Tmux,
+        ..Default::default()
+    };
+    let text = format_agenda_entry(&opts, &entry, now).replace('#', "##");
+    assert!(text.contains("Review ##42"));
+    let line = format_collection_entry(&opts, &entry, now);
+    assert_eq!(line, format!("#[fg=#62a0ea]{}#[fg=default]", text));
+
+    // Styles and escapes don't count towards the width, and are kept whole
+    assert_eq!(truncate_visible(&line, 100, Markup::Tmux, "…"), line);
+    assert_eq!(
+        truncate_visible(
+            "#[fg=#62a0ea]Review ##42#[fg=default]",
+            9,
+            Markup::Tmux,
+            "…"
+        ),
+        "#[fg=#62a0ea]Review ##…#[fg=default]"
+    );
+    assert_eq!(
+        truncate_visible("%{F#ff62a0ea}Standup%{F-} » Lunch", 6, Markup::Polybar, "…"),
+        "%{F#ff62a0ea}Stand…%{F-}"
+    );
+    assert_eq!(truncate_visible("Standup", 4, Markup::Plain, "…"), "Sta…");
+}
+
+#[test]
+fn test_pango_output() {
+    use crate::calendar::Threshold;
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut entry = AgendaEntry::new(
+        "Q&A <all hands>".to_owned(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    entry.color = Some("#62a0eaff".to_owned());
+    let mut opts = DisplayOptions {
+        markup: Markup::Pango,
+        ..Default::default()
+    };
+    let text = format_agenda_entry(&opts, &entry, now)
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    assert!(text.contains("Q&amp;A &lt;all hands&gt;"));
+    let line = format_collection_entry(&opts, &entry, now);
+    assert_eq!(
+        line,
+        format!("<span foreground=\"#62a0ea\">{}</span>", text)
+    );
+
+    // Within a threshold the entry takes its color and turns bold
+    opts.thresholds = vec!["45:#ff5555".parse::<Threshold>().unwrap()];
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        format!("<b><span foreground=\"#ff5555\">{}</span></b>", text)
+    );
+
+    // Tags don't count towards the width, and entities are a single character
+    assert_eq!(
+        truncate_visible(
+            "<b><span foreground=\"#ff5555\">Q&amp;A &lt;all</span></b>",
+            4,
+            Markup::Pango,
+            "…"
+        ),
+        "<b><span foreground=\"#ff5555\">Q&amp;A…</span></b>"
+    );
+}
+
+#[test]
+fn test_polybar_line_tags() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+    use chrono::TimeZone;
+
+    let config = Config::parse(
+        r##"
+        display = "compact"
+
+        [polybar]
+        foreground = "#ffdddddd"
+        underline = "#5c5cff"
+        on-click = "xdg-open https://calendar.example.com"
+        on-scroll-up = "notify-send next"
+        "##,
+    )
+    .unwrap();
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let mut standup = AgendaEntry::new(
+        "Standup".to_owned(),
+        now.naive_local() + Duration::hours(1),
+        Duration::hours(1),
+    );
+    standup.color = Some("#62a0ea".to_owned());
+    let review = AgendaEntry::new(
+        "Review".to_owned(),
+        now.naive_local() + Duration::hours(2),
+        Duration::hours(1),
+    );
+    // The entry's own color hands back to the line's
+    assert_eq!(
+        process_entries(vec![standup, review], &opts, now),
+        "%{A4:notify-send next:}%{A1:xdg-open https\\://calendar.example.com:}\
+         %{u#5c5cff}%{+u}%{F#ffdddddd}%{F#62a0ea}Standup · 1h%{F#ffdddddd} » Review · 2h\
+         %{F-}%{-u}%{u-}%{A}%{A}"
+    );
+
+    // Without events there is nothing to wrap
+    assert_eq!(process_entries(Vec::new(), &opts, now), "");
+    opts.empty = EmptyOutput::Text("No events".to_owned());
+    assert_eq!(process_entries(Vec::new(), &opts, now), "No events");
+
+    // Other bars don't understand the tags
+    opts.markup = Markup::Plain;
+    assert_eq!(
+        process_entries(
+            vec![AgendaEntry::new(
+                "Review".to_owned(),
+                now.naive_local() + Duration::hours(2),
+                Duration::hours(1),
+            )],
+            &opts,
+            now
+        ),
+        "Review · 2h"
+    );
+}
+
+#[test]
+fn test_format_template() {
+    use crate::template::EntryTemplate;
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut entry = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    entry.location = Some("Room 4".to_owned());
+    entry.calendar = Some("Work".to_owned());
+    let mut opts = DisplayOptions {
+        format: Some(
+            "{start:%H:%M}–{end} {name} @ {location} ({until}, {duration}) {{{source}}}"
+                .parse()
+                .unwrap(),
+        ),
+        show_calendar: true,
+        ..Default::default()
+    };
+    // The template places the calendar name itself
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        "09:30–09:45 Standup @ Room 4 (30min, 15min) {Work}"
+    );
+
+    // Once started the countdown is to the end
+    opts.format = Some("{name}: {countdown}".parse().unwrap());
+    assert_eq!(
+        format_agenda_entry(&opts, &entry, now + Duration::minutes(35)),
+        "Standup: 10min"
+    );
+    entry.location = None;
+    opts.format = Some("{name}{location}".parse().unwrap());
+    assert_eq!(format_agenda_entry(&opts, &entry, now), "Standup");
+
+    // Whole percent through the entry, 0 before it and 100 after
+    opts.format = Some("{progress}".parse().unwrap());
+    let progress = |minutes| format_agenda_entry(&opts, &entry, now + Duration::minutes(minutes));
+    assert_eq!(progress(20), "0");
+    assert_eq!(progress(35), "33");
+    assert_eq!(progress(44), "93");
+    assert_eq!(progress(50), "100");
+
+    // Started events take the ongoing template, in place of the display mode's too
+    let started = now + Duration::minutes(35);
+    opts.format = None;
+    opts.ongoing_format = Some("{name} {progress}%".parse().unwrap());
+    assert_eq!(
+        format_agenda_entry(&opts, &entry, now),
+        "Standup 09:30 (in 30min)"
+    );
+    assert_eq!(format_agenda_entry(&opts, &entry, started), "Standup 33%");
+    opts.format = Some("{name} in {countdown}".parse().unwrap());
+    assert_eq!(format_agenda_entry(&opts, &entry, now), "Standup in 30min");
+    assert_eq!(format_agenda_entry(&opts, &entry, started), "Standup 33%");
+
+    assert!("{title}".parse::<EntryTemplate>().is_err());
+    assert!("{name".parse::<EntryTemplate>().is_err());
+    assert!("{start:%Q}".parse::<EntryTemplate>().is_err());
+}
+
+#[test]
+fn test_template_file() {
+    use crate::template::render_file;
+
+    let dir = TempDir::new("template");
+    let path = dir.join("tooltip.tera");
+    std::fs::write(
+        &path,
+        "{% for entry in entries %}{{ entry.start | date(format=\"%a %H:%M\") }} \
+         {{ entry.name }}{% if entry.location %} @ {{ entry.location }}{% endif %} \
+         ({{ entry.countdown }})\n{% endfor %}{{ entries | length }} by {{ now | date(format=\"%H:%M\") }}",
+    )
+    .unwrap();
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut standup = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::minutes(30),
+        Duration::minutes(15),
+    );
+    standup.location = Some("Room 4".to_owned());
+    let review = AgendaEntry::new(
+        "Review".to_owned(),
+        now + Duration::hours(2),
+        Duration::hours(1),
+    );
+    let opts = DisplayOptions::default();
+    assert_eq!(
+        render_file(&path, &[standup, review], &opts, now).unwrap(),
+        "Mon 09:30 Standup @ Room 4 (30min)\nMon 11:00 Review (2h)\n2 by 09:00"
+    );
+
+    // Mistakes in the template are reported with their cause
+    std::fs::write(&path, "{{ entries | nonsense }}").unwrap();
+    let error = render_file(&path, &[], &opts, now).unwrap_err();
+    assert!(error.contains("nonsense"), "{}", error);
+    assert!(render_file(&dir.join("missing"), &[], &opts, now).is_err());
+}
+
+#[test]
+fn test_grapheme_truncation() {
+    // Emoji sequences and combining marks are cut whole
+    assert_eq!(
+        truncate_visible("👩‍👩‍👧 Family dinner", 3, Markup::Plain, "…"),
+        "👩‍👩‍👧 …"
+    );
+    assert_eq!(
+        truncate_visible("Cafe\u{301} meetup", 5, Markup::Plain, "…"),
+        "Cafe\u{301}…"
+    );
+    assert_eq!(
+        truncate_visible("全体会議の準備", 4, Markup::Plain, "…"),
+        "全体会…"
+    );
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let entry = AgendaEntry::new(
+        "Quarterly planning with the whole team".to_owned(),
+        now + Duration::minutes(30),
+        Duration::hours(1),
+    );
+    // Only the title is shortened, so the time stays in view
+    let opts = DisplayOptions {
+        title_width: Some(10),
+        ..Default::default()
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        "Quarterly… 09:30 (in 30min)"
+    );
+}
+
+#[test]
+fn test_marquee() {
+    use crate::marquee::{next_step, window};
+
+    assert_eq!(window("Standup", 10, 4), "Standup");
+    assert_eq!(window("Planning", 5, 0), "Plann");
+    assert_eq!(window("Planning", 5, 4), "ning ");
+    // A gap separates the end from the start coming round again
+    assert_eq!(window("Planning", 5, 7), "g   P");
+    assert_eq!(window("Planning", 5, 11), "Plann");
+    assert_eq!(window("👩‍👩‍👧 dinner", 3, 1), " di");
+
+    let dir = TempDir::new("marquee");
+    let state = dir.join("scroll");
+    assert_eq!(next_step(&state).unwrap(), 0);
+    assert_eq!(next_step(&state).unwrap(), 1);
+    assert_eq!(next_step(&state).unwrap(), 2);
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let entry = AgendaEntry::new(
+        "Quarterly planning".to_owned(),
+        now + Duration::minutes(30),
+        Duration::hours(1),
+    );
+    let opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        title_width: Some(8),
+        scroll_step: Some(10),
+        ..Default::default()
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &entry, now),
+        "planning · 30min"
+    );
+}
+
+#[test]
+fn test_twelve_hour_clock() {
+    use crate::config::Config;
+    use crate::output::format_day;
+    use crate::sources::LoadOptions;
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(13, 0, 0)
+        .unwrap();
+    let entry = AgendaEntry::new(
+        "Review".to_owned(),
+        now + Duration::minutes(90),
+        Duration::minutes(30),
+    );
+    let mut opts = DisplayOptions::default();
+    Config::parse("12h = true")
+        .unwrap()
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert_eq!(opts.clock, Clock::TwelveHour);
+    assert_eq!(
+        format_agenda_entry(&opts, &entry, now),
+        "Review 2:30 PM (in 1.5h)"
+    );
+    assert_eq!(
+        format_day(std::slice::from_ref(&entry), now.date(), &opts),
+        "2:30 PM–3:00 PM  Review"
+    );
+    opts.format = Some("{start} to {end}".parse().unwrap());
+    assert_eq!(
+        format_agenda_entry(&opts, &entry, now),
+        "2:30 PM to 3:00 PM"
+    );
+    assert_eq!(Clock::TwelveHour.format(chrono::NaiveTime::MIN), "12:00 AM");
+}
+
+#[test]
+fn test_locale_formatting() {
+    use crate::week::format_week;
+    use chrono::{Locale, NaiveTime};
+
+    let time = NaiveTime::from_hms_opt(14, 30, 0).unwrap();
+    assert_eq!(Clock::Locale(Locale::de_DE).format(time), "14:30");
+    assert_eq!(Clock::Locale(Locale::en_US).format(time), "2:30 PM");
+    assert_eq!(Clock::Locale(Locale::fi_FI).format(time), "14.30");
+
+    // Day names follow the locale too
+    let monday = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+    let grid = format_week(&[], monday, Some(Locale::de_DE));
+    assert!(grid.trim_start().starts_with("Mo 06"), "{}", grid);
+}
+
+#[test]
+fn test_localized_phrasing() {
+    use crate::i18n::Language;
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut opts = DisplayOptions {
+        language: Language::German,
+        ..Default::default()
+    };
+    let standup = AgendaEntry::new("Standup".to_owned(), now, Duration::minutes(15));
+    assert_eq!(
+        format_agenda_entry(&opts, &standup, now - Duration::minutes(30)),
+        "Standup 09:00 (in 30min)"
+    );
+    assert_eq!(
+        format_agenda_entry(&opts, &standup, now + Duration::minutes(5)),
+        "Standup 09:00 (vor 5min)"
+    );
+
+    let mut report = AgendaEntry::new("Bericht".to_owned(), now, Duration::zero());
+    report.due = Some(now);
+    assert_eq!(
+        format_agenda_entry(&opts, &report, now - Duration::hours(3)),
+        "Bericht fällig in 3h"
+    );
+    opts.language = Language::French;
+    assert_eq!(
+        format_agenda_entry(&opts, &report, now + Duration::hours(1)),
+        "Bericht EN RETARD"
+    );
+}
+
+#[test]
+fn test_day_labels() {
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(23, 0, 0)
+        .unwrap();
+    let at = |days: i64| {
+        AgendaEntry::new(
+            "Standup".to_owned(),
+            (now.date() + Duration::days(days))
+                .and_hms_opt(9, 0, 0)
+                .unwrap(),
+            Duration::minutes(15),
+        )
+    };
+    let mut opts = DisplayOptions {
+        day_label: DayLabel::Relative,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_agenda_entry(&opts, &at(1), now),
+        "Standup tomorrow 09:00 (in 10h)"
+    );
+    assert!(format_agenda_entry(&opts, &at(3), now).starts_with("Standup Thu 09:00 (in "));
+    assert!(format_agenda_entry(&opts, &at(9), now).starts_with("Standup 15 May 09:00"));
+    // Today's entries keep just the time
+    assert!(format_agenda_entry(&opts, &at(0), now).starts_with("Standup 09:00"));
+
+    opts.day_label = DayLabel::Weekday;
+    assert!(format_agenda_entry(&opts, &at(1), now).starts_with("Standup Tue 09:00"));
+
+    // Compact mode has its own setting
+    opts.mode = DisplayMode::Compact;
+    assert_eq!(format_agenda_entry(&opts, &at(1), now), "Standup · 10h");
+    opts.compact_day_label = DayLabel::Relative;
+    assert_eq!(
+        format_agenda_entry(&opts, &at(1), now),
+        "Standup · tomorrow · 10h"
+    );
+
+    opts.format = Some("{day} {start} {name}".parse().unwrap());
+    assert_eq!(
+        format_agenda_entry(&opts, &at(1), now),
+        "tomorrow 09:00 Standup"
+    );
+    assert!("bogus".parse::<DayLabel>().is_err());
+}
+
+#[test]
+fn test_show_end() {
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(14, 40, 0)
+        .unwrap();
+    let review = AgendaEntry::new(
+        "Review".to_owned(),
+        now - Duration::minutes(10),
+        Duration::minutes(30),
+    );
+    let planning = AgendaEntry::new(
+        "Planning".to_owned(),
+        now + Duration::minutes(20),
+        Duration::hours(1),
+    );
+    let mut opts = DisplayOptions {
+        show_end: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_agenda_entry(&opts, &review, now),
+        "Review until 15:00"
+    );
+    assert_eq!(
+        format_agenda_entry(&opts, &planning, now),
+        "Planning 15:00–16:00 (in 20min)"
+    );
+    opts.humanize = true;
+    assert_eq!(
+        format_agenda_entry(&opts, &review, now),
+        "Review until 15:00"
+    );
+
+    opts.mode = DisplayMode::Compact;
+    opts.humanize = false;
+    assert_eq!(
+        format_agenda_entry(&opts, &review, now),
+        "Review · until 15:00"
+    );
+    assert_eq!(
+        format_agenda_entry(&opts, &planning, now),
+        "Planning · 20min"
+    );
+}
+
+#[test]
+fn test_live_countdown() {
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(14, 27, 13)
+        .unwrap();
+    let standup = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::seconds(167),
+        Duration::minutes(15),
+    );
+    let mut opts = DisplayOptions {
+        countdown_below: Some(Duration::minutes(5)),
+        ..Default::default()
+    };
+    assert_eq!(
+        format_agenda_entry(&opts, &standup, now),
+        "Standup 14:30 (in 2:47)"
+    );
+    // Further out the usual countdown is shown
+    assert_eq!(
+        format_agenda_entry(&opts, &standup, now - Duration::minutes(10)),
+        "Standup 14:30 (in 12min)"
+    );
+    // As is the time since the start once it has begun
+    assert_eq!(
+        format_agenda_entry(&opts, &standup, now + Duration::minutes(4)),
+        "Standup 14:30 (1min ago)"
+    );
+
+    opts.mode = DisplayMode::Compact;
+    opts.humanize = true;
+    assert_eq!(
+        format_agenda_entry(&opts, &standup, now + Duration::seconds(160)),
+        "Standup · 0:07"
+    );
+
+    // Any number of minutes is taken, up to the longest window
+    let config = crate::config::Config::parse("countdown = 4294967295").unwrap();
+    config
+        .apply(&mut opts, &mut crate::sources::LoadOptions::default())
+        .unwrap();
+    assert_eq!(
+        opts.countdown_below,
+        Some(Duration::hours(MAX_WINDOW_HOURS))
+    );
+}
+
+#[test]
+fn test_category_filters() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+
+    let start = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let entry = |name: &str, categories: &[&str]| {
+        let mut entry = AgendaEntry::new(name.to_owned(), start, Duration::hours(1));
+        entry.categories = categories.iter().map(|c| c.to_string()).collect();
+        entry
+    };
+    let entries = [
+        entry("Standup", &["Work"]),
+        entry("On call", &["work", "Oncall"]),
+        entry("Christmas", &["Holidays"]),
+        entry("Dentist", &[]),
+    ];
+    let shown = |opts: &DisplayOptions| {
+        entries
+            .iter()
+            .filter(|entry| !opts.is_excluded(entry))
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    let mut opts = DisplayOptions::default();
+    assert_eq!(shown(&opts).len(), 4);
+    opts.exclude_categories = vec!["holidays".to_owned()];
+    assert_eq!(shown(&opts), ["Standup", "On call", "Dentist"]);
+
+    let config =
+        Config::parse("categories = [\"WORK\"]\nexclude-categories = [\"oncall\"]").unwrap();
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert_eq!(shown(&opts), ["Standup"]);
+}
+
+#[test]
+fn test_skip_all_day() {
+    let records = crate::fallback::parse(
+        "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Holiday\nDTSTART;VALUE=DATE:20240506\n\
+         END:VEVENT\nBEGIN:VEVENT\nSUMMARY:On call\nDTSTART:20240506T000000\n\
+         DTEND:20240507T000000\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Standup\n\
+         DTSTART:20240506T090000\nDTEND:20240506T091500\nEND:VEVENT\nEND:VCALENDAR\n",
+        None,
+    );
+    let entries: Vec<_> = records
+        .iter()
+        .flat_map(|record| record.entries(Local::now(), Local::now()).unwrap())
+        .collect();
+    assert!(entries[0].all_day);
+    // Timed from midnight to midnight, a shift rather than a day off
+    assert!(!entries[1].all_day);
+    assert_eq!(entries[1].duration, Duration::days(1));
+
+    let mut opts = DisplayOptions::default();
+    assert!(!opts.is_excluded(&entries[0]));
+    opts.skip_all_day = true;
+    assert!(opts.is_excluded(&entries[0]));
+    assert!(!opts.is_excluded(&entries[1]));
+    assert!(!opts.is_excluded(&entries[2]));
+}
+
+#[test]
+fn test_all_day_label() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+
+    let day = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+    let now = day.and_hms_opt(14, 0, 0).unwrap();
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    let mut holiday = AgendaEntry::new("Holiday".to_owned(), midnight, Duration::days(1));
+    holiday.all_day = true;
+    let mut birthday = AgendaEntry::new(
+        "Birthday".to_owned(),
+        midnight + Duration::days(1),
+        Duration::days(1),
+    );
+    birthday.all_day = true;
+    let shift = AgendaEntry::new("On call".to_owned(), midnight, Duration::days(1));
+
+    let mut opts = DisplayOptions {
+        day_label: DayLabel::Relative,
+        ..Default::default()
+    };
+    assert_eq!(format_agenda_entry(&opts, &holiday, now), "Holiday All day");
+    assert_eq!(
+        format_agenda_entry(&opts, &birthday, now),
+        "Birthday tomorrow All day"
+    );
+    assert_eq!(
+        format_agenda_entry(&opts, &shift, now),
+        "On call 00:00 (14h ago)"
+    );
+    opts.mode = DisplayMode::Compact;
+    opts.humanize = true;
+    assert_eq!(
+        format_agenda_entry(&opts, &holiday, now),
+        "Holiday · All day"
+    );
+
+    let config = Config::parse("all-day-label = \"today\"\nlang = \"de\"").unwrap();
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert_eq!(format_agenda_entry(&opts, &holiday, now), "Holiday today");
+    opts.all_day_label = None;
+    assert_eq!(
+        format_agenda_entry(&opts, &holiday, now),
+        "Holiday Ganztägig"
+    );
+}
+
+#[test]
+fn test_cancelled_events() {
+    let when = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let records = crate::fallback::parse(
+        "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Standup\nDTSTART:20240506T100000\n\
+         DTEND:20240506T101500\nSTATUS:CANCELLED\nEND:VEVENT\n\
+         BEGIN:VEVENT\nSUMMARY:Review\nDTSTART:20240506T140000\nDTEND:20240506T150000\n\
+         STATUS:confirmed\nEND:VEVENT\nEND:VCALENDAR\n",
+        None,
+    );
+    assert_eq!(records[0].status, Status::Cancelled);
+    assert_eq!(records[1].status, Status::Confirmed);
+    assert_eq!(Status::parse("TENTATIVE"), Status::Tentative);
+
+    let entries: Vec<_> = records
+        .iter()
+        .flat_map(|record| record.entries(Local::now(), Local::now()).unwrap())
+        .collect();
+    let mut opts = DisplayOptions::default();
+    assert!(opts.is_excluded(&entries[0]));
+    assert!(!opts.is_excluded(&entries[1]));
+
+    opts.show_cancelled = true;
+    assert!(!opts.is_excluded(&entries[0]));
+    assert_eq!(
+        format_agenda_entry(&opts, &entries[0], when),
+        "Standup 10:00 CANCELLED"
+    );
+    opts.mode = DisplayMode::Compact;
+    assert_eq!(
+        format_agenda_entry(&opts, &entries[0], when),
+        "Standup · CANCELLED"
+    );
+    opts.markup = Markup::Pango;
+    assert_eq!(
+        format_collection_entry(&opts, &entries[0], when),
+        "<s>Standup · CANCELLED</s>"
+    );
+}
+
+#[test]
+fn test_tentative_marker() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+
+    let when = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let records = crate::fallback::parse(
+        "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Offsite sync\nDTSTART:20240506T100000\n\
+         DTEND:20240506T110000\nSTATUS:TENTATIVE\nEND:VEVENT\nEND:VCALENDAR\n",
+        None,
+    );
+    let entry = records[0]
+        .entries(Local::now(), Local::now())
+        .unwrap()
+        .remove(0);
+    assert_eq!(entry.status, Status::Tentative);
+
+    let mut opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Offsite sync? · 1h"
+    );
+    // The marker survives a shortened title
+    opts.title_width = Some(7);
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Offsit…? · 1h"
+    );
+
+    let config = Config::parse("tentative-marker = \" (maybe)\"").unwrap();
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert_eq!(
+        format_collection_entry(&opts, &entry, when),
+        "Offsite sync (maybe) 10:00 (in 1h)"
+    );
+    let confirmed = AgendaEntry {
+        status: Status::Confirmed,
+        ..entry
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &confirmed, when),
+        "Offsite sync 10:00 (in 1h)"
+    );
+}
+
+#[test]
+fn test_category_icons() {
+    use crate::config::Config;
+    use crate::output::format_day;
+    use crate::sources::LoadOptions;
+
+    let config = Config::parse(
+        r#"
+        [icons]
+        categories = { standup = "S", "1:1" = "O" }
+        calendars = { Holidays = "H" }
+        "#,
+    )
+    .unwrap();
+    let mut opts = DisplayOptions {
+        markup: Markup::Plain,
+        ..Default::default()
+    };
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+
+    let records = crate::fallback::parse(
+        "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Standup\nDTSTART:20240506T090000\n\
+         DTEND:20240506T091500\nCATEGORIES:Team,STANDUP\nEND:VEVENT\nEND:VCALENDAR\n",
+        None,
+    );
+    assert_eq!(records[0].categories, ["Team", "STANDUP"]);
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(8, 0, 0)
+        .unwrap();
+    let mut standup = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::hours(1),
+        Duration::minutes(15),
+    );
+    standup.categories = records[0].categories.clone();
+    standup.calendar = Some("Holidays".to_owned());
+    standup.icon = Some("W".to_owned());
+    let mut holiday = all_day_entry("Whit Monday", now.date(), 1);
+    holiday.calendar = Some("Holidays".to_owned());
+    let mut review = AgendaEntry::new(
+        "Review".to_owned(),
+        now + Duration::hours(3),
+        Duration::hours(1),
+    );
+    review.icon = Some("W".to_owned());
+
+    // A category wins over the calendar, and both over the source's icon
+    for entry in [&mut standup, &mut holiday, &mut review] {
+        opts.icons.apply(entry);
+    }
+    assert_eq!(
+        format_collection_entry(&opts, &standup, now),
+        "S Standup 09:00 (in 1h)"
+    );
+    assert_eq!(
+        format_collection_entry(&opts, &review, now),
+        "W Review 11:00 (in 3h)"
+    );
+    assert_eq!(
+        format_day(&[standup, holiday, review], now.date(), &opts),
+        "H Whit Monday\n09:00–09:15  S Standup\n11:00–12:00  W Review"
+    );
+}
+
+#[test]
+fn test_ascii_glyphs() {
+    use chrono::TimeZone;
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 11, 50, 0).unwrap();
+    let entries = vec![
+        AgendaEntry::new(
+            "Standup meeting".to_owned(),
+            now.naive_local() + Duration::minutes(10),
+            Duration::minutes(15),
+        ),
+        AgendaEntry::new(
+            "Lunch".to_owned(),
+            now.naive_local() + Duration::hours(2),
+            Duration::hours(1),
+        ),
+    ];
+    let mut opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        markup: Markup::Plain,
+        ascii: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup meeting - 10min | Lunch - 2h"
+    );
+    opts.title_width = Some(10);
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup... - 10min | Lunch - 2h"
+    );
+
+    opts.mode = DisplayMode::Default;
+    opts.title_width = None;
+    opts.show_end = true;
+    opts.max_width = Some(30);
+    assert_eq!(
+        process_entries(entries, &opts, now),
+        "Standup meeting 12:00-12:15..."
+    );
+}
+
+#[test]
+fn test_color_thresholds() {
+    use crate::output::{format_waybar, line_color};
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 50, 0)
+        .unwrap();
+    let mut standup = AgendaEntry::new(
+        "Standup".to_owned(),
+        now + Duration::minutes(12),
+        Duration::minutes(15),
+    );
+    standup.color = Some("#62a0ea".to_owned());
+    let opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        thresholds: vec!["15:#f9f06b".parse().unwrap(), "5:#ff5555".parse().unwrap()],
+        ..Default::default()
+    };
+    assert_eq!(
+        format_collection_entry(&opts, &standup, now),
+        "%{F#f9f06b}Standup · 12min%{F-}"
+    );
+    let soon = now + Duration::minutes(9);
+    assert_eq!(
+        format_collection_entry(&opts, &standup, soon),
+        "%{F#ff5555}Standup · 3min%{F-}"
+    );
+    // Further out, and once started, the calendar's color is back
+    assert_eq!(
+        line_color(&opts, &standup, now - Duration::hours(1)).as_deref(),
+        Some("#62a0ea")
+    );
+    assert_eq!(
+        line_color(&opts, &standup, now + Duration::minutes(13)).as_deref(),
+        Some("#62a0ea")
+    );
+
+    let color = threshold_color(&opts, &standup, soon);
+    let json: serde_json::Value = serde_json::from_str(&format_waybar(
+        "Standup · 3min",
+        &[standup],
+        soon,
+        false,
+        color,
+        &DisplayOptions::default(),
+    ))
+    .unwrap();
+    assert_eq!(
+        json["text"],
+        "<span color=\"#ff5555\">Standup · 3min</span>"
+    );
+
+    assert!("15".parse::<Threshold>().is_err());
+    assert!("soon:#ff5555".parse::<Threshold>().is_err());
+    assert!("5:red".parse::<Threshold>().is_err());
+    assert!("-5:#ff5555".parse::<Threshold>().is_err());
+    let far = "99999999:#ff5555".parse::<Threshold>().unwrap();
+    assert_eq!(far.within, Duration::hours(MAX_WINDOW_HOURS));
+}
+
+#[test]
+fn test_exit_codes() {
+    use crate::sources::SourceError;
+    use crate::{exit_code, EXIT_NETWORK, EXIT_PARSE};
+    use std::error::Error;
+    use std::path::PathBuf;
+
+    let code = |error: Box<dyn Error>| exit_code(error.as_ref());
+    let parse = SourceError::Parse(PathBuf::from("work.ics"), "bad DTSTART".to_owned());
+    assert_eq!(code(parse.into()), EXIT_PARSE);
+    let http = SourceError::Http("https://example.com".to_owned(), "timed out".to_owned());
+    assert_eq!(code(http.into()), EXIT_NETWORK);
+    let auth = SourceError::Auth("google".to_owned(), "denied".to_owned());
+    assert_eq!(code(auth.into()), EXIT_NETWORK);
+    assert_eq!(code("Unknown flag".into()), 1);
+}
+
+#[test]
+fn test_urgent_line() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+    use chrono::TimeZone;
+
+    let config = Config::parse(
+        r##"
+        display = "compact"
+        urgent = 5
+        urgent-exit = 3
+
+        [polybar]
+        urgent-underline = "#ff5555"
+        "##,
+    )
+    .unwrap();
+    assert_eq!(config.urgent_exit, Some(3));
+    let mut opts = DisplayOptions::default();
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+    assert!(Config::parse("urgent = -5").is_err());
+    let mut far = DisplayOptions::default();
+    Config::parse("urgent = 4294967295")
+        .unwrap()
+        .apply(&mut far, &mut LoadOptions::default())
+        .unwrap();
+    assert_eq!(far.urgent_within, Some(Duration::hours(MAX_WINDOW_HOURS)));
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 56, 0).unwrap();
+    let standup = AgendaEntry::new(
+        "Standup".to_owned(),
+        now.naive_local() + Duration::minutes(4),
+        Duration::minutes(15),
+    );
+    assert_eq!(
+        process_entries(vec![standup.clone()], &opts, now),
+        "%{u#ff5555}%{+u}Standup · 4min%{-u}%{u-}"
+    );
+    let earlier = now - Duration::minutes(2);
+    assert_eq!(
+        process_entries(vec![standup.clone()], &opts, earlier),
+        "Standup · 6min"
+    );
+    // Todos due soon aren't urgent
+    let mut report = standup.clone();
+    report.due = Some(report.start);
+    assert!(!is_urgent(
+        &[report],
+        Duration::minutes(5),
+        now.naive_local()
+    ));
+
+    opts.line_tags.urgent_underline = None;
+    assert_eq!(
+        process_entries(vec![standup], &opts, now),
+        "%{+u}Standup · 4min%{-u}"
+    );
+}
+
+#[test]
+fn test_show_count() {
+    use crate::i18n::Language;
+    use chrono::TimeZone;
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let at = |hours| now.naive_local() + Duration::hours(hours);
+    let entries: Vec<_> = [
+        ("Standup", 1),
+        ("Review", 3),
+        ("Retro", 6),
+        ("Planning", 24),
+    ]
+    .into_iter()
+    .map(|(name, hours)| AgendaEntry::new(name.to_owned(), at(hours), Duration::hours(1)))
+    .collect();
+    let mut opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        markup: Markup::Plain,
+        show_count: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup · 1h » Review · 3h (3 today)"
+    );
+    opts.language = Language::German;
+    opts.more_format = Some("+{}".to_owned());
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup · 1h » Review · 3h +1 (3 heute)"
+    );
+    opts.more_format = None;
+    opts.count_format = Some("[{count}]".to_owned());
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup · 1h » Review · 3h [3]"
+    );
+    // Nothing is left today
+    assert_eq!(
+        process_entries(entries[3..].to_vec(), &opts, now),
+        "Planning · 24h"
+    );
+}
+
+#[test]
+fn test_only_next() {
+    use chrono::TimeZone;
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let at = |minutes| now.naive_local() + Duration::minutes(minutes);
+    let entries = vec![
+        AgendaEntry::new("Review".to_owned(), at(60), Duration::hours(1)),
+        AgendaEntry::new("Standup".to_owned(), at(-5), Duration::minutes(15)),
+        AgendaEntry::new("Retro".to_owned(), at(120), Duration::hours(1)),
+    ];
+    let opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        markup: Markup::Plain,
+        only_next: true,
+        show_count: true,
+        more_format: Some("+{}".to_owned()),
+        lines: Some(3),
+        ..Default::default()
+    };
+    // The ongoing event, alone
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup · 5min/10min"
+    );
+    assert_eq!(
+        process_entries(entries[..1].to_vec(), &opts, now),
+        "Review · 1h"
+    );
+    assert_eq!(process_entries(Vec::new(), &opts, now), "");
+}
+
+#[test]
+fn test_entry_limit() {
+    use chrono::TimeZone;
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let entries: Vec<_> = ["Standup", "Review", "Retro", "Planning"]
+        .into_iter()
+        .zip(1..)
+        .map(|(name, hours)| {
+            let start = now.naive_local() + Duration::hours(hours);
+            AgendaEntry::new(name.to_owned(), start, Duration::minutes(30))
+        })
+        .collect();
+    let mut opts = DisplayOptions {
+        mode: DisplayMode::Compact,
+        markup: Markup::Plain,
+        more_format: Some("+{}".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup · 1h » Review · 2h +2"
+    );
+    opts.limit = Some(1);
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "Standup · 1h +3"
+    );
+    opts.limit = Some(4);
+    assert_eq!(
+        process_entries(entries, &opts, now),
+        "Standup · 1h » Review · 2h » Retro · 3h » Planning · 4h"
+    );
+}
+
+#[test]
+fn test_countdown_only() {
+    use chrono::TimeZone;
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let at = |minutes| now.naive_local() + Duration::minutes(minutes);
+    let mut todo = AgendaEntry::new("Report".to_owned(), at(5), Duration::zero());
+    todo.due = Some(at(5));
+    let entries = vec![
+        AgendaEntry::new("Standup".to_owned(), at(-5), Duration::minutes(15)),
+        todo,
+        AgendaEntry::new("Review".to_owned(), at(12), Duration::hours(1)),
+    ];
+    let mut opts = DisplayOptions {
+        markup: Markup::Plain,
+        countdown_only: true,
+        empty: EmptyOutput::Text("free".to_owned()),
+        ..Default::default()
+    };
+    // Past the ongoing event and the todo
+    assert_eq!(process_entries(entries.clone(), &opts, now), "12min");
+    opts.countdown_below = Some(Duration::minutes(15));
+    assert_eq!(process_entries(entries.clone(), &opts, now), "12:00");
+    assert_eq!(process_entries(entries[..2].to_vec(), &opts, now), "free");
+}
+
+#[test]
+fn test_multi_line_output() {
+    use crate::config::Config;
+    use crate::sources::LoadOptions;
+    use chrono::TimeZone;
+
+    let config = Config::parse("lines = 2\nheader = \"{count} left today\"").unwrap();
+    let mut opts = DisplayOptions {
+        markup: Markup::Plain,
+        ..Default::default()
+    };
+    config
+        .apply(&mut opts, &mut LoadOptions::default())
+        .unwrap();
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let at = |hours| now.naive_local() + Duration::hours(hours);
+    let entries: Vec<_> = [("Standup", 1), ("Review", 3), ("Retro", 6)]
+        .into_iter()
+        .map(|(name, hours)| AgendaEntry::new(name.to_owned(), at(hours), Duration::hours(1)))
+        .collect();
+    assert_eq!(
+        process_entries(entries.clone(), &opts, now),
+        "3 left today\nStandup 10:00 (in 1h)\nReview 12:00 (in 3h)"
+    );
+
+    // Every line is cut on its own
+    opts.header = None;
+    opts.max_width = Some(12);
+    assert_eq!(
+        process_entries(entries, &opts, now),
+        "Standup 10:…\nReview 12:0…"
+    );
+
+    opts.empty = EmptyOutput::Text("Free".to_owned());
+    opts.header = Some("Agenda".to_owned());
+    assert_eq!(process_entries(Vec::new(), &opts, now), "Agenda\nFree");
+}
+
+#[test]
+fn test_html_output() {
+    use crate::output::{format_html, OutputFormat};
+
+    let now = NaiveDate::from_ymd_opt(2024, 5, 6)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let mut standup = AgendaEntry::new(
+        "Standup & <planning>".to_owned(),
+        now + Duration::hours(1),
+        Duration::minutes(15),
+    );
+    standup.color = Some("#62a0eaff".to_owned());
+    standup.link = Some("https://meet.example.com/abc?x=\"1\"".to_owned());
+    let holiday = all_day_entry("Holiday", now.date().succ_opt().unwrap(), 1);
+    let opts = DisplayOptions::default();
+    assert!(matches!("html".parse(), Ok(OutputFormat::Html)));
+    assert_eq!(
+        format_html(&[standup, holiday], &opts, now),
+        "<table style=\"border-collapse: collapse; font-family: sans-serif\">\n  \
+         <tr>\n    \
+         <td style=\"padding: 2px 8px; color: #888888\">10:00</td>\n    \
+         <td style=\"padding: 2px 8px; color: #62a0ea\">\
+         <a href=\"https://meet.example.com/abc?x=&quot;1&quot;\" style=\"color: inherit\">\
+         Standup &amp; &lt;planning&gt;</a></td>\n    \
+         <td style=\"padding: 2px 8px; color: #888888\">in 1h</td>\n  \
+         </tr>\n  \
+         <tr>\n    \
+         <td style=\"padding: 2px 8px; color: #888888\">tomorrow</td>\n    \
+         <td style=\"padding: 2px 8px\">Holiday</td>\n    \
+         <td style=\"padding: 2px 8px; color: #888888\">in 15h</td>\n  \
+         </tr>\n\
+         </table>"
+    );
+
+    let idle = DisplayOptions {
+        empty: EmptyOutput::Text("No events".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        format_html(&[], &idle, now),
+        "<p style=\"font-family: sans-serif; color: #888888\">No events</p>"
+    );
+    assert_eq!(format_html(&[], &opts, now), "");
+}
+
+#[test]
+fn test_export_ics() {
+    use crate::export::to_ics;
+
+    let day = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+    let mut offsite = AgendaEntry::new(
+        "Offsite with ACME".to_owned(),
+        day.and_time(chrono::NaiveTime::MIN),
+        Duration::days(2),
+    );
+    offsite.location = Some("Berlin".to_owned());
+    let mut report = AgendaEntry::new(
+        "Report".to_owned(),
+        day.and_hms_opt(17, 0, 0).unwrap(),
+        Duration::zero(),
+    );
+    report.due = Some(report.start);
+    let entries = [offsite, report];
+
+    let ics = to_ics(&entries, false).to_string();
+    assert!(ics.contains("SUMMARY:Offsite with ACME"));
+    assert!(ics.contains("LOCATION:Berlin"));
+    assert!(ics.contains("DTSTART;VALUE=DATE:20240506"));
+    assert!(ics.contains("DTEND;VALUE=DATE:20240508"));
+    assert!(!ics.contains("Report"));
+
+    let scrubbed = to_ics(&entries, true).to_string();
+    assert!(scrubbed.contains("SUMMARY:Busy"));
+    assert!(!scrubbed.contains("ACME") && !scrubbed.contains("Berlin"));
+    // The same event keeps its UID
+    let uid = |ics: &str| {
+        ics.lines()
+            .find(|line| line.starts_with("UID:"))
+            .map(str::to_owned)
+    };
+    assert_eq!(uid(&ics), uid(&scrubbed));
+}
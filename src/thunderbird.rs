@@ -0,0 +1,362 @@
+use crate::sources::{Collection, LoadOptions, SourceError};
+#[cfg(any(feature = "thunderbird", test))]
+use chrono::{DateTime, NaiveDateTime};
+#[cfg(any(feature = "thunderbird", test))]
+use std::path::{Path, PathBuf};
+
+// `thunderbird:` for the calendars stored by Thunderbird, local ones and the offline
+// copies of CalDAV and ics calendars. The default profile is used unless a profile
+// directory follows, e.g. `thunderbird:/home/me/.thunderbird/abcd1234.work`
+pub const SCHEME: &str = "thunderbird:";
+
+// Bits of `cal_events.flags`
+#[cfg(any(feature = "thunderbird", test))]
+const FLAG_ALL_DAY: i64 = 8;
+#[cfg(any(feature = "thunderbird", test))]
+const FLAG_HAS_RECURRENCE: i64 = 16;
+
+// A calendar registered in the profile's preferences
+#[cfg(any(feature = "thunderbird", test))]
+#[derive(Debug, Default, PartialEq)]
+pub struct TbCalendar {
+    pub id: String,
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub disabled: bool,
+}
+
+// A row of the `cal_events` table. Times are microseconds since the epoch, with the
+// zone they were given in ("UTC", "floating" or an Olson name)
+#[cfg(any(feature = "thunderbird", test))]
+#[derive(Debug, Default)]
+pub struct StoredEvent {
+    pub cal_id: String,
+    pub id: String,
+    pub title: String,
+    pub flags: i64,
+    pub start: i64,
+    pub start_tz: String,
+    pub end: i64,
+    pub end_tz: String,
+    // Set on a changed occurrence of a recurring event, the time it replaces
+    pub recurrence_id: Option<i64>,
+    pub recurrence_id_tz: Option<String>,
+}
+
+// The profile marked as default in `profiles.ini`, relative to `dir` (usually
+// `~/.thunderbird`). Newer versions record the default of each installation in an
+// `[Install...]` section, which takes precedence
+#[cfg(any(feature = "thunderbird", test))]
+pub fn default_profile(dir: &Path, profiles_ini: &str) -> Option<PathBuf> {
+    let mut install_default = None;
+    let mut profile_default = None;
+    let mut first = None;
+    let mut section = "";
+    let (mut path, mut relative, mut is_default) = (None, true, false);
+    let mut finish = |path: Option<&str>, relative: bool, is_default: bool| {
+        let path = path.map(|path| {
+            if relative {
+                dir.join(path)
+            } else {
+                PathBuf::from(path)
+            }
+        });
+        if is_default && profile_default.is_none() {
+            profile_default = path.clone();
+        }
+        if first.is_none() {
+            first = path;
+        }
+    };
+    for line in profiles_ini.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if section.starts_with("Profile") {
+                finish(path.take(), relative, is_default);
+            }
+            section = header;
+            (relative, is_default) = (true, false);
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match (section.starts_with("Install"), key.trim(), value.trim()) {
+            (true, "Default", value) if install_default.is_none() => {
+                install_default = Some(dir.join(value))
+            }
+            (false, "Path", value) => path = Some(value),
+            (false, "IsRelative", value) => relative = value == "1",
+            (false, "Default", value) => is_default = value == "1",
+            _ => {}
+        }
+    }
+    if section.starts_with("Profile") {
+        finish(path, relative, is_default);
+    }
+    install_default.or(profile_default).or(first)
+}
+
+// The calendars listed in `prefs.js`, as `calendar.registry.<id>.<key>` preferences
+#[cfg(any(feature = "thunderbird", test))]
+pub fn parse_prefs(prefs: &str) -> Vec<TbCalendar> {
+    let mut calendars: Vec<TbCalendar> = Vec::new();
+    for line in prefs.lines().map(str::trim) {
+        let Some(rest) = line
+            .strip_prefix("user_pref(\"calendar.registry.")
+            .and_then(|rest| rest.strip_suffix(");"))
+        else {
+            continue;
+        };
+        let Some((name, value)) = rest.split_once("\",") else {
+            continue;
+        };
+        let Some((id, key)) = name.split_once('.') else {
+            continue;
+        };
+        let value = value.trim();
+        let string = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .map(|v| v.replace("\\\"", "\"").replace("\\\\", "\\"));
+        let calendar = match calendars.iter().position(|c| c.id == id) {
+            Some(index) => &mut calendars[index],
+            None => {
+                calendars.push(TbCalendar {
+                    id: id.to_owned(),
+                    ..Default::default()
+                });
+                calendars.last_mut().unwrap()
+            }
+        };
+        match key {
+            "name" => calendar.name = string,
+            "color" => calendar.color = string,
+            "disabled" => calendar.disabled = value == "true",
+            _ => {}
+        }
+    }
+    calendars
+}
+
+// A stored time as an iCalendar property, e.g. `DTSTART;TZID=Europe/Berlin:20240506T100000`.
+// Floating times and dates are stored as if they were UTC
+#[cfg(any(feature = "thunderbird", test))]
+fn time_property(name: &str, micros: i64, tz: &str, all_day: bool) -> Option<String> {
+    let utc = DateTime::from_timestamp_micros(micros)?;
+    let local = |naive: NaiveDateTime| naive.format("%Y%m%dT%H%M%S");
+    Some(match tz {
+        _ if all_day => format!("{};VALUE=DATE:{}", name, utc.format("%Y%m%d")),
+        "floating" => format!("{}:{}", name, local(utc.naive_utc())),
+        "UTC" => format!("{}:{}Z", name, local(utc.naive_utc())),
+        tz => match tz.parse::<chrono_tz::Tz>() {
+            Ok(zone) => format!(
+                "{};TZID={}:{}",
+                name,
+                tz,
+                local(utc.with_timezone(&zone).naive_local())
+            ),
+            Err(_) => format!("{}:{}Z", name, local(utc.naive_utc())),
+        },
+    })
+}
+
+#[cfg(any(feature = "thunderbird", test))]
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// The events of one calendar as an ics calendar. `recurrence` has the RRULE, RDATE,
+// EXDATE... lines of each recurring event by id. Changed occurrences become events of
+// their own, with the occurrence they replace excluded from the series
+#[cfg(any(feature = "thunderbird", test))]
+pub fn to_ics(events: &[StoredEvent], recurrence: &[(String, String)]) -> String {
+    let mut text = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n");
+    for event in events {
+        let all_day = event.flags & FLAG_ALL_DAY != 0;
+        let (Some(start), Some(end)) = (
+            time_property("DTSTART", event.start, &event.start_tz, all_day),
+            time_property("DTEND", event.end, &event.end_tz, all_day),
+        ) else {
+            continue;
+        };
+        text.push_str("BEGIN:VEVENT\r\n");
+        text.push_str(&format!("UID:{}\r\n", event.id));
+        text.push_str(&format!("SUMMARY:{}\r\n", escape_text(&event.title)));
+        text.push_str(&format!("{}\r\n{}\r\n", start, end));
+        if event.recurrence_id.is_none() && event.flags & FLAG_HAS_RECURRENCE != 0 {
+            for (_, line) in recurrence.iter().filter(|(id, _)| *id == event.id) {
+                text.push_str(line.trim());
+                text.push_str("\r\n");
+            }
+            let changed = events
+                .iter()
+                .filter(|other| other.id == event.id)
+                .filter_map(|other| {
+                    time_property(
+                        "EXDATE",
+                        other.recurrence_id?,
+                        other.recurrence_id_tz.as_deref().unwrap_or("UTC"),
+                        all_day,
+                    )
+                });
+            for exdate in changed {
+                text.push_str(&exdate);
+                text.push_str("\r\n");
+            }
+        }
+        text.push_str("END:VEVENT\r\n");
+    }
+    text.push_str("END:VCALENDAR\r\n");
+    text
+}
+
+#[cfg(feature = "thunderbird")]
+mod sqlite {
+    use super::{default_profile, parse_prefs, to_ics, StoredEvent};
+    use crate::sources::{parse_calendar, Collection, Document, LoadOptions, SourceError};
+    use rusqlite::{Connection, OpenFlags};
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
+
+    // Local calendars, and the offline copies of remote ones
+    const DATABASES: [&str; 2] = ["local.sqlite", "cache.sqlite"];
+
+    // Recurring events and changed occurrences are needed whenever they happened, the
+    // rest only if they overlap the window
+    const EVENTS_QUERY: &str = "SELECT cal_id, id, title, flags, event_start, event_start_tz,
+        event_end, event_end_tz, recurrence_id, recurrence_id_tz FROM cal_events
+        WHERE (ical_status IS NULL OR ical_status != 'CANCELLED')
+        AND ((flags & 16) != 0 OR recurrence_id IS NOT NULL
+            OR (event_end > ?1 AND event_start < ?2))";
+    const RECURRENCE_QUERY: &str = "SELECT cal_id, item_id, icalString FROM cal_recurrence";
+
+    fn sqlite_error(path: &Path, e: rusqlite::Error) -> SourceError {
+        SourceError::Service(path.display().to_string(), e.to_string())
+    }
+
+    fn profile(path: &str) -> Result<PathBuf, SourceError> {
+        if !path.is_empty() {
+            return Ok(PathBuf::from(path));
+        }
+        let dir = env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".thunderbird"))
+            .ok_or_else(|| {
+                SourceError::Service("thunderbird".to_owned(), "HOME is unset".to_owned())
+            })?;
+        let ini = dir.join("profiles.ini");
+        let profiles = fs::read_to_string(&ini).map_err(|e| SourceError::Io(ini.clone(), e))?;
+        default_profile(&dir, &profiles).ok_or_else(|| {
+            SourceError::Service("thunderbird".to_owned(), "no profile found".to_owned())
+        })
+    }
+
+    type Rows = (Vec<StoredEvent>, Vec<(String, String, String)>);
+
+    fn read_database(path: &Path, window: (i64, i64)) -> rusqlite::Result<Rows> {
+        // Thunderbird keeps the database open, reading it alongside is fine
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let mut statement = connection.prepare(EVENTS_QUERY)?;
+        let events = statement
+            .query_map([window.0, window.1], |row| {
+                Ok(StoredEvent {
+                    cal_id: row.get(0)?,
+                    id: row.get(1)?,
+                    title: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    flags: row.get(3)?,
+                    start: row.get(4)?,
+                    start_tz: row.get(5)?,
+                    end: row.get(6)?,
+                    end_tz: row.get(7)?,
+                    recurrence_id: row.get(8)?,
+                    recurrence_id_tz: row.get(9)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut statement = connection.prepare(RECURRENCE_QUERY)?;
+        let recurrence = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((events, recurrence))
+    }
+
+    pub fn load(path: &str, opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+        let profile = profile(path)?;
+        let prefs_path = profile.join("prefs.js");
+        let prefs = fs::read_to_string(&prefs_path).map_err(|e| SourceError::Io(prefs_path, e))?;
+        let window = match opts.window {
+            Some((start, end)) => (start.timestamp_micros(), end.timestamp_micros()),
+            None => (i64::MIN, i64::MAX),
+        };
+
+        let mut events = Vec::new();
+        let mut recurrence = Vec::new();
+        for name in DATABASES {
+            let database = profile.join("calendar-data").join(name);
+            if !database.is_file() {
+                continue;
+            }
+            let (found, lines) =
+                read_database(&database, window).map_err(|e| sqlite_error(&database, e))?;
+            events.extend(found);
+            recurrence.extend(lines);
+        }
+
+        let mut collections = Vec::new();
+        for calendar in parse_prefs(&prefs) {
+            if calendar.disabled || !opts.wants_calendar(calendar.name.as_deref()) {
+                continue;
+            }
+            let (own, others): (Vec<_>, Vec<_>) = events
+                .into_iter()
+                .partition(|event| event.cal_id == calendar.id);
+            events = others;
+            if own.is_empty() {
+                continue;
+            }
+            let lines: Vec<_> = recurrence
+                .iter()
+                .filter(|(cal_id, _, _)| *cal_id == calendar.id)
+                .map(|(_, id, line)| (id.clone(), line.clone()))
+                .collect();
+            let label = format!(
+                "thunderbird:{}",
+                calendar.name.as_deref().unwrap_or(&calendar.id)
+            );
+            collections.push(Collection {
+                documents: vec![Document::Ics(parse_calendar(
+                    &to_ics(&own, &lines),
+                    Path::new(&label),
+                    opts,
+                )?)],
+                name: calendar.name,
+                color: calendar.color,
+                stale: None,
+                label: None,
+                icon: None,
+            });
+        }
+        Ok(collections)
+    }
+}
+
+// The calendars of a Thunderbird profile, read from its databases
+pub fn load(path: &str, opts: &LoadOptions) -> Result<Vec<Collection>, SourceError> {
+    #[cfg(feature = "thunderbird")]
+    return sqlite::load(path, opts);
+    #[cfg(not(feature = "thunderbird"))]
+    {
+        let _ = (path, opts);
+        Err(SourceError::Service(
+            "thunderbird".to_owned(),
+            "not supported by this build, rebuild with `--features thunderbird`".to_owned(),
+        ))
+    }
+}
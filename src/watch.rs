@@ -0,0 +1,79 @@
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Changes arriving this soon after the first, e.g. a sync rewriting a whole collection,
+// are read as one
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Notifies of changes to calendar files, with inotify on Linux and FSEvents on macOS
+pub struct Watcher {
+    // Stops watching once dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Watcher {
+    // Watch directories (e.g. vdir collections) with everything in them. Files are
+    // watched through their directory, as editors and sync tools often replace a file
+    // rather than write to it
+    pub fn new(paths: &[PathBuf]) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        // Events come with absolute paths, which relative ones would never match
+        let (dirs, files): (Vec<_>, Vec<_>) = paths
+            .iter()
+            .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+            .partition(|p| p.is_dir());
+        for dir in &dirs {
+            watcher.watch(dir, RecursiveMode::Recursive)?;
+        }
+        for file in &files {
+            let parent = file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(".".as_ref());
+            watcher.watch(parent, RecursiveMode::NonRecursive)?;
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            files,
+            dirs,
+        })
+    }
+
+    // Whether an event is about the calendars, and not another file next to one
+    fn is_relevant(&self, event: &Event) -> bool {
+        (event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove())
+            && event.paths.iter().any(|path| {
+                self.files
+                    .iter()
+                    .any(|file| file.file_name() == path.file_name())
+                    || self.dirs.iter().any(|dir| path.starts_with(dir))
+            })
+    }
+
+    // Wait until a calendar changes or `timeout` passes, whichever comes first, and
+    // tell which one it was
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(Ok(event)) if self.is_relevant(&event) => break,
+                Ok(_) => continue,
+                Err(_) => return false,
+            }
+        }
+        thread::sleep(DEBOUNCE);
+        while self.events.try_recv().is_ok() {}
+        true
+    }
+}
@@ -0,0 +1,138 @@
+use crate::calendar::{AgendaEntry, DisplayOptions};
+use crate::output::format_day;
+use chrono::{Datelike, Duration, Locale, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+use itertools::Itertools;
+
+const COLUMN_WIDTH: usize = 12;
+// Hours always shown, so that a quiet week still looks like a week
+const DAY_START: u32 = 8;
+const DAY_END: u32 = 18;
+
+// The first day of the week containing `today`
+pub fn week_start(today: NaiveDate, first_day: Weekday) -> NaiveDate {
+    let offset = today.weekday().days_since(first_day);
+    today - Duration::days(offset as i64)
+}
+
+fn overlaps(entry: &AgendaEntry, from: NaiveDateTime, to: NaiveDateTime) -> bool {
+    entry.start < to && entry.start + entry.duration.max(Duration::minutes(1)) > from
+}
+
+fn cell(text: &str) -> String {
+    let text: String = text.chars().take(COLUMN_WIDTH).collect();
+    format!("{:<width$}", text, width = COLUMN_WIDTH)
+}
+
+// Names of the entries in a cell. Entries starting in the block are named, ones
+// that started earlier are shown as a continuation
+fn cell_text<'a>(entries: impl Iterator<Item = &'a AgendaEntry>, from: NaiveDateTime) -> String {
+    entries
+        .map(|entry| {
+            if entry.start >= from {
+                entry.name.as_str()
+            } else {
+                "|"
+            }
+        })
+        .join(", ")
+}
+
+// A 7-column grid of the week starting at `first`, with one row per hour and a row
+// for all-day events on top. Days are named in `locale`, English if None
+pub fn format_week(entries: &[AgendaEntry], first: NaiveDate, locale: Option<Locale>) -> String {
+    let days: Vec<NaiveDate> = first.iter_days().take(7).collect();
+    let (all_day, timed): (Vec<&AgendaEntry>, Vec<&AgendaEntry>) = entries
+        .iter()
+        .sorted_by_key(|e| e.start)
+        .partition(|e| e.all_day);
+
+    let week_end = (first + Duration::days(7)).and_time(NaiveTime::MIN);
+    let in_week = |e: &&&AgendaEntry| overlaps(e, first.and_time(NaiveTime::MIN), week_end);
+    let first_hour = timed
+        .iter()
+        .filter(in_week)
+        .map(|e| e.start.max(first.and_time(NaiveTime::MIN)).time().hour())
+        .fold(DAY_START, u32::min);
+    let last_hour = timed
+        .iter()
+        .filter(in_week)
+        .map(|e| {
+            let end = (e.start + e.duration).min(week_end) - Duration::seconds(1);
+            if end.date() > e.start.date() {
+                23
+            } else {
+                end.time().hour()
+            }
+        })
+        .fold(DAY_END, u32::max);
+
+    let mut lines = vec![format!(
+        "{:<8}{}",
+        "",
+        days.iter()
+            .map(|d| cell(
+                &d.format_localized("%a %d", locale.unwrap_or(Locale::POSIX))
+                    .to_string()
+            ))
+            .join(" ")
+    )];
+
+    let all_day_cells = days
+        .iter()
+        .map(|day| {
+            let from = day.and_time(NaiveTime::MIN);
+            let text = all_day
+                .iter()
+                .filter(|e| overlaps(e, from, from + Duration::days(1)))
+                .map(|e| e.name.as_str())
+                .join(", ");
+            cell(&text)
+        })
+        .collect::<Vec<_>>();
+    if all_day_cells.iter().any(|c| !c.trim().is_empty()) {
+        lines.push(format!("{:<8}{}", "all day", all_day_cells.join(" ")));
+    }
+
+    for hour in first_hour..=last_hour {
+        let cells = days.iter().map(|day| {
+            let from = day.and_time(NaiveTime::from_hms_opt(hour, 0, 0).unwrap());
+            let to = from + Duration::hours(1);
+            cell(&cell_text(
+                timed.iter().copied().filter(|e| overlaps(e, from, to)),
+                from,
+            ))
+        });
+        lines.push(format!(
+            "{:02}:00   {}",
+            hour,
+            cells.collect::<Vec<_>>().join(" ")
+        ));
+    }
+
+    lines.iter().map(|line| line.trim_end()).join("\n")
+}
+
+// The seven days from `first` as a list, each day's entries under a header naming it,
+// for a terminal or a rofi popup. Days without entries are left out
+pub fn format_week_list(
+    entries: &[AgendaEntry],
+    first: NaiveDate,
+    opts: &DisplayOptions,
+) -> String {
+    first
+        .iter_days()
+        .take(7)
+        .filter_map(|day| {
+            let agenda = format_day(entries, day, opts);
+            if agenda.is_empty() {
+                return None;
+            }
+            let header = day.format_localized("%A %-d %B", opts.locale.unwrap_or(Locale::POSIX));
+            Some(format!(
+                "{}\n{}",
+                header,
+                agenda.lines().map(|line| format!("  {}", line)).join("\n")
+            ))
+        })
+        .join("\n\n")
+}